@@ -0,0 +1,95 @@
+#![feature(test)]
+
+extern crate mpd;
+extern crate test;
+
+use std::io::{self, Read, Write};
+use test::{Bencher, black_box};
+
+/// Stream replaying a canned response for every command sent
+struct Canned {
+    reply: Vec<u8>,
+    data: Vec<u8>,
+    pos: usize,
+}
+
+impl Canned {
+    fn client(reply: Vec<u8>) -> mpd::Client<Canned> {
+        mpd::Client::new(Canned {
+                reply: reply,
+                data: b"OK MPD 0.21.0\n".to_vec(),
+                pos: 0,
+            })
+            .unwrap()
+    }
+}
+
+impl Read for Canned {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = (&self.data[self.pos..]).read(buf)?;
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+impl Write for Canned {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.data != self.reply {
+            self.data = self.reply.clone();
+        }
+        self.pos = 0;
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Large library listing made of the playlist corpus repeated many times
+fn library(songs: usize) -> Vec<u8> {
+    let corpus = include_str!("corpus/playlistinfo.txt").trim_end_matches("OK\n");
+    let mut reply = corpus.repeat(songs / 3).into_bytes();
+    reply.extend_from_slice(b"OK\n");
+    reply
+}
+
+#[bench]
+fn status(b: &mut Bencher) {
+    let mut mpd = Canned::client(include_bytes!("corpus/status.txt").to_vec());
+    b.iter(|| { black_box(mpd.status()).unwrap(); });
+}
+
+#[bench]
+fn queue(b: &mut Bencher) {
+    let mut mpd = Canned::client(include_bytes!("corpus/playlistinfo.txt").to_vec());
+    b.iter(|| { black_box(mpd.queue()).unwrap(); });
+}
+
+#[bench]
+fn queue_large(b: &mut Bencher) {
+    let mut mpd = Canned::client(library(3000));
+    b.iter(|| { black_box(mpd.queue()).unwrap(); });
+}
+
+#[bench]
+fn for_each_song_large(b: &mut Bencher) {
+    let mut mpd = Canned::client(library(3000));
+    b.iter(|| {
+        let mut total = 0;
+        mpd.for_each_song(&mpd::Query::new(), |song| total += song.duration().map_or(0, |d| d.num_seconds()))
+            .unwrap();
+        black_box(total)
+    });
+}
+
+#[bench]
+fn format_queue_large(b: &mut Bencher) {
+    let mut mpd = Canned::client(library(3000));
+    let songs = mpd.queue().unwrap();
+    let template = mpd::song::format::Template::parse("[%artist:.20% - ]%title%|%file% [%time%]").unwrap();
+    let mut line = String::new();
+    b.iter(|| for song in &songs {
+        line.clear();
+        black_box(template.render_into(song, &mut line));
+    });
+}
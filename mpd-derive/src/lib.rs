@@ -0,0 +1,373 @@
+//! Derive macros for [rust-mpd](https://github.com/kstep/rust-mpd) response types
+//!
+//! `#[derive(FromResponse)]` implements `mpd::FromResponse` trait for a struct
+//! with named fields, so it can be built from MPD response pairs, e.g. with
+//! `RawResponse::decode()`. Values are parsed with `mpd::raw::FromValue` trait.
+//!
+//! Fields are configured with `#[mpd(...)]` attributes:
+//!
+//!   - `key = "name"`: MPD key for the field (field name by default),
+//!     several attributes with different keys can be given for the same field,
+//!   - `optional`: the field is not required, `Default` value is used if it's missing
+//!     (fields of `Option<T>` type are always optional),
+//!   - `repeated`: collect all values of the key into a `Vec<T>` field,
+//!   - `parse_with = "path"`: parse value with `fn(&str) -> mpd::error::Result<T>` function,
+//!   - `merge_with = "path"`: update field value with `fn(&mut T, &str) -> mpd::error::Result<()>`
+//!     function, field starts with `Default` value (useful to build one value from several keys),
+//!   - `rest`: catch-all field (like `BTreeMap<String, String>`), all unknown pairs are inserted into it,
+//!   - `skip`: the field is not read from response, `Default` value is used.
+//!
+//! A struct itself can be marked with `#[mpd(default)]`, in which case all missing
+//! fields are taken from the struct's `Default` implementation.
+//!
+//! ```rust,ignore
+//! #[derive(FromResponse)]
+//! struct Partition {
+//!     #[mpd(key = "partition")]
+//!     name: String,
+//!     #[mpd(key = "outputname", repeated)]
+//!     outputs: Vec<String>,
+//!     #[mpd(rest)]
+//!     other: BTreeMap<String, String>,
+//! }
+//! ```
+
+extern crate proc_macro;
+extern crate proc_macro2;
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{Attribute, Data, DeriveInput, Fields, GenericArgument, Ident, LitStr, Path, PathArguments, Type};
+
+/// Derive `mpd::FromResponse` implementation, see crate documentation for attributes
+#[proc_macro_derive(FromResponse, attributes(mpd))]
+pub fn derive_from_response(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+struct Binding {
+    key: String,
+    parse_with: Option<Path>,
+    merge_with: Option<Path>,
+}
+
+#[derive(PartialEq)]
+enum Mode {
+    Required,
+    Optional,
+    Option,
+    Repeated,
+    Merge,
+    Rest,
+    Skip,
+}
+
+struct Field {
+    ident: Ident,
+    temp: Ident,
+    ty: Type,
+    mode: Mode,
+    bindings: Vec<Binding>,
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match input.data {
+        Data::Struct(ref data) => {
+            match data.fields {
+                Fields::Named(ref fields) => &fields.named,
+                _ => return Err(syn::Error::new_spanned(input, "FromResponse can be derived for structs with named fields only")),
+            }
+        }
+        _ => return Err(syn::Error::new_spanned(input, "FromResponse can be derived for structs only")),
+    };
+
+    let struct_default = parse_struct_attrs(&input.attrs)?;
+    let fields = fields.iter().map(parse_field).collect::<syn::Result<Vec<_>>>()?;
+
+    let mut keys = Vec::new();
+    for field in &fields {
+        for binding in &field.bindings {
+            if keys.contains(&binding.key) {
+                return Err(syn::Error::new_spanned(&field.ident, format!("duplicate MPD key `{}`", binding.key)));
+            }
+            keys.push(binding.key.clone());
+        }
+    }
+
+    let inits = fields.iter().map(|f| {
+        let temp = &f.temp;
+        let ty = &f.ty;
+        match f.mode {
+            Mode::Required | Mode::Optional => quote! { let mut #temp: ::std::option::Option<#ty> = None; },
+            Mode::Option | Mode::Repeated | Mode::Merge | Mode::Rest => {
+                quote! { let mut #temp: #ty = ::std::default::Default::default(); }
+            }
+            Mode::Skip => quote! {},
+        }
+    });
+
+    let mut arms = Vec::new();
+    for field in &fields {
+        let temp = &field.temp;
+        for binding in &field.bindings {
+            let key = &binding.key;
+            let action = if let Some(ref merge) = binding.merge_with {
+                quote! { #merge(&mut #temp, &value)?; }
+            } else {
+                let value = match binding.parse_with {
+                    Some(ref parse) => quote! { #parse(&value)? },
+                    None => {
+                        let ty = match field.mode {
+                            Mode::Option | Mode::Repeated => inner_type(&field.ty).unwrap_or(&field.ty),
+                            _ => &field.ty,
+                        };
+                        quote! { <#ty as ::mpd::raw::FromValue>::from_value(&value)? }
+                    }
+                };
+                match field.mode {
+                    Mode::Repeated => quote! { #temp.push(#value); },
+                    _ => quote! { #temp = Some(#value); },
+                }
+            };
+            arms.push(quote! { #key => { #action } });
+        }
+    }
+
+    let rest = match fields.iter().find(|f| f.mode == Mode::Rest) {
+        Some(field) => {
+            let temp = &field.temp;
+            quote! { _ => { #temp.insert(key, value); } }
+        }
+        None => quote! { _ => (), },
+    };
+
+    let default = if struct_default {
+        quote! {
+            #[allow(unused_variables)]
+            let __default: Self = ::std::default::Default::default();
+        }
+    } else {
+        quote! {}
+    };
+
+    let values = fields.iter().map(|f| {
+        let ident = &f.ident;
+        let temp = &f.temp;
+        let missing = if struct_default {
+            quote! { __default.#ident }
+        } else {
+            quote! { ::std::default::Default::default() }
+        };
+        let value = match f.mode {
+            Mode::Required if !struct_default => {
+                let key = &f.bindings[0].key;
+                quote! {
+                    match #temp {
+                        Some(v) => v,
+                        None => return Err(::mpd::error::Error::Proto(::mpd::error::ProtoError::NoField(#key))),
+                    }
+                }
+            }
+            Mode::Required | Mode::Optional => quote! { match #temp { Some(v) => v, None => #missing } },
+            Mode::Skip => missing,
+            _ => quote! { #temp },
+        };
+        quote! { #ident: #value }
+    });
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::mpd::raw::FromResponse for #name #ty_generics #where_clause {
+            fn from_response<I>(iter: I) -> ::mpd::error::Result<Self>
+                where I: ::std::iter::Iterator<Item = ::mpd::error::Result<(::std::string::String, ::std::string::String)>>
+            {
+                #(#inits)*
+                for pair in iter {
+                    let (key, value) = pair?;
+                    match &*key {
+                        #(#arms)*
+                        #rest
+                    }
+                }
+                #default
+                Ok(#name {
+                    #(#values,)*
+                })
+            }
+        }
+    })
+}
+
+fn parse_struct_attrs(attrs: &[Attribute]) -> syn::Result<bool> {
+    let mut default = false;
+    for attr in attrs.iter().filter(|a| a.path().is_ident("mpd")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("default") {
+                default = true;
+                Ok(())
+            } else {
+                Err(meta.error("unknown mpd struct attribute"))
+            }
+        })?;
+    }
+    Ok(default)
+}
+
+fn parse_field(field: &syn::Field) -> syn::Result<Field> {
+    let ident = field.ident.clone().expect("named field");
+    let (mut optional, mut repeated, mut rest, mut skip) = (false, false, false, false);
+    let mut bindings = Vec::new();
+
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("mpd")) {
+        let mut binding = Binding {
+            key: String::new(),
+            parse_with: None,
+            merge_with: None,
+        };
+        let mut has_binding = false;
+
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("key") {
+                binding.key = meta.value()?.parse::<LitStr>()?.value();
+                has_binding = true;
+            } else if meta.path.is_ident("parse_with") {
+                binding.parse_with = Some(meta.value()?.parse::<LitStr>()?.parse()?);
+                has_binding = true;
+            } else if meta.path.is_ident("merge_with") {
+                binding.merge_with = Some(meta.value()?.parse::<LitStr>()?.parse()?);
+                has_binding = true;
+            } else if meta.path.is_ident("optional") {
+                optional = true;
+            } else if meta.path.is_ident("repeated") {
+                repeated = true;
+            } else if meta.path.is_ident("rest") {
+                rest = true;
+            } else if meta.path.is_ident("skip") {
+                skip = true;
+            } else {
+                return Err(meta.error("unknown mpd field attribute"));
+            }
+            Ok(())
+        })?;
+
+        if has_binding {
+            if binding.key.is_empty() {
+                binding.key = ident.to_string();
+            }
+            bindings.push(binding);
+        }
+    }
+
+    let merge = bindings.iter().any(|b| b.merge_with.is_some());
+    let mode = match (skip, rest, merge, repeated) {
+        (true, _, _, _) => Mode::Skip,
+        (_, true, _, _) => Mode::Rest,
+        (_, _, true, _) => Mode::Merge,
+        (_, _, _, true) => Mode::Repeated,
+        _ if inner_type(&field.ty).is_some() && is_option(&field.ty) => Mode::Option,
+        _ if optional => Mode::Optional,
+        _ => Mode::Required,
+    };
+
+    if mode == Mode::Merge && bindings.iter().any(|b| b.merge_with.is_none()) {
+        return Err(syn::Error::new_spanned(&ident, "all keys of a field with `merge_with` must have `merge_with` function"));
+    }
+    if mode == Mode::Skip || mode == Mode::Rest {
+        bindings.clear();
+    } else if bindings.is_empty() {
+        bindings.push(Binding {
+            key: ident.to_string(),
+            parse_with: None,
+            merge_with: None,
+        });
+    }
+
+    Ok(Field {
+        temp: format_ident!("__field_{}", ident, span = Span::call_site()),
+        ident,
+        ty: field.ty.clone(),
+        mode,
+        bindings,
+    })
+}
+
+fn is_option(ty: &Type) -> bool {
+    last_segment_ident(ty).is_some_and(|i| i == "Option")
+}
+
+fn last_segment_ident(ty: &Type) -> Option<&Ident> {
+    match *ty {
+        Type::Path(ref path) if path.qself.is_none() => path.path.segments.last().map(|s| &s.ident),
+        _ => None,
+    }
+}
+
+/// Get `T` from `Option<T>` or `Vec<T>`
+fn inner_type(ty: &Type) -> Option<&Type> {
+    match *ty {
+        Type::Path(ref path) if path.qself.is_none() => {
+            let segment = path.path.segments.last()?;
+            match segment.arguments {
+                PathArguments::AngleBracketed(ref args) if args.args.len() == 1 => {
+                    match args.args[0] {
+                        GenericArgument::Type(ref ty) => Some(ty),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn error(input: &str) -> String {
+        let input: DeriveInput = syn::parse_str(input).unwrap();
+        match expand(&input) {
+            Ok(_) => panic!("expansion should fail"),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn attributes() {
+        let input: DeriveInput = syn::parse_str(r#"
+            #[mpd(default)]
+            struct A {
+                #[mpd(key = "a", optional)] a: u32,
+                #[mpd(key = "b", repeated)] b: Vec<u32>,
+                #[mpd(parse_with = "parse")] c: u32,
+                #[mpd(key = "d1", merge_with = "merge")] #[mpd(key = "d2", merge_with = "merge")] d: u32,
+                #[mpd(skip)] e: u32,
+                #[mpd(rest)] f: BTreeMap<String, String>,
+            }
+        "#).unwrap();
+        assert!(expand(&input).is_ok());
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error("struct A { #[mpd(unknown)] a: u32 }"), "unknown mpd field attribute");
+        assert_eq!(error("#[mpd(unknown)] struct A { a: u32 }"), "unknown mpd struct attribute");
+        assert_eq!(error("struct A { #[mpd(key = 1)] a: u32 }"), "expected string literal");
+        assert_eq!(error("struct A { #[mpd(parse_with = \"1 + 1\")] a: u32 }"), "expected identifier");
+        assert_eq!(error("struct A { a: u32, #[mpd(key = \"a\")] b: u32 }"), "duplicate MPD key `a`");
+        assert_eq!(error("struct A { #[mpd(merge_with = \"m\")] #[mpd(key = \"b\")] a: u32 }"),
+                   "all keys of a field with `merge_with` must have `merge_with` function");
+        assert_eq!(error("struct A(u32);"), "FromResponse can be derived for structs with named fields only");
+        assert_eq!(error("enum A { B }"), "FromResponse can be derived for structs only");
+    }
+}
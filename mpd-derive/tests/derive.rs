@@ -0,0 +1,149 @@
+extern crate mpd;
+
+use mpd::FromResponse;
+use mpd::error::{Error, ParseError, ProtoError, Result};
+use std::collections::BTreeMap;
+
+fn decode<T: FromResponse>(pairs: &[(&str, &str)]) -> Result<T> {
+    T::from_response(pairs.iter().map(|&(key, value)| Ok((key.to_owned(), value.to_owned()))))
+}
+
+#[derive(Debug, PartialEq, FromResponse)]
+struct Output {
+    #[mpd(key = "outputid")]
+    id: u32,
+    name: String,
+    #[mpd(optional)]
+    volume: i8,
+    plugin: Option<String>,
+}
+
+#[test]
+fn keys() {
+    let output: Output = decode(&[("outputid", "1"), ("name", "alsa"), ("volume", "50"), ("plugin", "pipewire")]).unwrap();
+    assert_eq!(output, Output { id: 1, name: "alsa".to_owned(), volume: 50, plugin: Some("pipewire".to_owned()) });
+
+    let output: Output = decode(&[("name", "alsa"), ("outputid", "2"), ("unknown", "x")]).unwrap();
+    assert_eq!(output, Output { id: 2, name: "alsa".to_owned(), volume: 0, plugin: None });
+
+    match decode::<Output>(&[("name", "alsa")]) {
+        Err(Error::Proto(ProtoError::NoField("outputid"))) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    match decode::<Output>(&[("outputid", "x"), ("name", "alsa")]) {
+        Err(Error::Parse(ParseError::BadInteger(_))) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[derive(Debug, PartialEq, FromResponse)]
+struct Partition {
+    #[mpd(key = "partition")]
+    name: String,
+    #[mpd(key = "outputname", repeated)]
+    outputs: Vec<String>,
+    #[mpd(key = "outputid", repeated)]
+    ids: Vec<u32>,
+}
+
+#[test]
+fn repeated() {
+    let partition: Partition = decode(&[("partition", "default"), ("outputname", "a"), ("outputid", "1"), ("outputname", "b")]).unwrap();
+    assert_eq!(partition.outputs, vec!["a", "b"]);
+    assert_eq!(partition.ids, vec![1]);
+
+    let partition: Partition = decode(&[("partition", "default")]).unwrap();
+    assert!(partition.outputs.is_empty());
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Range {
+    min: u32,
+    max: u32,
+}
+
+fn parse_time(value: &str) -> Result<(u32, u32)> {
+    let mut parts = value.splitn(2, ':');
+    match (parts.next(), parts.next()) {
+        (Some(elapsed), Some(total)) => Ok((elapsed.parse()?, total.parse()?)),
+        _ => Err(Error::Parse(ParseError::BadPair)),
+    }
+}
+
+fn merge_min(range: &mut Range, value: &str) -> Result<()> {
+    range.min = value.parse()?;
+    Ok(())
+}
+
+fn merge_max(range: &mut Range, value: &str) -> Result<()> {
+    range.max = value.parse()?;
+    Ok(())
+}
+
+#[derive(Debug, PartialEq, FromResponse)]
+struct Custom {
+    #[mpd(parse_with = "parse_time")]
+    time: (u32, u32),
+    #[mpd(key = "min", merge_with = "merge_min")]
+    #[mpd(key = "max", merge_with = "merge_max")]
+    range: Range,
+}
+
+#[test]
+fn parse_and_merge() {
+    let custom: Custom = decode(&[("time", "10:200"), ("max", "5"), ("min", "2")]).unwrap();
+    assert_eq!(custom, Custom { time: (10, 200), range: Range { min: 2, max: 5 } });
+
+    let custom: Custom = decode(&[("time", "10:200"), ("min", "2")]).unwrap();
+    assert_eq!(custom.range, Range { min: 2, max: 0 });
+
+    assert!(decode::<Custom>(&[("time", "10")]).is_err());
+    assert!(decode::<Custom>(&[("time", "10:200"), ("min", "x")]).is_err());
+}
+
+#[derive(Debug, PartialEq, FromResponse)]
+struct Rest {
+    file: String,
+    #[mpd(skip)]
+    cached: u32,
+    #[mpd(rest)]
+    other: BTreeMap<String, String>,
+}
+
+#[test]
+fn rest_and_skip() {
+    let rest: Rest = decode(&[("file", "a.flac"), ("Title", "A"), ("cached", "5")]).unwrap();
+    assert_eq!(rest.file, "a.flac");
+    assert_eq!(rest.cached, 0);
+    assert_eq!(rest.other.len(), 2);
+    assert_eq!(rest.other["Title"], "A");
+    assert_eq!(rest.other["cached"], "5");
+}
+
+#[derive(Debug, PartialEq, FromResponse)]
+#[mpd(default)]
+struct Volume {
+    state: String,
+    volume: i32,
+    #[mpd(skip)]
+    muted: bool,
+}
+
+impl Default for Volume {
+    fn default() -> Volume {
+        Volume {
+            state: "stop".to_owned(),
+            volume: -1,
+            muted: true,
+        }
+    }
+}
+
+#[test]
+fn struct_default() {
+    let volume: Volume = decode(&[("volume", "30")]).unwrap();
+    assert_eq!(volume, Volume { state: "stop".to_owned(), volume: 30, muted: true });
+
+    let volume: Volume = decode(&[]).unwrap();
+    assert_eq!(volume, Volume::default());
+}
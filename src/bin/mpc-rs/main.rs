@@ -0,0 +1,620 @@
+//! mpc-compatible command line MPD client
+//!
+//! Connects to `MPD_HOST` (which can be `password@host` or a socket path)
+//! and `MPD_PORT`, or `--host` and `--port` options. Run with `--help` to see
+//! supported commands.
+
+extern crate mpd;
+extern crate time;
+
+use mpd::error::{Error, ParseError};
+use mpd::idle::Idle;
+use mpd::song::format::{Template, format_duration};
+use mpd::{Client, Query, Song, State, Status, Subsystem, Term};
+use std::env;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::process;
+
+const USAGE: &'static str = "Usage: mpc-rs [options] <command> [<arguments>]
+
+Options:
+  --host=<host>     connect to server on <host> (or [password@]host, or socket path)
+  --port=<port>     connect to server port <port>
+  -f, --format=<f>  print songs with format <f> (like `[%artist:.20% - ]%title%')
+  -q, --quiet       don't print status after commands
+  --help            show this help
+
+Commands:
+  status                                show current song and status (default)
+  current                               show current song
+  play [<position>]                     start playing (at <position>)
+  pause | toggle | stop | next | prev   control playback
+  seek [+-][<HH:MM:SS>] | [+-]<0-100>%  seek in the current song
+  add <uri>...                          append songs to the queue
+  insert <uri>...                       insert songs after the current song
+  del <position>...                     remove songs from the queue
+  crop                                  remove all songs except the current one
+  clear | shuffle                       clear or shuffle the queue
+  move <from> <to>                      move song in the queue
+  playlist [<playlist>]                 print the queue (or a stored playlist)
+  lsplaylists                           list stored playlists
+  load | save | rm <playlist>           load, save or delete stored playlist
+  addplaylist <playlist> <uri>...       add songs to a stored playlist
+  delplaylist <playlist> <position>     remove song from a stored playlist
+  search | find | findadd <type> <query>...   search the database
+  list <type> [<type> <query>]...       list tag values
+  outputs                               list outputs
+  enable | disable | toggleoutput <output>...  change outputs (by number or name)
+  volume [+-]<num>                      set or change volume
+  repeat | random | single | consume [on|off]  set or toggle modes
+  crossfade [<seconds>]                 set or show crossfade
+  update | rescan [<path>]              update the database
+  sticker <uri> get|set|delete|list|find [<name>] [<value>]  song stickers
+  idle [<event>...]                     wait for events
+  idleloop [<event>...]                 print events as they happen
+  stats | version                       show server statistics or version
+";
+
+// Connection {{{
+enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Stream::Tcp(ref mut s) => s.read(buf),
+            #[cfg(unix)]
+            Stream::Unix(ref mut s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            Stream::Tcp(ref mut s) => s.write(buf),
+            #[cfg(unix)]
+            Stream::Unix(ref mut s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            Stream::Tcp(ref mut s) => s.flush(),
+            #[cfg(unix)]
+            Stream::Unix(ref mut s) => s.flush(),
+        }
+    }
+}
+
+fn connect(host: Option<String>, port: Option<String>) -> Result<Client<Stream>, Failure> {
+    let host = host.or_else(|| env::var("MPD_HOST").ok()).unwrap_or_else(|| "localhost".to_owned());
+    let port = port.or_else(|| env::var("MPD_PORT").ok()).unwrap_or_else(|| "6600".to_owned());
+    let port: u16 = port.parse().map_err(|_| Failure(format!("invalid port `{}'", port)))?;
+
+    // `@` at the start is an abstract socket name, not a password separator
+    let (password, host) = match host.rfind('@') {
+        Some(pos) if pos > 0 => (Some(host[..pos].to_owned()), host[pos + 1..].to_owned()),
+        _ => (None, host),
+    };
+
+    let stream = if host.starts_with('/') {
+        unix_stream(&host)?
+    } else {
+        Stream::Tcp(TcpStream::connect((&*host, port))?)
+    };
+
+    let mut client = Client::new(stream)?;
+    if let Some(password) = password {
+        client.login(&password)?;
+    }
+    Ok(client)
+}
+
+#[cfg(unix)]
+fn unix_stream(path: &str) -> Result<Stream, Failure> {
+    Ok(Stream::Unix(UnixStream::connect(path)?))
+}
+
+#[cfg(not(unix))]
+fn unix_stream(path: &str) -> Result<Stream, Failure> {
+    Err(Failure(format!("unix sockets are not supported: {}", path)))
+}
+// }}}
+
+// Errors {{{
+struct Failure(String);
+
+impl From<Error> for Failure {
+    fn from(e: Error) -> Failure {
+        Failure(e.to_string())
+    }
+}
+
+impl From<ParseError> for Failure {
+    fn from(e: ParseError) -> Failure {
+        match e {
+            ParseError::BadValue(msg) => Failure(msg),
+            e => Failure(e.to_string()),
+        }
+    }
+}
+
+impl From<io::Error> for Failure {
+    fn from(e: io::Error) -> Failure {
+        Failure(e.to_string())
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+fn usage_error<T>(command: &str) -> Result<T, Failure> {
+    Err(Failure(format!("invalid arguments for `{}', see --help", command)))
+}
+// }}}
+
+struct Options {
+    host: Option<String>,
+    port: Option<String>,
+    format: Template,
+    quiet: bool,
+}
+
+fn main() {
+    if let Err(e) = run() {
+        let _ = writeln!(io::stderr(), "mpc-rs: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Failure> {
+    let mut options = Options {
+        host: None,
+        port: None,
+        format: Template::default(),
+        quiet: false,
+    };
+
+    let mut args = Vec::new();
+    let mut iter = env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        let (name, value) = match arg.find('=') {
+            Some(pos) if arg.starts_with("--") => (arg[..pos].to_owned(), Some(arg[pos + 1..].to_owned())),
+            _ => (arg.clone(), None),
+        };
+        match &*name {
+            "--help" => {
+                print!("{}", USAGE);
+                return Ok(());
+            }
+            "-q" | "--quiet" => options.quiet = true,
+            "--host" | "--port" | "-f" | "--format" => {
+                let value = match value.or_else(|| iter.next()) {
+                    Some(value) => value,
+                    None => return Err(Failure(format!("missing value for `{}'", name))),
+                };
+                match &*name {
+                    "--host" => options.host = Some(value),
+                    "--port" => options.port = Some(value),
+                    _ => options.format = Template::parse(&value)?,
+                }
+            }
+            "--" => {
+                args.extend(iter.by_ref());
+            }
+            _ if name.starts_with('-') && name.len() > 1 && args.is_empty() => {
+                return Err(Failure(format!("unknown option `{}', see --help", name)))
+            }
+            _ => args.push(arg),
+        }
+    }
+
+    let mut client = connect(options.host.take(), options.port.take())?;
+    let (command, args) = match args.split_first() {
+        Some((command, args)) => (&**command, args),
+        None => ("status", &[][..]),
+    };
+
+    let show_status = execute(&mut client, &options, command, args)?;
+    if show_status && !options.quiet {
+        print_status(&mut client, &options)?;
+    }
+    Ok(())
+}
+
+/// Run a command, returns `true` if status should be printed after it
+fn execute(client: &mut Client<Stream>, options: &Options, command: &str, args: &[String]) -> Result<bool, Failure> {
+    match (command, args.len()) {
+        ("status", 0) => return Ok(true),
+        ("current", 0) => {
+            if let Some(song) = client.currentsong()? {
+                println!("{}", options.format.render(&song));
+            }
+            return Ok(false);
+        }
+
+        // Playback {{{
+        ("play", 0) => client.play()?,
+        ("play", 1) => client.switch(position(&args[0])?)?,
+        ("pause", 0) => client.pause(true)?,
+        ("toggle", 0) => {
+            match client.status()?.state {
+                State::Play => client.pause(true)?,
+                State::Pause => client.pause(false)?,
+                State::Stop => client.play()?,
+            }
+        }
+        ("stop", 0) => client.stop()?,
+        ("next", 0) => client.next()?,
+        ("prev", 0) => client.prev()?,
+        ("seek", 1) => {
+            let status = client.status()?;
+            let target = seek_target(&status, &args[0])?;
+            client.rewind(target)?;
+        }
+        // }}}
+
+        // Queue {{{
+        ("add", n) if n > 0 => {
+            for uri in args {
+                client.push(&song(uri))?;
+            }
+            return Ok(false);
+        }
+        ("insert", n) if n > 0 => {
+            // inserting each song right after the current one would reverse them
+            for uri in args.iter().rev() {
+                client.insert(&song(uri), mpd::QueuePosition::AfterCurrent(0))?;
+            }
+            return Ok(false);
+        }
+        ("del", n) if n > 0 => {
+            let mut positions = args.iter().map(|a| position(a)).collect::<Result<Vec<_>, _>>()?;
+            // delete from the end, so positions don't shift, and each one once
+            positions.sort();
+            positions.dedup();
+            for pos in positions.into_iter().rev() {
+                client.delete(pos)?;
+            }
+        }
+        ("crop", 0) => {
+            let status = client.status()?;
+            let current = match status.song {
+                Some(place) if status.state != State::Stop => place.pos,
+                _ => return Err(Failure("no song is playing".to_owned())),
+            };
+            if current + 1 < status.queue_len {
+                client.delete(current + 1..status.queue_len)?;
+            }
+            if current > 0 {
+                client.delete(0..current)?;
+            }
+        }
+        ("clear", 0) => client.clear()?,
+        ("shuffle", 0) => client.shuffle(..)?,
+        ("move", 2) => client.shift(position(&args[0])?, position(&args[1])? as usize)?,
+        ("playlist", 0) => print_songs(&client.queue()?, options),
+        ("playlist", 1) => print_songs(&client.playlist(&*args[0])?, options),
+        // }}}
+
+        // Stored playlists {{{
+        ("lsplaylists", 0) => {
+            for playlist in client.playlists()? {
+                println!("{}", playlist.name);
+            }
+            return Ok(false);
+        }
+        ("load", n) if n > 0 => {
+            for name in args {
+                println!("loading: {}", name);
+                client.load(&*name, ..)?;
+            }
+            return Ok(false);
+        }
+        ("save", 1) => {
+            client.save(&*args[0])?;
+            return Ok(false);
+        }
+        ("rm", n) if n > 0 => {
+            for name in args {
+                client.pl_remove(&*name)?;
+            }
+            return Ok(false);
+        }
+        ("addplaylist", n) if n > 1 => {
+            for uri in &args[1..] {
+                client.pl_push(&*args[0], &song(uri))?;
+            }
+            return Ok(false);
+        }
+        ("delplaylist", 2) => {
+            client.pl_delete(&*args[0], position(&args[1])?)?;
+            return Ok(false);
+        }
+        // }}}
+
+        // Database {{{
+        ("search", n) | ("find", n) | ("findadd", n) if n > 0 && n % 2 == 0 => {
+            let mut query = Query::new();
+            let mut q = &mut query;
+            for pair in args.chunks(2) {
+                q = q.and(term(&pair[0]), &*pair[1]);
+            }
+            match command {
+                "search" => print_songs(&client.search(q, None)?, options),
+                "find" => print_songs(&client.find(q, None)?, options),
+                _ => client.findadd(q)?,
+            }
+            return Ok(false);
+        }
+        ("list", n) if n % 2 == 1 => {
+            let mut query = Query::new();
+            let mut q = &mut query;
+            for pair in args[1..].chunks(2) {
+                q = q.and(term(&pair[0]), &*pair[1]);
+            }
+            for value in client.list(&term(&args[0]), q)? {
+                println!("{}", value);
+            }
+            return Ok(false);
+        }
+        ("update", _) | ("rescan", _) => {
+            let paths = if args.is_empty() { vec![None] } else { args.iter().map(|path| Some(&**path)).collect() };
+            for path in paths {
+                let job = if command == "update" { client.update(path)? } else { client.rescan(path)? };
+                if !options.quiet {
+                    println!("Updating DB (#{}) ...", job.id);
+                }
+            }
+        }
+        // }}}
+
+        // Outputs {{{
+        ("outputs", 0) => {
+            for output in client.outputs()? {
+                println!("Output {} ({}) is {}",
+                         output.id + 1,
+                         output.name,
+                         if output.enabled { "enabled" } else { "disabled" });
+            }
+            return Ok(false);
+        }
+        ("enable", n) | ("disable", n) | ("toggleoutput", n) if n > 0 => {
+            let outputs = client.outputs()?;
+            for arg in args {
+                let output = match arg.parse::<u32>() {
+                    Ok(n) => outputs.iter().find(|o| o.id + 1 == n),
+                    Err(_) => outputs.iter().find(|o| o.name == *arg),
+                };
+                let id = match output {
+                    Some(output) => output.id,
+                    None => return Err(Failure(format!("unknown output `{}'", arg))),
+                };
+                match command {
+                    "enable" => client.out_enable(id)?,
+                    "disable" => client.out_disable(id)?,
+                    _ => client.out_toggle(id)?,
+                }
+            }
+            return execute(client, options, "outputs", &[]);
+        }
+        // }}}
+
+        // Options {{{
+        ("volume", 0) => {
+            let status = client.status()?;
+            println!("volume:{}", volume(&status));
+            return Ok(false);
+        }
+        ("volume", 1) => {
+            let arg = &*args[0];
+            let value: i32 = arg.trim_start_matches('+').parse().map_err(|_| Failure(format!("invalid volume `{}'", arg)))?;
+            let volume = if arg.starts_with('+') || arg.starts_with('-') {
+                client.status()?.volume as i32 + value
+            } else {
+                value
+            };
+            client.volume(volume.max(0).min(100) as i8)?;
+        }
+        ("repeat", n) | ("random", n) | ("single", n) | ("consume", n) if n <= 1 => {
+            let status = client.status()?;
+            let current = match command {
+                "repeat" => status.repeat,
+                "random" => status.random,
+                "single" => status.single,
+                _ => status.consume,
+            };
+            let value = match args.first().map(|s| &**s) {
+                None => !current,
+                Some("on") | Some("1") => true,
+                Some("off") | Some("0") => false,
+                Some(_) => return usage_error(command),
+            };
+            match command {
+                "repeat" => client.repeat(value)?,
+                "random" => client.random(value)?,
+                "single" => client.single(value)?,
+                _ => client.consume(value)?,
+            }
+        }
+        ("crossfade", 0) => {
+            let crossfade = client.status()?.crossfade.map_or(0, |d| d.num_seconds());
+            println!("crossfade: {}", crossfade);
+            return Ok(false);
+        }
+        ("crossfade", 1) => {
+            let secs: i64 = args[0].parse().map_err(|_| Failure(format!("invalid seconds `{}'", args[0])))?;
+            client.crossfade(secs)?;
+            return Ok(false);
+        }
+        // }}}
+
+        // Stickers {{{
+        ("sticker", n) if n >= 2 => {
+            let uri = &*args[0];
+            match (&*args[1], &args[2..]) {
+                ("get", [ref name]) => println!("{}={}", name, client.sticker("song", uri, name)?),
+                ("set", [ref name, ref value]) => client.set_sticker("song", uri, name, value)?,
+                ("delete", [ref name]) => client.delete_sticker("song", uri, name)?,
+                ("delete", []) => client.clear_stickers("song", uri)?,
+                ("list", []) => {
+                    for sticker in client.stickers("song", uri)? {
+                        println!("{}", sticker);
+                    }
+                }
+                ("find", [ref name]) => {
+                    for (file, value) in client.find_sticker("song", uri, name)? {
+                        println!("{}: {}={}", file, name, value);
+                    }
+                }
+                _ => return usage_error(command),
+            }
+            return Ok(false);
+        }
+        // }}}
+
+        // Events {{{
+        ("idle", _) | ("idleloop", _) => {
+            let subsystems = args.iter()
+                .map(|a| a.parse::<Subsystem>().map_err(|_| Failure(format!("unknown event `{}'", a))))
+                .collect::<Result<Vec<_>, _>>()?;
+            loop {
+                for subsystem in client.wait(&subsystems)? {
+                    println!("{}", subsystem);
+                }
+                io::stdout().flush()?;
+                if command == "idle" {
+                    return Ok(false);
+                }
+            }
+        }
+        // }}}
+
+        ("stats", 0) => {
+            let stats = client.stats()?;
+            println!("Artists: {:>6}", stats.artists);
+            println!("Albums:  {:>6}", stats.albums);
+            println!("Songs:   {:>6}", stats.songs);
+            println!();
+            println!("Play Time:    {}", format_duration(stats.playtime));
+            println!("Uptime:       {}", format_duration(stats.uptime));
+            println!("DB Updated:   {}", time::at(stats.db_update).rfc822());
+            println!("DB Play Time: {}", format_duration(stats.db_playtime));
+            return Ok(false);
+        }
+        ("version", 0) => {
+            let version = client.version;
+            println!("mpd version: {}", version);
+            return Ok(false);
+        }
+
+        _ => return usage_error(command),
+    }
+    Ok(true)
+}
+
+/// Parse 1-based position, like mpc does
+fn position(arg: &str) -> Result<u32, Failure> {
+    match arg.parse::<u32>() {
+        Ok(pos) if pos > 0 => Ok(pos - 1),
+        _ => Err(Failure(format!("invalid position `{}'", arg))),
+    }
+}
+
+/// Song reference for commands taking song paths
+fn song(uri: &str) -> Song {
+    Song { file: uri.to_owned(), ..Song::default() }
+}
+
+fn term(name: &str) -> Term<'static> {
+    match name {
+        "any" => Term::Any,
+        "file" => Term::File,
+        "base" => Term::Base,
+        "modified-since" => Term::LastMod,
+        tag => Term::Tag(tag.to_owned().into()),
+    }
+}
+
+/// Parse seek argument into an absolute position in seconds
+fn seek_target(status: &Status, arg: &str) -> Result<f64, Failure> {
+    let invalid = || Failure(format!("invalid seek position `{}'", arg));
+    let elapsed = status.elapsed.map_or(0.0, |d| d.num_milliseconds() as f64 / 1000.0);
+    let duration = status.duration.or_else(|| status.time.map(|t| t.1)).map_or(0.0, |d| d.num_milliseconds() as f64 / 1000.0);
+
+    let (sign, value) = match arg.chars().next() {
+        Some('+') => (1.0, &arg[1..]),
+        Some('-') => (-1.0, &arg[1..]),
+        _ => (0.0, arg),
+    };
+
+    let offset = if value.ends_with('%') {
+        let percent: f64 = value[..value.len() - 1].parse().map_err(|_| invalid())?;
+        duration * percent / 100.0
+    } else {
+        let mut secs = 0.0;
+        for part in value.split(':') {
+            secs = secs * 60.0 + part.parse::<f64>().map_err(|_| invalid())?;
+        }
+        secs
+    };
+
+    Ok(if sign == 0.0 { offset } else { (elapsed + sign * offset).max(0.0) })
+}
+
+fn volume(status: &Status) -> String {
+    if status.volume < 0 { "n/a".to_owned() } else { format!("{:>3}%", status.volume) }
+}
+
+fn print_songs(songs: &[Song], options: &Options) {
+    for song in songs {
+        println!("{}", options.format.render(song));
+    }
+}
+
+fn print_status(client: &mut Client<Stream>, options: &Options) -> Result<(), Failure> {
+    let status = client.status()?;
+
+    if status.state != State::Stop {
+        if let Some(song) = client.currentsong()? {
+            println!("{}", options.format.render(&song));
+        }
+        let (elapsed, total) = status.time.unwrap_or((time::Duration::zero(), time::Duration::zero()));
+        let percent = if total.num_seconds() > 0 { elapsed.num_seconds() * 100 / total.num_seconds() } else { 0 };
+        println!("[{}] #{}/{} {:>7}/{} ({}%)",
+                 if status.state == State::Play { "playing" } else { "paused" },
+                 status.song.map_or(0, |s| s.pos + 1),
+                 status.queue_len,
+                 format_duration(elapsed),
+                 format_duration(total),
+                 percent);
+    }
+
+    if let Some(job) = status.updating_db {
+        println!("Updating DB (#{}) ...", job);
+    }
+
+    let on_off = |v: bool| if v { "on " } else { "off" };
+    println!("volume:{}   repeat: {}   random: {}   single: {}   consume: {}",
+             volume(&status),
+             on_off(status.repeat),
+             on_off(status.random),
+             on_off(status.single),
+             on_off(status.consume));
+
+    if let Some(ref error) = status.error {
+        println!("ERROR: {}", error);
+    }
+    Ok(())
+}
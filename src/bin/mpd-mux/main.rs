@@ -0,0 +1,92 @@
+//! MPD connection multiplexer
+//!
+//! Accepts many MPD protocol clients (like desktop widgets) and runs their commands
+//! on a few shared MPD connections, so they don't hit MPD `max_connections` limit.
+//! Run it with
+//! `mpd-mux [--listen <addr>] [--mpd <[password@]host:port>] [--connections <n>]`
+//! (MPD address defaults to `MPD_HOST` and `MPD_PORT`).
+//!
+//! One more MPD connection waits in `idle` for all clients at once, and its events
+//! are queued for every client separately, so each one gets the events it would get
+//! from MPD itself. Connections are shared, so commands changing connection state
+//! (`binarylimit`, `tagtypes` with arguments, channel subscriptions) are rejected.
+//! If MPD password is given, the mux logs in itself, and clients which didn't log in
+//! with the same password can only run `ping`, `password` and `close`.
+
+extern crate mpd;
+
+use mpd::Client;
+use mpd::error::Result;
+use mpd::proxy::{Acl, Proxy, Rules, User};
+use std::env;
+use std::io::{self, Write};
+use std::net::TcpListener;
+use std::process;
+
+fn connect(addr: &str, password: Option<&str>) -> Result<Client> {
+    let mut client = Client::connect(addr)?;
+    if let Some(password) = password {
+        client.login(password)?;
+    }
+    Ok(client)
+}
+
+fn main() {
+    let mut listen = "127.0.0.1:6601".to_owned();
+    let mut mpd = format!("{}:{}",
+                          env::var("MPD_HOST").unwrap_or_else(|_| "localhost".to_owned()),
+                          env::var("MPD_PORT").unwrap_or_else(|_| "6600".to_owned()));
+    let mut connections = 2;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match (&*arg, args.next()) {
+            ("--listen", Some(value)) => listen = value,
+            ("--mpd", Some(value)) => mpd = value,
+            ("--connections", Some(value)) => {
+                connections = match value.parse() {
+                    Ok(n) if n > 0 => n,
+                    _ => fail(&format!("invalid number of connections: {}", value)),
+                }
+            }
+            _ => fail("usage: mpd-mux [--listen <addr>] [--mpd <[password@]host:port>] [--connections <n>]"),
+        }
+    }
+
+    let (password, addr) = match mpd.rfind('@') {
+        Some(pos) if pos > 0 => (Some(&mpd[..pos]), &mpd[pos + 1..]),
+        _ => (None, &*mpd),
+    };
+
+    let listener = match TcpListener::bind(&*listen) {
+        Ok(listener) => listener,
+        Err(e) => fail(&format!("can't listen on {}: {}", listen, e)),
+    };
+    let mut clients = (0..connections + 1)
+        .map(|_| connect(addr, password))
+        .collect::<Result<Vec<_>>>()
+        .unwrap_or_else(|e| fail(&format!("can't connect to {}: {}", addr, e)));
+
+    let idle = clients.pop().unwrap();
+    let acl = Acl {
+        guest: if password.is_some() { Rules::none() } else { Rules::default() },
+        users: password.into_iter()
+            .map(|password| {
+                User {
+                    name: "mpd".to_owned(),
+                    password: password.to_owned(),
+                    rules: Rules::default(),
+                }
+            })
+            .collect(),
+    };
+
+    if let Err(e) = Proxy::multiplex(clients, idle, acl).listen(listener) {
+        fail(&e.to_string());
+    }
+}
+
+fn fail(msg: &str) -> ! {
+    let _ = writeln!(io::stderr(), "mpd-mux: {}", msg);
+    process::exit(1);
+}
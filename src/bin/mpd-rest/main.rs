@@ -0,0 +1,707 @@
+//! REST/JSON gateway for MPD
+//!
+//! Exposes `Client` operations as HTTP endpoints for web frontends, which can't
+//! speak MPD protocol. Build it with `cargo build --features rest`, and run it
+//! with `mpd-rest [--listen <addr>] [--mpd <[password@]host:port>] [--connections <n>] [--subscribers <n>]`
+//! (MPD address defaults to `MPD_HOST` and `MPD_PORT`).
+//!
+//! Requests share a pool of `--connections` MPD connections (4 by default).
+//! Event streams share one more connection, waiting in `idle`, and at most
+//! `--subscribers` streams (32 by default) are served at once.
+//!
+//! Request bodies and responses are JSON. Endpoints:
+//!
+//! - `GET /status`, `GET /stats`, `GET /current`: player status, database
+//!   statistics and current song,
+//! - `POST /player/<play|pause|resume|stop|next|prev>`: control playback
+//!   (`play` takes optional `{"position": n}`), `POST /player/seek` takes `{"time": secs}`,
+//! - `PUT /options` with any of `volume`, `repeat`, `random`, `single`,
+//!   `consume` and `crossfade` keys,
+//! - `GET /queue`, `DELETE /queue` (clear), `POST /queue` with `{"uri": uri}`
+//!   (and optional `"position"`) or `{"filter": expression}` to add songs,
+//! - `GET`, `PUT` (with `position` and/or `priority`) and `DELETE /queue/<id>`,
+//! - `GET /find` and `GET /search` with `filter` (MPD filter expression,
+//!   like `(artist == 'Nirvana')`) and optional `window` (`start:end`) query parameters,
+//! - `GET /stickers?uri=<uri>[&name=<name>]`, `PUT` (with `{"value": value}`)
+//!   and `DELETE` on the same URL (`type` parameter defaults to `song`),
+//! - `GET /outputs`, `PUT /outputs/<id>` with `{"enabled": bool}`,
+//! - `GET /playlists`, `GET /playlists/<name>`, `PUT /playlists/<name>` (save the queue),
+//!   `POST /playlists/<name>` with `{"uri": uri}`, `POST /playlists/<name>/load`,
+//!   `DELETE /playlists/<name>`,
+//! - `GET /events[?subsystems=player,mixer]`: idle events as Server-Sent Events,
+//!   with current status attached for player, mixer and options events
+//!   (`503` if there are too many subscribers already).
+//!
+//! Errors are reported as `{"error": message}` with 4xx/5xx status codes.
+
+extern crate mpd;
+extern crate rustc_serialize;
+extern crate tiny_http;
+
+use mpd::error::{Error, ErrorCode};
+use mpd::idle::Idle;
+use mpd::pool::Pool;
+use mpd::{Client, Id, Song, Subsystem};
+use rustc_serialize::Encodable;
+use rustc_serialize::json::{self, Json};
+use std::collections::BTreeMap;
+use std::env;
+use std::io::{self, Write};
+use std::net::TcpStream;
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender, channel};
+use std::thread;
+use std::time::Duration;
+use tiny_http::{Header, Method, Request, Response};
+
+// Errors {{{
+enum ApiError {
+    Mpd(Error),
+    BadRequest(String),
+    NotFound,
+    Unavailable(String),
+    Internal(String),
+}
+
+impl From<Error> for ApiError {
+    fn from(e: Error) -> ApiError {
+        ApiError::Mpd(e)
+    }
+}
+
+impl ApiError {
+    fn status(&self) -> u16 {
+        match *self {
+            ApiError::Mpd(Error::Server(ref e)) => {
+                match e.code {
+                    ErrorCode::NoExist => 404,
+                    ErrorCode::Exist => 409,
+                    ErrorCode::Password | ErrorCode::Permission => 403,
+                    ErrorCode::Argument | ErrorCode::PlaylistMax | ErrorCode::PlaylistLoad => 400,
+                    ErrorCode::UpdateAlready | ErrorCode::PlayerSync => 409,
+                    _ => 500,
+                }
+            }
+            ApiError::Mpd(Error::Argument(_)) => 400,
+            ApiError::Mpd(Error::Unsupported { .. }) => 501,
+            ApiError::Mpd(_) => 502,
+            ApiError::BadRequest(_) => 400,
+            ApiError::NotFound => 404,
+            ApiError::Unavailable(_) => 503,
+            ApiError::Internal(_) => 500,
+        }
+    }
+
+    fn message(&self) -> String {
+        match *self {
+            ApiError::Mpd(Error::Server(ref e)) => e.detail.clone(),
+            ApiError::Mpd(ref e) => e.to_string(),
+            ApiError::BadRequest(ref msg) | ApiError::Unavailable(ref msg) | ApiError::Internal(ref msg) => msg.clone(),
+            ApiError::NotFound => "not found".to_owned(),
+        }
+    }
+
+    /// Connection state is unknown after these errors, so it must not be reused
+    fn is_fatal(&self) -> bool {
+        match *self {
+            ApiError::Mpd(ref e) => e.is_fatal(),
+            _ => false,
+        }
+    }
+}
+
+fn bad_request<T>(msg: &str) -> Result<T, ApiError> {
+    Err(ApiError::BadRequest(msg.to_owned()))
+}
+// }}}
+
+// Requests {{{
+/// Parsed query string parameters
+struct Params(Vec<(String, String)>);
+
+impl Params {
+    fn parse(query: &str) -> Params {
+        Params(query.split('&')
+            .filter(|p| !p.is_empty())
+            .map(|p| {
+                let mut kv = p.splitn(2, '=');
+                (decode(kv.next().unwrap(), true), decode(kv.next().unwrap_or(""), true))
+            })
+            .collect())
+    }
+
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.iter().find(|&&(ref k, _)| k == key).map(|&(_, ref v)| &**v)
+    }
+
+    fn require(&self, key: &str) -> Result<&str, ApiError> {
+        self.get(key).ok_or_else(|| ApiError::BadRequest(format!("missing `{}' parameter", key)))
+    }
+}
+
+/// Parsed JSON request body
+struct Body(Json);
+
+impl Body {
+    fn read(request: &mut Request) -> Result<Body, ApiError> {
+        let mut text = String::new();
+        request.as_reader()
+            .read_to_string(&mut text)
+            .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+        if text.trim().is_empty() {
+            return Ok(Body(Json::Null));
+        }
+        Json::from_str(&text).map(Body).map_err(|e| ApiError::BadRequest(format!("invalid JSON: {}", e)))
+    }
+
+    fn get(&self, key: &str) -> Option<&Json> {
+        self.0.find(key).and_then(|v| if v.is_null() { None } else { Some(v) })
+    }
+
+    fn string(&self, key: &str) -> Result<Option<&str>, ApiError> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(v) => v.as_string().map(Some).ok_or_else(|| invalid_field(key)),
+        }
+    }
+
+    fn number(&self, key: &str) -> Result<Option<f64>, ApiError> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(v) => v.as_f64().map(Some).ok_or_else(|| invalid_field(key)),
+        }
+    }
+
+    fn integer(&self, key: &str) -> Result<Option<u32>, ApiError> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(v) => v.as_u64().filter(|&n| n <= u32::max_value() as u64).map(|n| Some(n as u32)).ok_or_else(|| invalid_field(key)),
+        }
+    }
+
+    fn boolean(&self, key: &str) -> Result<Option<bool>, ApiError> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(v) => v.as_boolean().map(Some).ok_or_else(|| invalid_field(key)),
+        }
+    }
+}
+
+fn invalid_field(key: &str) -> ApiError {
+    ApiError::BadRequest(format!("invalid `{}' field", key))
+}
+
+/// Percent-decode URL component, `+` is decoded as space in query strings only
+fn decode(text: &str, query: bool) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = |b: u8| (b as char).to_digit(16);
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() && hex(bytes[i + 1]).is_some() && hex(bytes[i + 2]).is_some() => {
+                out.push((hex(bytes[i + 1]).unwrap() * 16 + hex(bytes[i + 2]).unwrap()) as u8);
+                i += 3;
+            }
+            b'+' if query => {
+                out.push(b' ');
+                i += 1;
+            }
+            b => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn parse_id(id: &str) -> Result<Id, ApiError> {
+    id.parse().map(Id).map_err(|_| ApiError::NotFound)
+}
+// }}}
+
+// Routes {{{
+type Reply = Result<String, ApiError>;
+
+fn encode<T: Encodable>(value: &T) -> Reply {
+    json::encode(value).map_err(|e| ApiError::Internal(e.to_string()))
+}
+
+fn ok() -> Reply {
+    Ok("{}".to_owned())
+}
+
+fn route(client: &mut Client<TcpStream>, method: &Method, path: &[String], params: &Params, body: &Body) -> Reply {
+    let path: Vec<&str> = path.iter().map(|s| &**s).collect();
+    match (method, &path[..]) {
+        (&Method::Get, ["status"]) => encode(&client.status()?),
+        (&Method::Get, ["stats"]) => encode(&client.stats()?),
+        (&Method::Get, ["current"]) => encode(&client.currentsong()?),
+
+        // Playback {{{
+        (&Method::Post, ["player", action]) => {
+            match *action {
+                "play" => {
+                    match body.integer("position")? {
+                        Some(pos) => client.switch(pos)?,
+                        None => client.play()?,
+                    }
+                }
+                "pause" => client.pause(true)?,
+                "resume" => client.pause(false)?,
+                "stop" => client.stop()?,
+                "next" => client.next()?,
+                "prev" => client.prev()?,
+                "seek" => {
+                    match body.number("time")? {
+                        Some(time) if time >= 0.0 => client.rewind(time)?,
+                        _ => return bad_request("missing or invalid `time' field"),
+                    }
+                }
+                _ => return Err(ApiError::NotFound),
+            }
+            encode(&client.status()?)
+        }
+        (&Method::Put, ["options"]) => {
+            if let Some(volume) = body.integer("volume")? {
+                if volume > 100 {
+                    return Err(invalid_field("volume"));
+                }
+                client.volume(volume as i8)?;
+            }
+            if let Some(repeat) = body.boolean("repeat")? {
+                client.repeat(repeat)?;
+            }
+            if let Some(random) = body.boolean("random")? {
+                client.random(random)?;
+            }
+            if let Some(single) = body.boolean("single")? {
+                client.single(single)?;
+            }
+            if let Some(consume) = body.boolean("consume")? {
+                client.consume(consume)?;
+            }
+            if let Some(crossfade) = body.integer("crossfade")? {
+                client.crossfade(crossfade as i64)?;
+            }
+            encode(&client.status()?)
+        }
+        // }}}
+
+        // Queue {{{
+        (&Method::Get, ["queue"]) => encode(&client.queue()?),
+        (&Method::Delete, ["queue"]) => {
+            client.clear()?;
+            ok()
+        }
+        (&Method::Post, ["queue"]) => {
+            if let Some(filter) = body.string("filter")? {
+                client.raw("findadd", filter)?;
+                return ok();
+            }
+            let song = Song { file: body.string("uri")?.ok_or_else(|| invalid_field("uri"))?.to_owned(), ..Song::default() };
+            let id = match body.integer("position")? {
+                Some(pos) => Id(client.insert(&song, pos as usize)? as u32),
+                None => client.push(&song)?,
+            };
+            Ok(format!("{{\"id\":{}}}", id))
+        }
+        (&Method::Get, ["queue", id]) => {
+            match client.songs(parse_id(id)?)?.pop() {
+                Some(song) => encode(&song),
+                None => Err(ApiError::NotFound),
+            }
+        }
+        (&Method::Put, ["queue", id]) => {
+            let id = parse_id(id)?;
+            if let Some(pos) = body.integer("position")? {
+                client.shift(id, pos as usize)?;
+            }
+            if let Some(prio) = body.integer("priority")? {
+                if prio > 255 {
+                    return Err(invalid_field("priority"));
+                }
+                client.priority(id, prio as u8)?;
+            }
+            ok()
+        }
+        (&Method::Delete, ["queue", id]) => {
+            client.delete(parse_id(id)?)?;
+            ok()
+        }
+        // }}}
+
+        // Database {{{
+        (&Method::Get, [command @ "find"]) |
+        (&Method::Get, [command @ "search"]) => {
+            let filter = params.require("filter")?;
+            let reply = match params.get("window") {
+                Some(window) => {
+                    let mut range = window.splitn(2, ':').map(|n| n.parse::<u32>());
+                    match (range.next(), range.next()) {
+                        (Some(Ok(start)), Some(Ok(end))) if start <= end => {
+                            client.raw(command, (filter, "window", format!("{}:{}", start, end)))?
+                        }
+                        _ => return bad_request("invalid `window' parameter"),
+                    }
+                }
+                None => client.raw(command, filter)?,
+            };
+            encode(&reply.decode_all::<Song>("file")?)
+        }
+        // }}}
+
+        // Stickers {{{
+        (&Method::Get, ["stickers"]) => {
+            let (typ, uri) = (params.get("type").unwrap_or("song"), params.require("uri")?);
+            match params.get("name") {
+                Some(name) => Ok(Json::String(client.sticker(typ, uri, name)?).to_string()),
+                None => {
+                    let stickers = client.raw("sticker list", (typ, uri))?
+                        .fields("sticker")
+                        .into_iter()
+                        .filter_map(|s| {
+                            let mut kv = s.splitn(2, '=');
+                            Some((kv.next()?.to_owned(), Json::String(kv.next()?.to_owned())))
+                        })
+                        .collect::<BTreeMap<_, _>>();
+                    Ok(Json::Object(stickers).to_string())
+                }
+            }
+        }
+        (&Method::Put, ["stickers"]) => {
+            let (typ, uri, name) = (params.get("type").unwrap_or("song"), params.require("uri")?, params.require("name")?);
+            let value = match body.get("value") {
+                Some(&Json::String(ref value)) => value.clone(),
+                Some(value) => value.to_string(),
+                None => return Err(invalid_field("value")),
+            };
+            client.set_sticker(typ, uri, name, &value)?;
+            ok()
+        }
+        (&Method::Delete, ["stickers"]) => {
+            let (typ, uri) = (params.get("type").unwrap_or("song"), params.require("uri")?);
+            match params.get("name") {
+                Some(name) => client.delete_sticker(typ, uri, name)?,
+                None => client.clear_stickers(typ, uri)?,
+            }
+            ok()
+        }
+        // }}}
+
+        // Outputs {{{
+        (&Method::Get, ["outputs"]) => encode(&client.outputs()?),
+        (&Method::Put, ["outputs", id]) => {
+            let id: u32 = id.parse().map_err(|_| ApiError::NotFound)?;
+            match body.boolean("enabled")? {
+                Some(true) => client.out_enable(id)?,
+                Some(false) => client.out_disable(id)?,
+                None => return Err(invalid_field("enabled")),
+            }
+            ok()
+        }
+        // }}}
+
+        // Playlists {{{
+        (&Method::Get, ["playlists"]) => {
+            let playlists = client.playlists()?
+                .into_iter()
+                .map(|pl| {
+                    let mut obj = BTreeMap::new();
+                    obj.insert("name".to_owned(), Json::String(pl.name));
+                    if let Some(last_mod) = pl.last_mod {
+                        obj.insert("last_mod".to_owned(), Json::String(last_mod.rfc3339().to_string()));
+                    }
+                    Json::Object(obj)
+                })
+                .collect();
+            Ok(Json::Array(playlists).to_string())
+        }
+        (&Method::Get, ["playlists", name]) => encode(&client.playlist(*name)?),
+        (&Method::Put, ["playlists", name]) => {
+            client.save(*name)?;
+            ok()
+        }
+        (&Method::Post, ["playlists", name]) => {
+            let song = Song { file: body.string("uri")?.ok_or_else(|| invalid_field("uri"))?.to_owned(), ..Song::default() };
+            client.pl_push(*name, &song)?;
+            ok()
+        }
+        (&Method::Post, ["playlists", name, "load"]) => {
+            client.load(*name, ..)?;
+            ok()
+        }
+        (&Method::Delete, ["playlists", name]) => {
+            client.pl_remove(*name)?;
+            ok()
+        }
+        // }}}
+
+        _ => Err(ApiError::NotFound),
+    }
+}
+// }}}
+
+// Events {{{
+/// Interval (in seconds) of keep-alive comments, which detect closed event streams
+const KEEPALIVE: u64 = 15;
+
+/// Message to event streams
+#[derive(Clone)]
+enum Message {
+    /// idle event with JSON data
+    Event(Subsystem, String),
+    /// idle connection failed, streams are closed
+    Error(String),
+}
+
+#[derive(Default)]
+struct Subscribers {
+    senders: Vec<Sender<Message>>,
+    /// number of open event streams
+    count: usize,
+    /// whether the idle connection is open
+    watching: bool,
+}
+
+/// Idle events, waited for on one shared connection and fanned out to event streams
+struct Events {
+    pool: Arc<Pool>,
+    limit: usize,
+    subscribers: Mutex<Subscribers>,
+}
+
+/// Event stream subscription, it's counted until dropped
+struct Subscription {
+    events: Arc<Events>,
+    receiver: Receiver<Message>,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.events.subscribers.lock().unwrap().count -= 1;
+    }
+}
+
+impl Events {
+    fn new(pool: Arc<Pool>, limit: usize) -> Events {
+        Events {
+            pool: pool,
+            limit: limit,
+            subscribers: Mutex::new(Subscribers::default()),
+        }
+    }
+
+    /// Subscribe to events, opening the idle connection if it's not open yet
+    fn subscribe(events: &Arc<Events>) -> Result<Subscription, ApiError> {
+        let mut subscribers = events.subscribers.lock().unwrap();
+        if subscribers.count >= events.limit {
+            return Err(ApiError::Unavailable("too many event subscribers".to_owned()));
+        }
+        if !subscribers.watching {
+            let client = events.pool.connect()?;
+            let watched = events.clone();
+            thread::spawn(move || watched.watch(client));
+            subscribers.watching = true;
+        }
+
+        let (sender, receiver) = channel();
+        subscribers.senders.push(sender);
+        subscribers.count += 1;
+        Ok(Subscription {
+            events: events.clone(),
+            receiver: receiver,
+        })
+    }
+
+    /// Wait for events, until the connection fails or there are no subscribers left
+    fn watch(&self, mut client: Client<TcpStream>) {
+        loop {
+            let changed = match client.wait(&[]) {
+                Ok(changed) => changed,
+                Err(e) => {
+                    let mut subscribers = self.subscribers.lock().unwrap();
+                    for sender in subscribers.senders.drain(..) {
+                        let _ = sender.send(Message::Error(e.to_string()));
+                    }
+                    subscribers.watching = false;
+                    return;
+                }
+            };
+
+            let status = if changed.iter().any(|s| *s == Subsystem::Player || *s == Subsystem::Mixer || *s == Subsystem::Options) {
+                client.status().ok().and_then(|s| json::encode(&s).ok())
+            } else {
+                None
+            };
+
+            let mut subscribers = self.subscribers.lock().unwrap();
+            for subsystem in changed {
+                let data = match (subsystem, &status) {
+                    (Subsystem::Player, &Some(ref status)) |
+                    (Subsystem::Mixer, &Some(ref status)) |
+                    (Subsystem::Options, &Some(ref status)) => format!("{{\"subsystem\":\"{}\",\"status\":{}}}", subsystem, status),
+                    _ => format!("{{\"subsystem\":\"{}\"}}", subsystem),
+                };
+                subscribers.senders.retain(|sender| sender.send(Message::Event(subsystem, data.clone())).is_ok());
+            }
+            if subscribers.count == 0 {
+                subscribers.senders.clear();
+                subscribers.watching = false;
+                return;
+            }
+        }
+    }
+}
+
+/// Subscribe to idle events and stream them as Server-Sent Events in a new thread
+///
+/// Subscriber limit is checked before the thread is spawned.
+fn events(events: &Arc<Events>, request: Request, params: &Params) {
+    let subsystems = params.get("subsystems")
+        .map(|s| s.split(',').filter(|s| !s.is_empty()).map(|s| s.parse::<Subsystem>().map_err(|_| s)).collect())
+        .unwrap_or_else(|| Ok(Vec::new()));
+    let subsystems: Vec<Subsystem> = match subsystems {
+        Ok(subsystems) => subsystems,
+        Err(name) => return respond(request, Err(ApiError::BadRequest(format!("unknown subsystem `{}'", name)))),
+    };
+    let subscription = match Events::subscribe(events) {
+        Ok(subscription) => subscription,
+        Err(e) => return respond(request, Err(e)),
+    };
+    thread::spawn(move || stream(request, subscription, subsystems));
+}
+
+/// Stream events from the subscription
+///
+/// Closed streams are noticed when the next event or keep-alive comment is written.
+fn stream(request: Request, subscription: Subscription, subsystems: Vec<Subsystem>) {
+    let mut writer = request.into_writer();
+    let result = writer.write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n")
+        .and_then(|_| writer.flush());
+    if result.is_err() {
+        return;
+    }
+
+    loop {
+        let result = match subscription.receiver.recv_timeout(Duration::from_secs(KEEPALIVE)) {
+            Ok(Message::Event(subsystem, ref data)) if subsystems.is_empty() || subsystems.contains(&subsystem) => {
+                write!(writer, "event: {}\ndata: {}\n\n", subsystem, data)
+            }
+            Ok(Message::Event(..)) => continue,
+            Ok(Message::Error(e)) => {
+                let _ = write!(writer, "event: error\ndata: {}\n\n", Json::String(e));
+                return;
+            }
+            Err(RecvTimeoutError::Timeout) => writer.write_all(b": keepalive\n\n"),
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+        if result.and_then(|_| writer.flush()).is_err() {
+            return;
+        }
+    }
+}
+// }}}
+
+fn respond(request: Request, reply: Reply) {
+    let (code, body) = match reply {
+        Ok(body) => (200, body),
+        Err(e) => (e.status(), format!("{{\"error\":{}}}", Json::String(e.message()))),
+    };
+    let header = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+    let _ = request.respond(Response::from_string(body).with_status_code(code).with_header(header));
+}
+
+fn handle(pool: &Pool, events: &Arc<Events>, mut request: Request) {
+    let (path, params) = {
+        let mut url = request.url().splitn(2, '?');
+        let path = url.next()
+            .unwrap_or("")
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(|s| decode(s, false))
+            .collect::<Vec<_>>();
+        (path, Params::parse(url.next().unwrap_or("")))
+    };
+
+    if *request.method() == Method::Get && path == ["events"] {
+        return self::events(events, request, &params);
+    }
+
+    let reply = Body::read(&mut request).and_then(|body| {
+        let mut client = pool.get()?;
+        let reply = route(&mut client, request.method(), &path, &params, &body);
+        match reply {
+            Err(ref e) if e.is_fatal() => client.discard(),
+            _ => (),
+        }
+        reply
+    });
+    respond(request, reply);
+}
+
+fn main() {
+    let mut listen = "127.0.0.1:8080".to_owned();
+    let mut mpd = format!("{}:{}",
+                          env::var("MPD_HOST").unwrap_or_else(|_| "localhost".to_owned()),
+                          env::var("MPD_PORT").unwrap_or_else(|_| "6600".to_owned()));
+    let mut connections = 4;
+    let mut subscribers = 32;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next();
+        match (&*arg, value) {
+            ("--listen", Some(value)) => listen = value,
+            ("--mpd", Some(value)) => mpd = value,
+            ("--connections", Some(value)) => {
+                connections = match value.parse() {
+                    Ok(n) if n > 0 => n,
+                    _ => fail("invalid number of connections"),
+                }
+            }
+            ("--subscribers", Some(value)) => {
+                subscribers = match value.parse() {
+                    Ok(n) => n,
+                    _ => fail("invalid number of subscribers"),
+                }
+            }
+            _ => fail("usage: mpd-rest [--listen <addr>] [--mpd <[password@]host:port>] [--connections <n>] [--subscribers <n>]"),
+        }
+    }
+
+    let pool = match mpd.rfind('@') {
+        Some(pos) => {
+            let mut pool = Pool::new(&mpd[pos + 1..], connections);
+            pool.set_password(&mpd[..pos]);
+            pool
+        }
+        None => Pool::new(&mpd, connections),
+    };
+
+    let http = match tiny_http::Server::http(&*listen) {
+        Ok(http) => Arc::new(http),
+        Err(e) => fail(&format!("can't listen on {}: {}", listen, e)),
+    };
+    let pool = Arc::new(pool);
+    let events = Arc::new(Events::new(pool.clone(), subscribers));
+
+    let workers = (0..connections)
+        .map(|_| {
+            let (http, pool, events) = (http.clone(), pool.clone(), events.clone());
+            thread::spawn(move || while let Ok(request) = http.recv() {
+                handle(&pool, &events, request);
+            })
+        })
+        .collect::<Vec<_>>();
+    for worker in workers {
+        let _ = worker.join();
+    }
+}
+
+fn fail(msg: &str) -> ! {
+    let _ = writeln!(io::stderr(), "mpd-rest: {}", msg);
+    process::exit(1);
+}
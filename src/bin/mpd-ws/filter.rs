@@ -0,0 +1,97 @@
+//! Validation of command lines sent by WebSocket clients
+
+use std::fmt;
+
+/// Command list delimiters, allowed whenever commands in the list are
+const COMMAND_LIST: &'static [&'static str] = &["command_list_begin", "command_list_ok_begin", "command_list_end"];
+
+/// Reason to reject a command line, reported back to the client as MPD `ACK`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rejected {
+    /// MPD error code
+    pub code: u16,
+    /// command name (may be empty)
+    pub command: String,
+    /// error message
+    pub message: &'static str,
+}
+
+impl Rejected {
+    /// Format `ACK` line for command at given position in a command list
+    pub fn ack(&self, pos: usize) -> String {
+        format!("ACK [{}@{}] {{{}}} {}\n", self.code, pos, self.command, self.message)
+    }
+}
+
+impl fmt::Display for Rejected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.command, self.message)
+    }
+}
+
+/// Command filter
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    allow: Option<Vec<String>>,
+}
+
+impl Filter {
+    /// Filter allowing all commands, or only listed ones
+    pub fn new(allow: Option<Vec<String>>) -> Filter {
+        Filter { allow: allow }
+    }
+
+    /// Check command line (without the trailing newline)
+    ///
+    /// Lines with control characters are always rejected, as they could smuggle
+    /// extra commands past the allowlist.
+    pub fn check(&self, line: &str) -> Result<(), Rejected> {
+        let command = line.split(' ').next().unwrap_or("");
+
+        if line.chars().any(char::is_control) {
+            return Err(Rejected {
+                code: 2,
+                command: command.chars().filter(|c| !c.is_control()).collect(),
+                message: "control characters are not allowed",
+            });
+        }
+
+        match self.allow {
+            Some(ref allow) if !COMMAND_LIST.contains(&command) && !allow.iter().any(|c| c == command) => {
+                Err(Rejected {
+                    code: 4,
+                    command: command.to_owned(),
+                    message: "command not allowed",
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn control_chars() {
+        let filter = Filter::new(None);
+        assert!(filter.check("play").is_ok());
+        assert!(filter.check("add \"a b.mp3\"").is_ok());
+        assert_eq!(filter.check("add a\rclear").unwrap_err().ack(0),
+                   "ACK [2@0] {add} control characters are not allowed\n");
+        assert!(filter.check("find \"x\u{0}\"").is_err());
+        assert!(filter.check("status\t").is_err());
+    }
+
+    #[test]
+    fn allowlist() {
+        let filter = Filter::new(Some(vec!["status".to_owned(), "play".to_owned()]));
+        assert!(filter.check("status").is_ok());
+        assert!(filter.check("play 3").is_ok());
+        assert!(filter.check("command_list_ok_begin").is_ok());
+        assert_eq!(filter.check("clear").unwrap_err().ack(2), "ACK [4@2] {clear} command not allowed\n");
+        assert!(filter.check("playlistclear x").is_err());
+        assert!(filter.check("").is_err());
+    }
+}
@@ -0,0 +1,433 @@
+//! WebSocket bridge to MPD
+//!
+//! Relays MPD protocol between WebSocket clients (like browser-based MPD clients)
+//! and MPD over TCP or a Unix socket. Build it with `cargo build --features websocket`,
+//! and run it with
+//! `mpd-ws [--listen <addr>] [--mpd <[password@]host:port|[password@]socket path>] [--allow <command,...>]`
+//! (MPD address defaults to `MPD_HOST` and `MPD_PORT`).
+//!
+//! Each WebSocket connection gets its own MPD connection. Client messages are split
+//! into command lines. Lines with control characters are rejected, and with `--allow`
+//! commands not in the list are rejected too. Rejected commands (or whole command lists
+//! with a rejected command) are not sent to MPD, the client gets an `ACK` in place of
+//! their reply, so it stays in sync with the protocol.
+//!
+//! MPD replies are sent as one text message per reply (binary data, like album art,
+//! is sent in binary messages). If the MPD address has a password, the bridge logs in
+//! itself, so clients (like a LAN kiosk) don't need to know it.
+
+extern crate tungstenite;
+
+mod filter;
+
+use filter::Filter;
+use std::collections::VecDeque;
+use std::env;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::process;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::Duration;
+use tungstenite::{Message, WebSocket};
+use tungstenite::error::{Error as WsError, ProtocolError};
+
+/// How often to check for MPD replies while waiting for client messages
+const POLL_INTERVAL: u64 = 20;
+
+/// Max size of text message with a part of a long reply
+const MAX_MESSAGE: usize = 64 * 1024;
+
+// Connection {{{
+enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Stream {
+    fn try_clone(&self) -> io::Result<Stream> {
+        match *self {
+            Stream::Tcp(ref s) => s.try_clone().map(Stream::Tcp),
+            #[cfg(unix)]
+            Stream::Unix(ref s) => s.try_clone().map(Stream::Unix),
+        }
+    }
+
+    fn shutdown(&self) {
+        let _ = match *self {
+            Stream::Tcp(ref s) => s.shutdown(Shutdown::Both),
+            #[cfg(unix)]
+            Stream::Unix(ref s) => s.shutdown(Shutdown::Both),
+        };
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Stream::Tcp(ref mut s) => s.read(buf),
+            #[cfg(unix)]
+            Stream::Unix(ref mut s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            Stream::Tcp(ref mut s) => s.write(buf),
+            #[cfg(unix)]
+            Stream::Unix(ref mut s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            Stream::Tcp(ref mut s) => s.flush(),
+            #[cfg(unix)]
+            Stream::Unix(ref mut s) => s.flush(),
+        }
+    }
+}
+
+/// MPD server address and password
+struct Target {
+    addr: String,
+    password: Option<String>,
+}
+
+impl Target {
+    fn parse(addr: &str) -> Target {
+        match addr.rfind('@') {
+            Some(pos) if pos > 0 => {
+                Target {
+                    addr: addr[pos + 1..].to_owned(),
+                    password: Some(addr[..pos].to_owned()),
+                }
+            }
+            _ => {
+                Target {
+                    addr: addr.to_owned(),
+                    password: None,
+                }
+            }
+        }
+    }
+
+    /// Connect and log in, returns the stream, its reader and the banner line
+    fn connect(&self) -> io::Result<(Stream, BufReader<Stream>, String)> {
+        let mut stream = if self.addr.starts_with('/') {
+            unix_stream(&self.addr)?
+        } else {
+            Stream::Tcp(TcpStream::connect(&*self.addr)?)
+        };
+        let mut reader = BufReader::new(stream.try_clone()?);
+
+        let mut banner = String::new();
+        reader.read_line(&mut banner)?;
+        if !banner.starts_with("OK MPD ") {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid MPD banner"));
+        }
+
+        if let Some(ref password) = self.password {
+            let password = password.replace('\\', "\\\\").replace('"', "\\\"");
+            write!(stream, "password \"{}\"\n", password)?;
+            let mut reply = String::new();
+            reader.read_line(&mut reply)?;
+            if reply != "OK\n" {
+                return Err(io::Error::new(io::ErrorKind::PermissionDenied, reply.trim_end().to_owned()));
+            }
+        }
+
+        Ok((stream, reader, banner))
+    }
+}
+
+#[cfg(unix)]
+fn unix_stream(path: &str) -> io::Result<Stream> {
+    UnixStream::connect(path).map(Stream::Unix)
+}
+
+#[cfg(not(unix))]
+fn unix_stream(_: &str) -> io::Result<Stream> {
+    Err(io::Error::new(io::ErrorKind::Other, "unix sockets are not supported"))
+}
+// }}}
+
+// Replies {{{
+/// Parts of MPD replies, sent from the reader thread
+enum Chunk {
+    Text(String),
+    Binary(Vec<u8>),
+    /// the reply is complete
+    Done,
+}
+
+/// Read MPD replies and split them into chunks, until the connection is closed
+fn read_replies(mut reader: BufReader<Stream>, chunks: Sender<Chunk>) -> io::Result<()> {
+    let mut text = String::new();
+    let mut line = Vec::new();
+    let closed = || io::Error::new(io::ErrorKind::BrokenPipe, "client is gone");
+
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(());
+        }
+        text.push_str(&String::from_utf8_lossy(&line));
+
+        if line.starts_with(b"binary: ") {
+            let len = String::from_utf8_lossy(&line[8..]).trim().parse::<usize>().unwrap_or(0);
+            // binary data is followed by a newline
+            let mut data = vec![0; len + 1];
+            reader.read_exact(&mut data)?;
+            chunks.send(Chunk::Text(text.split_off(0))).map_err(|_| closed())?;
+            chunks.send(Chunk::Binary(data)).map_err(|_| closed())?;
+        } else if line == b"OK\n" || line.starts_with(b"ACK ") {
+            chunks.send(Chunk::Text(text.split_off(0))).map_err(|_| closed())?;
+            chunks.send(Chunk::Done).map_err(|_| closed())?;
+        } else if text.len() >= MAX_MESSAGE {
+            chunks.send(Chunk::Text(text.split_off(0))).map_err(|_| closed())?;
+        }
+    }
+}
+// }}}
+
+// Session {{{
+/// Reply expected by the client, in order of commands
+enum Pending {
+    /// reply from MPD
+    Mpd,
+    /// `ACK` for a rejected command
+    Rejected(String),
+}
+
+enum List {
+    /// not in a command list
+    Off,
+    /// collecting command list lines, sent to MPD all at once
+    Collecting(Vec<String>),
+    /// a command in the list was rejected, skip the rest of it
+    Rejected,
+}
+
+struct Session {
+    peer: String,
+    ws: WebSocket<TcpStream>,
+    mpd: Stream,
+    filter: Arc<Filter>,
+    pending: VecDeque<Pending>,
+    list: List,
+}
+
+impl Session {
+    fn run(&mut self, chunks: Receiver<Chunk>) -> Result<(), WsError> {
+        loop {
+            match self.ws.read() {
+                Ok(Message::Text(text)) => self.handle(&text)?,
+                Ok(Message::Binary(data)) => {
+                    match String::from_utf8(data) {
+                        Ok(text) => self.handle(&text)?,
+                        Err(_) => return Ok(()),
+                    }
+                }
+                Ok(Message::Close(_)) => return Ok(()),
+                Ok(_) => (),
+                Err(WsError::Io(ref e)) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => (),
+                Err(WsError::ConnectionClosed) |
+                Err(WsError::Protocol(ProtocolError::ResetWithoutClosingHandshake)) => return Ok(()),
+                Err(e) => return Err(e),
+            }
+
+            loop {
+                match chunks.try_recv() {
+                    Ok(Chunk::Text(text)) => self.ws.send(Message::Text(text))?,
+                    Ok(Chunk::Binary(data)) => self.ws.send(Message::Binary(data))?,
+                    Ok(Chunk::Done) => {
+                        self.pending.pop_front();
+                        self.flush_rejected()?;
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        let _ = self.ws.close(None);
+                        return self.ws.flush();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Handle command lines from the client
+    fn handle(&mut self, text: &str) -> Result<(), WsError> {
+        let text = if text.ends_with('\n') { &text[..text.len() - 1] } else { text };
+        for line in text.split('\n') {
+            self.handle_line(line)?;
+        }
+        Ok(())
+    }
+
+    fn handle_line(&mut self, line: &str) -> Result<(), WsError> {
+        let is_end = line == "command_list_end";
+
+        match self.list {
+            List::Rejected => {
+                if is_end {
+                    self.list = List::Off;
+                }
+                return Ok(());
+            }
+            List::Collecting(_) => (),
+            List::Off => {
+                if let Err(rejected) = self.filter.check(line) {
+                    self.log(&rejected);
+                    return self.reject(rejected.ack(0));
+                }
+                if line == "command_list_begin" || line == "command_list_ok_begin" {
+                    self.list = List::Collecting(vec![line.to_owned()]);
+                    return Ok(());
+                }
+
+                self.mpd.write_all(line.as_bytes()).and_then(|_| self.mpd.write_all(b"\n"))?;
+                // `noidle` completes the pending `idle`, and `close` has no reply
+                if line != "noidle" && line != "close" {
+                    self.pending.push_back(Pending::Mpd);
+                }
+                return Ok(());
+            }
+        }
+
+        let check = self.filter.check(line);
+        let mut lines = match ::std::mem::replace(&mut self.list, List::Off) {
+            List::Collecting(lines) => lines,
+            _ => unreachable!(),
+        };
+
+        match check {
+            Err(rejected) => {
+                self.log(&rejected);
+                self.list = if is_end { List::Off } else { List::Rejected };
+                self.reject(rejected.ack(lines.len() - 1))
+            }
+            Ok(()) if is_end => {
+                lines.push(line.to_owned());
+                let mut text = lines.join("\n");
+                text.push('\n');
+                self.mpd.write_all(text.as_bytes())?;
+                self.pending.push_back(Pending::Mpd);
+                Ok(())
+            }
+            Ok(()) => {
+                lines.push(line.to_owned());
+                self.list = List::Collecting(lines);
+                Ok(())
+            }
+        }
+    }
+
+    /// Send `ACK` right away, or after replies to the previous commands
+    fn reject(&mut self, ack: String) -> Result<(), WsError> {
+        self.pending.push_back(Pending::Rejected(ack));
+        self.flush_rejected()
+    }
+
+    fn flush_rejected(&mut self) -> Result<(), WsError> {
+        while let Some(&Pending::Rejected(_)) = self.pending.front() {
+            if let Some(Pending::Rejected(ack)) = self.pending.pop_front() {
+                self.ws.send(Message::Text(ack))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn log(&self, rejected: &filter::Rejected) {
+        let _ = writeln!(io::stderr(), "mpd-ws: {}: rejected {}", self.peer, rejected);
+    }
+}
+
+fn serve(stream: TcpStream, target: &Target, filter: Arc<Filter>) -> Result<(), WsError> {
+    let peer = stream.peer_addr().map(|a| a.to_string()).unwrap_or_default();
+    let mut ws = tungstenite::accept(stream).map_err(|e| {
+        match e {
+            tungstenite::HandshakeError::Failure(e) => e,
+            tungstenite::HandshakeError::Interrupted(_) => WsError::ConnectionClosed,
+        }
+    })?;
+
+    let (mpd, reader, banner) = match target.connect() {
+        Ok(conn) => conn,
+        Err(e) => {
+            let _ = ws.close(None);
+            let _ = ws.flush();
+            return Err(e.into());
+        }
+    };
+    ws.send(Message::Text(banner))?;
+    ws.get_ref().set_read_timeout(Some(Duration::from_millis(POLL_INTERVAL)))?;
+
+    let (sender, chunks) = mpsc::channel();
+    thread::spawn(move || read_replies(reader, sender));
+
+    let mut session = Session {
+        peer: peer,
+        ws: ws,
+        mpd: mpd,
+        filter: filter,
+        pending: VecDeque::new(),
+        list: List::Off,
+    };
+    let result = session.run(chunks);
+    session.mpd.shutdown();
+    result
+}
+// }}}
+
+fn main() {
+    let mut listen = "127.0.0.1:8600".to_owned();
+    let mut mpd = env::var("MPD_HOST").unwrap_or_else(|_| "localhost".to_owned());
+    if !mpd.contains('/') {
+        mpd = format!("{}:{}", mpd, env::var("MPD_PORT").unwrap_or_else(|_| "6600".to_owned()));
+    }
+    let mut allow: Option<Vec<String>> = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match (&*arg, args.next()) {
+            ("--listen", Some(value)) => listen = value,
+            ("--mpd", Some(value)) => mpd = value,
+            ("--allow", Some(value)) => {
+                allow.get_or_insert_with(Vec::new)
+                    .extend(value.split(',').map(|c| c.trim().to_owned()).filter(|c| !c.is_empty()))
+            }
+            _ => {
+                fail("usage: mpd-ws [--listen <addr>] [--mpd <[password@]host:port|socket>] [--allow <command,...>]")
+            }
+        }
+    }
+
+    let listener = match TcpListener::bind(&*listen) {
+        Ok(listener) => listener,
+        Err(e) => fail(&format!("can't listen on {}: {}", listen, e)),
+    };
+    let target = Arc::new(Target::parse(&mpd));
+    let filter = Arc::new(Filter::new(allow));
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        let (target, filter) = (target.clone(), filter.clone());
+        thread::spawn(move || if let Err(e) = serve(stream, &target, filter) {
+            let _ = writeln!(io::stderr(), "mpd-ws: {}", e);
+        });
+    }
+}
+
+fn fail(msg: &str) -> ! {
+    let _ = writeln!(io::stderr(), "mpd-ws: {}", msg);
+    process::exit(1);
+}
@@ -0,0 +1,167 @@
+//! The module describes server capabilities negotiated with MPD
+//!
+//! The protocol version announced in the connection banner tells which commands
+//! the server understands at all, and `commands`, `notcommands`, `tagtypes` and
+//! `urlhandlers` replies tell what is actually available to the current connection.
+//!
+//! `Client` keeps a `Capabilities` snapshot and checks every command against it
+//! before sending, so a command the server can't handle fails early with
+//! `Error::Unsupported` instead of an opaque `ACK [5@0]`.
+
+use error::{Error, Result};
+use std::collections::BTreeSet;
+use version::Version;
+
+/// Server capabilities
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capabilities {
+    /// protocol version from connection banner
+    pub version: Version,
+    /// commands available to current connection
+    pub commands: BTreeSet<String>,
+    /// commands known to server, but forbidden for current connection
+    pub notcommands: BTreeSet<String>,
+    /// supported tag types
+    pub tagtypes: BTreeSet<String>,
+    /// supported URL schemes (like `http://`)
+    pub urlhandlers: BTreeSet<String>,
+}
+
+impl Capabilities {
+    /// Create capabilities known from protocol version only
+    pub fn new(version: Version) -> Capabilities {
+        Capabilities {
+            version: version,
+            commands: BTreeSet::new(),
+            notcommands: BTreeSet::new(),
+            tagtypes: BTreeSet::new(),
+            urlhandlers: BTreeSet::new(),
+        }
+    }
+
+    /// Check if commands lists were received from server
+    ///
+    /// If they were not, only protocol version is used to check commands.
+    pub fn is_probed(&self) -> bool {
+        !self.commands.is_empty() || !self.notcommands.is_empty()
+    }
+
+    /// Minimal protocol version required for a command, if it is known
+    ///
+    /// Command can be given with a subcommand (like `sticker set`), in which case
+    /// the subcommand is looked up first, then the command itself.
+    pub fn min_version(command: &str) -> Option<Version> {
+        min_version_exact(command).or_else(|| command.split(' ').next().and_then(min_version_exact))
+    }
+
+    /// Check if command can be sent to server
+    pub fn supports(&self, command: &str) -> bool {
+        self.check(command).is_ok()
+    }
+
+    /// Check if command can be sent to server, returning `Error::Unsupported` if it can't
+    ///
+    /// Once command lists are received, commands the server doesn't know and
+    /// commands forbidden for current connection (see `notcommands`) are rejected.
+    pub fn check(&self, command: &str) -> Result<()> {
+        let min_version = Capabilities::min_version(command);
+        if let Some(min) = min_version {
+            if self.version < min {
+                return Err(Error::Unsupported {
+                    command: command.to_owned(),
+                    min_version: min_version,
+                });
+            }
+        }
+
+        let name = command.split(' ').next().unwrap_or(command);
+        if self.is_probed() && !name.starts_with("command_list_") && !self.commands.contains(name) {
+            return Err(Error::Unsupported {
+                command: command.to_owned(),
+                min_version: min_version,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Check if server supports a later form of a command (like `load` with
+    /// a position argument), returning `Error::Unsupported` if it doesn't
+    pub fn require(&self, command: &str, min_version: Version) -> Result<()> {
+        if self.version < min_version {
+            return Err(Error::Unsupported {
+                command: command.to_owned(),
+                min_version: Some(min_version),
+            });
+        }
+        Ok(())
+    }
+
+    /// Check if server supports given tag type (case insensitive)
+    pub fn supports_tag(&self, tag: &str) -> bool {
+        self.tagtypes.iter().any(|t| t.eq_ignore_ascii_case(tag))
+    }
+
+    /// Check if server can handle given URI (either local path or some URL with supported scheme)
+    pub fn supports_uri(&self, uri: &str) -> bool {
+        match uri.find("://") {
+            Some(pos) => self.urlhandlers.iter().any(|h| h.trim_end_matches("://") == &uri[..pos]),
+            None => true,
+        }
+    }
+}
+
+fn min_version_exact(command: &str) -> Option<Version> {
+    Some(match command {
+        "consume" | "single" | "sticker" => Version(0, 15, 0),
+        "rescan" | "replay_gain_mode" | "replay_gain_status" | "findadd" => Version(0, 16, 0),
+        "seekcur" | "prio" | "prioid" | "toggleoutput" | "config" | "searchadd" | "searchaddpl" | "mixrampdb" |
+        "mixrampdelay" | "channels" | "subscribe" | "unsubscribe" | "readmessages" | "sendmessage" => Version(0, 17, 0),
+        "rangeid" | "addtagid" | "cleartagid" | "listfiles" | "readcomments" | "listmounts" | "mount" | "unmount" |
+        "listneighbors" => Version(0, 19, 0),
+        "albumart" | "tagtypes enable" | "tagtypes disable" | "tagtypes clear" | "tagtypes all" => Version(0, 21, 0),
+        "readpicture" => Version(0, 22, 0),
+        "binarylimit" => Version(0, 22, 4),
+        "getvol" => Version(0, 23, 0),
+        "playlistlength" | "searchplaylist" | "stickernames" => Version(0, 24, 0),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use error::Error;
+    use version::Version;
+
+    #[test]
+    fn check_version() {
+        let caps = Capabilities::new(Version(0, 18, 0));
+        assert!(caps.supports("seekcur"));
+        assert!(caps.supports("sticker set"));
+        match caps.check("rangeid") {
+            Err(Error::Unsupported { ref command, min_version: Some(Version(0, 19, 0)) }) if command == "rangeid" => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn check_commands() {
+        let mut caps = Capabilities::new(Version(0, 21, 0));
+        caps.commands.insert("status".to_owned());
+        caps.notcommands.insert("kill".to_owned());
+        assert!(caps.supports("status"));
+        assert!(!caps.supports("kill"));
+        assert!(caps.supports("command_list_begin"));
+        assert!(!caps.supports("albumart"));
+    }
+
+    #[test]
+    fn check_uri() {
+        let mut caps = Capabilities::new(Version(0, 21, 0));
+        caps.urlhandlers.insert("http://".to_owned());
+        assert!(caps.supports_uri("Music/song.flac"));
+        assert!(caps.supports_uri("http://example.com/stream"));
+        assert!(!caps.supports_uri("smb://server/share"));
+    }
+}
@@ -6,21 +6,27 @@
 
 
 use bufstream::BufStream;
+use capabilities::Capabilities;
 
 use convert::*;
-use error::{Error, ProtoError, Result};
+use error::{CommandContext, Error, ParseError, ProtoError, Result, ServerError};
+use reply::Reply;
 use message::{Channel, Message};
 use mount::{Mount, Neighbor};
 use output::Output;
-use playlist::Playlist;
+use picture::{DEFAULT_AUTO_BINARY_LIMIT, DEFAULT_BINARY_LIMIT, MIN_BINARY_LIMIT, Picture};
+use playlist::{Playlist, PlaylistLength};
 use plugin::Plugin;
 use proto::*;
+use raw::RawResponse;
 use search::{Query, Window, Term};
-use song::{Id, Song};
+use song::{Id, QueuePosition, Song, SongBuf, SongRef, TagMask};
 use stats::Stats;
 use status::{ReplayGain, Status};
+use update::UpdateJob;
 use std::convert::From;
-use std::io::{BufRead, Lines, Read, Write};
+use std::io::{BufRead, Read, Write};
+use std::mem;
 use std::net::{TcpStream, ToSocketAddrs};
 use version::Version;
 
@@ -34,6 +40,12 @@ pub struct Client<S = TcpStream>
     socket: BufStream<S>,
     /// MPD version
     pub version: Version,
+    capabilities: Capabilities,
+    tag_mask: TagMask,
+    binary_limit: usize,
+    auto_binary_limit: usize,
+    pending: Vec<CommandContext>,
+    in_command_list: bool,
 }
 
 impl Default for Client<TcpStream> {
@@ -67,10 +79,40 @@ impl<S: Read + Write> Client<S> {
         Ok(Client {
             socket: socket,
             version: version,
+            capabilities: Capabilities::new(version),
+            tag_mask: TagMask::All,
+            binary_limit: DEFAULT_BINARY_LIMIT,
+            auto_binary_limit: DEFAULT_AUTO_BINARY_LIMIT,
+            pending: Vec::new(),
+            in_command_list: false,
         })
     }
     // }}}
 
+    // Capabilities {{{
+    /// Get server capabilities known so far
+    ///
+    /// Right after connection only protocol version is known,
+    /// use `refresh_capabilities()` to query server for the rest.
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    /// Query server for available commands, tag types and URL handlers
+    ///
+    /// Once capabilities are probed, commands not available to current connection
+    /// fail with `Error::Unsupported` without sending them to server.
+    pub fn refresh_capabilities(&mut self) -> Result<&Capabilities> {
+        let mut caps = Capabilities::new(self.version);
+        caps.commands = self.commands()?.into_iter().collect();
+        caps.notcommands = self.notcommands()?.into_iter().collect();
+        caps.tagtypes = self.tagtypes()?.into_iter().collect();
+        caps.urlhandlers = self.urlhandlers()?.into_iter().collect();
+        self.capabilities = caps;
+        Ok(&self.capabilities)
+    }
+    // }}}
+
     // Playback options & status {{{
     /// Get MPD status
     pub fn status(&mut self) -> Result<Status> {
@@ -209,19 +251,19 @@ impl<S: Read + Write> Client<S> {
             "playlistinfo"
         };
         self.run_command(command, pos.to_range())
-            .and_then(|_| self.read_structs("file"))
+            .and_then(|_| self.read_songs())
     }
 
     /// List all songs in a play queue
     pub fn queue(&mut self) -> Result<Vec<Song>> {
         self.run_command("playlistinfo", ())
-            .and_then(|_| self.read_structs("file"))
+            .and_then(|_| self.read_songs())
     }
 
     /// Get current playing song
     pub fn currentsong(&mut self) -> Result<Option<Song>> {
         self.run_command("currentsong", ())
-            .and_then(|_| self.read_struct::<Song>())
+            .and_then(|_| self.read_song())
             .map(|s| if s.place.is_none() { None } else { Some(s) })
     }
 
@@ -234,7 +276,7 @@ impl<S: Read + Write> Client<S> {
     /// List all changes in a queue since given version
     pub fn changes(&mut self, version: u32) -> Result<Vec<Song>> {
         self.run_command("plchanges", version)
-            .and_then(|_| self.read_structs("file"))
+            .and_then(|_| self.read_songs())
     }
 
     /// Append a song into a queue
@@ -245,7 +287,11 @@ impl<S: Read + Write> Client<S> {
     }
 
     /// Insert a song into a given position in a queue
-    pub fn insert<P: ToSongPath>(&mut self, path: P, pos: usize) -> Result<usize> {
+    ///
+    /// Position can be absolute or relative to the current song,
+    /// e.g. `QueuePosition::AfterCurrent(0)` to play the song next.
+    pub fn insert<P: ToSongPath, Q: ToQueuePosition>(&mut self, path: P, pos: Q) -> Result<usize> {
+        let pos = self.check_position("addid", pos)?;
         self.run_command("addid", (path, pos))
             .and_then(|_| self.read_field("Id"))
     }
@@ -258,8 +304,11 @@ impl<S: Read + Write> Client<S> {
     }
 
     /// Move a song (at a some position) or several songs (in a range) to other position in queue
-    pub fn shift<T: ToQueueRangeOrPlace>(&mut self, from: T, to: usize) -> Result<()> {
+    ///
+    /// Target position can be absolute or relative to the current song.
+    pub fn shift<T: ToQueueRangeOrPlace, Q: ToQueuePosition>(&mut self, from: T, to: Q) -> Result<()> {
         let command = if T::is_id() { "moveid" } else { "move" };
+        let to = self.check_position(command, to)?;
         self.run_command(command, (from.to_range(), to))
             .and_then(|_| self.expect_ok())
     }
@@ -321,10 +370,38 @@ impl<S: Read + Write> Client<S> {
         self.run_command("kill", ()).and_then(|_| self.expect_ok())
     }
 
+    /// Set maximal chunk size (in bytes) for binary replies, like `albumart` and `readpicture`
+    ///
+    /// Bigger chunks mean less round trips for large pictures, MPD doesn't accept
+    /// values less than 64 bytes.
+    pub fn set_binary_limit(&mut self, limit: usize) -> Result<()> {
+        self.run_command("binarylimit", limit)
+            .and_then(|_| self.expect_ok())
+            .map(|_| self.binary_limit = limit)
+    }
+
+    /// Let client raise binary chunk size automatically up to `max` bytes (use 0 to disable)
+    ///
+    /// Once the first chunk of a binary reply reveals total size,
+    /// chunk size is raised to fetch the rest in as few chunks as possible,
+    /// but not more than `max` bytes per chunk (`DEFAULT_AUTO_BINARY_LIMIT` by default).
+    /// Does nothing on servers without `binarylimit` command support, and stops
+    /// trying if server rejects the command (like a proxy sharing the connection).
+    pub fn set_auto_binary_limit(&mut self, max: usize) {
+        self.auto_binary_limit = max;
+    }
+
     /// Login to MPD server with given password
+    ///
+    /// If capabilities were probed before, they are refreshed,
+    /// as password changes the set of available commands.
     pub fn login(&mut self, password: &str) -> Result<()> {
         self.run_command("password", password)
-            .and_then(|_| self.expect_ok())
+            .and_then(|_| self.expect_ok())?;
+        if self.capabilities.is_probed() {
+            self.refresh_capabilities()?;
+        }
+        Ok(())
     }
     // }}}
 
@@ -337,8 +414,32 @@ impl<S: Read + Write> Client<S> {
 
     /// List all songs in a playlist
     pub fn playlist<N: ToPlaylistName>(&mut self, name: N) -> Result<Vec<Song>> {
-        self.run_command("listplaylistinfo", name.to_name())
-            .and_then(|_| self.read_structs("file"))
+        self.run_command("listplaylistinfo", PlaylistName(name.to_name()))
+            .and_then(|_| self.read_songs())
+    }
+
+    /// List files in a playlist (without songs metadata)
+    pub fn pl_files<N: ToPlaylistName>(&mut self, name: N) -> Result<Vec<String>> {
+        self.run_command("listplaylist", PlaylistName(name.to_name()))
+            .and_then(|_| self.read_list("file"))
+    }
+
+    /// Get number of songs and their total duration in a playlist
+    pub fn pl_length<N: ToPlaylistName>(&mut self, name: N) -> Result<PlaylistLength> {
+        self.run_command("playlistlength", PlaylistName(name.to_name()))
+            .and_then(|_| self.read_struct())
+    }
+
+    /// Search songs in a playlist (case insensitive)
+    ///
+    /// Like with `search()`, every query term must be a substring of the song's
+    /// tag value, the query is sent as a filter expression (see `Expression`).
+    pub fn pl_search<N, W>(&mut self, name: N, query: &Query, window: W) -> Result<Vec<Song>>
+        where N: ToPlaylistName,
+              W: Into<Window>
+    {
+        self.run_command("searchplaylist", (PlaylistName(name.to_name()), query.expression("contains"), window.into()))
+            .and_then(|_| self.read_songs())
     }
 
     /// Load playlist into queue
@@ -346,7 +447,21 @@ impl<S: Read + Write> Client<S> {
     /// You can give either full range (`..`) to load all songs in a playlist,
     /// or some partial range to load only part of playlist.
     pub fn load<T: ToQueueRange, N: ToPlaylistName>(&mut self, name: N, range: T) -> Result<()> {
-        self.run_command("load", (name.to_name(), range.to_range()))
+        self.run_command("load", (PlaylistName(name.to_name()), range.to_range()))
+            .and_then(|_| self.expect_ok())
+    }
+
+    /// Load playlist (or some range of it) into a given position in queue
+    ///
+    /// Position can be absolute or relative to the current song.
+    pub fn load_at<T, N, Q>(&mut self, name: N, range: T, pos: Q) -> Result<()>
+        where T: ToQueueRange,
+              N: ToPlaylistName,
+              Q: ToQueuePosition
+    {
+        self.capabilities.require("load {POSITION}", Version(0, 23, 1))?;
+        let pos = self.check_position("load", pos)?;
+        self.run_command("load", (PlaylistName(name.to_name()), range.to_range(), pos))
             .and_then(|_| self.expect_ok())
     }
 
@@ -354,59 +469,90 @@ impl<S: Read + Write> Client<S> {
     ///
     /// If playlist with given name doesn't exist, create new one.
     pub fn save<N: ToPlaylistName>(&mut self, name: N) -> Result<()> {
-        self.run_command("save", name.to_name())
+        self.run_command("save", PlaylistName(name.to_name()))
             .and_then(|_| self.expect_ok())
     }
 
     /// Rename playlist
     pub fn pl_rename<N: ToPlaylistName>(&mut self, name: N, newname: &str) -> Result<()> {
-        self.run_command("rename", (name.to_name(), newname))
+        self.run_command("rename", (PlaylistName(name.to_name()), PlaylistName(newname)))
             .and_then(|_| self.expect_ok())
     }
 
     /// Clear playlist
     pub fn pl_clear<N: ToPlaylistName>(&mut self, name: N) -> Result<()> {
-        self.run_command("playlistclear", name.to_name())
+        self.run_command("playlistclear", PlaylistName(name.to_name()))
             .and_then(|_| self.expect_ok())
     }
 
     /// Delete playlist
     pub fn pl_remove<N: ToPlaylistName>(&mut self, name: N) -> Result<()> {
-        self.run_command("rm", name.to_name())
+        self.run_command("rm", PlaylistName(name.to_name()))
             .and_then(|_| self.expect_ok())
     }
 
     /// Add new songs to a playlist
     pub fn pl_push<N: ToPlaylistName, P: ToSongPath>(&mut self, name: N, path: P) -> Result<()> {
-        self.run_command("playlistadd", (name.to_name(), path))
+        self.run_command("playlistadd", (PlaylistName(name.to_name()), path))
+            .and_then(|_| self.expect_ok())
+    }
+
+    /// Insert a song into a given position in a playlist
+    pub fn pl_insert<N: ToPlaylistName, P: ToSongPath>(&mut self, name: N, path: P, pos: u32) -> Result<()> {
+        self.capabilities.require("playlistadd {POSITION}", Version(0, 23, 1))?;
+        self.run_command("playlistadd", (PlaylistName(name.to_name()), path, pos))
+            .and_then(|_| self.expect_ok())
+    }
+
+    /// Find songs in the db matching query (case insensitive) and add them to a playlist
+    pub fn pl_search_add<N: ToPlaylistName>(&mut self, name: N, query: &Query) -> Result<()> {
+        self.run_command("searchaddpl", (PlaylistName(name.to_name()), query))
             .and_then(|_| self.expect_ok())
     }
 
     /// Delete a song at a given position in a playlist
     pub fn pl_delete<N: ToPlaylistName>(&mut self, name: N, pos: u32) -> Result<()> {
-        self.run_command("playlistdelete", (name.to_name(), pos))
+        self.run_command("playlistdelete", (PlaylistName(name.to_name()), pos))
+            .and_then(|_| self.expect_ok())
+    }
+
+    /// Delete songs in a given range from a playlist
+    pub fn pl_delete_range<N: ToPlaylistName, T: ToQueueRange>(&mut self, name: N, range: T) -> Result<()> {
+        self.capabilities.require("playlistdelete {START:END}", Version(0, 23, 3))?;
+        self.run_command("playlistdelete", (PlaylistName(name.to_name()), range.to_range()))
             .and_then(|_| self.expect_ok())
     }
 
     /// Move song in a playlist from one position into another
     pub fn pl_shift<N: ToPlaylistName>(&mut self, name: N, from: u32, to: u32) -> Result<()> {
-        self.run_command("playlistmove", (name.to_name(), from, to))
+        self.run_command("playlistmove", (PlaylistName(name.to_name()), from, to))
             .and_then(|_| self.expect_ok())
     }
     // }}}
 
     // Database methods {{{
     /// Run database rescan, i.e. remove non-existing files from DB
-    /// as well as add new files to DB
-    pub fn rescan(&mut self) -> Result<u32> {
-        self.run_command("rescan", ())
-            .and_then(|_| self.read_field("updating_db"))
+    /// as well as add new files to DB, rereading unmodified files too
+    ///
+    /// Only the path (a file or directory) is rescanned, if given.
+    pub fn rescan(&mut self, path: Option<&str>) -> Result<UpdateJob> {
+        self.update_job("rescan", path)
     }
 
     /// Run database update, i.e. remove non-existing files from DB
-    pub fn update(&mut self) -> Result<u32> {
-        self.run_command("update", ())
-            .and_then(|_| self.read_field("updating_db"))
+    /// as well as add new files to DB
+    ///
+    /// Only the path (a file or directory) is updated, if given.
+    pub fn update(&mut self, path: Option<&str>) -> Result<UpdateJob> {
+        self.update_job("update", path)
+    }
+
+    fn update_job(&mut self, command: &str, path: Option<&str>) -> Result<UpdateJob> {
+        match path {
+            Some(path) => self.run_command(command, path)?,
+            None => self.run_command(command, ())?,
+        }
+        self.read_field("updating_db").map(|id| UpdateJob { id: id })
     }
     // }}}
 
@@ -433,7 +579,44 @@ impl<S: Read + Write> Client<S> {
 
     fn find_generic(&mut self, cmd: &str, query: &Query, window: Window) -> Result<Vec<Song>> {
         self.run_command(cmd, (query, window))
-            .and_then(|_| self.read_structs("file"))
+            .and_then(|_| self.read_songs())
+    }
+
+    /// Call a function for every song matching the query (or every song
+    /// in the database, if the query is empty) without building owned `Song`s
+    ///
+    /// Songs are borrowed from a single buffer reused for the whole reply,
+    /// so it's the cheapest way to compute aggregates over large libraries.
+    /// Use `SongRef::to_song()` to keep some of the songs.
+    pub fn for_each_song<F>(&mut self, query: &Query, mut f: F) -> Result<()>
+        where F: FnMut(SongRef)
+    {
+        if query.is_empty() {
+            self.run_command("listallinfo", ())?;
+        } else {
+            self.run_command("find", query)?;
+        }
+
+        let mask = self.tag_mask.clone();
+        let tagtypes = self.capabilities.tagtypes.clone();
+        let mut song = SongBuf::default();
+        self.read_pairs_with(|key, value| {
+            // directories and playlists are listed among songs by `listallinfo`
+            if key == "file" || key == "directory" || key == "playlist" {
+                if !song.is_empty() {
+                    f(song.get(&mask, &tagtypes));
+                }
+                song.clear();
+            }
+            if key == "file" || !song.is_empty() {
+                song.push(key, value);
+            }
+            Ok(())
+        })?;
+        if !song.is_empty() {
+            f(song.get(&mask, &tagtypes));
+        }
+        Ok(())
     }
 
     /// Lists unique tags values of the specified type for songs matching the given query.
@@ -453,9 +636,47 @@ impl<S: Read + Write> Client<S> {
     /// Lists the contents of a directory.
     pub fn lsinfo<P: ToSongPath>(&mut self, path: P) -> Result<Song> {
         self.run_command("lsinfo", path)
-            .and_then(|_| self.read_struct())
+            .and_then(|_| self.read_song())
+    }
+
+    // }}}
+
+    // Pictures {{{
+    /// Get album art for a song, or `None` if there is no picture
+    ///
+    /// MPD looks for a picture file (like `cover.jpg`) in the song's directory.
+    pub fn albumart<P: ToSongPath>(&mut self, path: P) -> Result<Option<Picture>> {
+        self.albumart_progress(path, |_, _| true)
+    }
+
+    /// Get album art for a song, reporting progress
+    ///
+    /// Progress callback gets number of bytes received so far and total picture size,
+    /// and returns `false` to cancel the transfer, in which case `Error::Cancelled` is returned.
+    /// `Ok(None)` means there is no picture.
+    pub fn albumart_progress<P, F>(&mut self, path: P, progress: F) -> Result<Option<Picture>>
+        where P: ToSongPath,
+              F: FnMut(usize, usize) -> bool
+    {
+        self.read_picture("albumart", path.to_path(), progress)
     }
 
+    /// Get picture embedded into a song file, or `None` if there is no picture
+    pub fn readpicture<P: ToSongPath>(&mut self, path: P) -> Result<Option<Picture>> {
+        self.readpicture_progress(path, |_, _| true)
+    }
+
+    /// Get picture embedded into a song file, reporting progress
+    ///
+    /// Progress callback gets number of bytes received so far and total picture size,
+    /// and returns `false` to cancel the transfer, in which case `Error::Cancelled` is returned.
+    /// `Ok(None)` means there is no picture.
+    pub fn readpicture_progress<P, F>(&mut self, path: P, progress: F) -> Result<Option<Picture>>
+        where P: ToSongPath,
+              F: FnMut(usize, usize) -> bool
+    {
+        self.read_picture("readpicture", path.to_path(), progress)
+    }
     // }}}
 
     // Output methods {{{
@@ -524,9 +745,63 @@ impl<S: Read + Write> Client<S> {
             .and_then(|_| self.read_list("tagtype"))
     }
 
+    /// Get tag types requested by current connection
+    ///
+    /// Songs received by the client carry this mask, see `Song::tag()`.
+    pub fn tag_mask(&self) -> &TagMask {
+        &self.tag_mask
+    }
+
+    /// Stop receiving given tag types in song replies for current connection
+    pub fn tagtypes_disable(&mut self, tags: &[&str]) -> Result<()> {
+        self.run_command("tagtypes disable", tags)
+            .and_then(|_| self.expect_ok())
+            .and_then(|_| self.reload_tag_mask())
+    }
+
+    /// Start receiving given tag types in song replies for current connection
+    pub fn tagtypes_enable(&mut self, tags: &[&str]) -> Result<()> {
+        self.run_command("tagtypes enable", tags)
+            .and_then(|_| self.expect_ok())
+            .and_then(|_| self.reload_tag_mask())
+    }
+
+    /// Stop receiving any tags in song replies for current connection
+    pub fn tagtypes_clear(&mut self) -> Result<()> {
+        self.run_command("tagtypes clear", ())
+            .and_then(|_| self.expect_ok())
+            .map(|_| self.tag_mask = TagMask::only(Vec::<String>::new()))
+    }
+
+    /// Receive all tags in song replies for current connection (the default)
+    pub fn tagtypes_all(&mut self) -> Result<()> {
+        self.run_command("tagtypes all", ())
+            .and_then(|_| self.expect_ok())
+            .map(|_| self.tag_mask = TagMask::All)
+    }
+
+    fn reload_tag_mask(&mut self) -> Result<()> {
+        let tags = self.tagtypes()?;
+        self.tag_mask = TagMask::only(tags);
+        Ok(())
+    }
+
     /// List all available decoder plugins
     pub fn decoders(&mut self) -> Result<Vec<Plugin>> {
-        self.run_command("decoders", ()).and_then(|_| self.read_struct())
+        self.run_command("decoders", ()).and_then(|_| self.feed_struct())
+    }
+    // }}}
+
+    // Raw commands {{{
+    /// Send arbitrary command, and return all reply pairs
+    ///
+    /// Use it to call commands this crate doesn't wrap yet.
+    /// Arguments are quoted and validated the same way as for other commands.
+    /// Commands with binary replies (like `albumart`) are not supported.
+    pub fn raw<A: ToArguments>(&mut self, command: &str, arguments: A) -> Result<RawResponse> {
+        self.run_command(command, arguments)
+            .and_then(|_| self.read_pairs().collect())
+            .map(RawResponse)
     }
     // }}}
 
@@ -598,19 +873,20 @@ impl<S: Read + Write> Client<S> {
     // Sticker methods {{{
     /// Show sticker value for a given object, identified by type and uri
     pub fn sticker(&mut self, typ: &str, uri: &str, name: &str) -> Result<String> {
-        self.run_command("sticker set", (typ, uri, name))
-            .and_then(|_| self.read_field("sticker"))
+        self.run_command("sticker get", (typ, uri, StickerName(name)))
+            .and_then(|_| self.read_field::<String, _>("sticker"))
+            .map(|s| s.splitn(2, '=').nth(1).unwrap_or("").to_owned())
     }
 
     /// Set sticker value for a given object, identified by type and uri
     pub fn set_sticker(&mut self, typ: &str, uri: &str, name: &str, value: &str) -> Result<()> {
-        self.run_command("sticker set", (typ, uri, name, value))
+        self.run_command("sticker set", (typ, uri, StickerName(name), value))
             .and_then(|_| self.expect_ok())
     }
 
     /// Delete sticker from a given object, identified by type and uri
     pub fn delete_sticker(&mut self, typ: &str, uri: &str, name: &str) -> Result<()> {
-        self.run_command("sticker delete", (typ, uri, name))
+        self.run_command("sticker delete", (typ, uri, StickerName(name)))
             .and_then(|_| self.expect_ok())
     }
 
@@ -630,7 +906,7 @@ impl<S: Read + Write> Client<S> {
     /// List all (file, sticker) pairs for sticker name and objects of given type
     /// from given directory (identified by uri)
     pub fn find_sticker(&mut self, typ: &str, uri: &str, name: &str) -> Result<Vec<(String, String)>> {
-        self.run_command("sticker find", (typ, uri, name))
+        self.run_command("sticker find", (typ, uri, StickerName(name)))
             .and_then(|_| {
                 self.read_pairs()
                     .split("file")
@@ -649,13 +925,108 @@ impl<S: Read + Write> Client<S> {
     /// List all files of a given type under given directory (identified by uri)
     /// with a tag set to given value
     pub fn find_sticker_eq(&mut self, typ: &str, uri: &str, name: &str, value: &str) -> Result<Vec<String>> {
-        self.run_command("sticker find", (typ, uri, name, value))
+        self.run_command("sticker find", (typ, uri, StickerName(name), value))
             .and_then(|_| self.read_list("file"))
     }
     // }}}
 }
 
 // Helper methods {{{
+impl<S: Read + Write> Client<S> {
+    fn read_songs(&mut self) -> Result<Vec<Song>> {
+        let mut songs: Vec<Song> = self.feed_structs("file")?;
+        for song in &mut songs {
+            song.move_tags(&self.capabilities.tagtypes);
+            song.tag_mask = self.tag_mask.clone();
+        }
+        Ok(songs)
+    }
+
+    /// Check if server supports position, relative positions require MPD 0.23
+    fn check_position<Q: ToQueuePosition>(&self, command: &str, pos: Q) -> Result<QueuePosition> {
+        let pos = pos.to_position()?;
+        if pos.is_relative() {
+            self.capabilities.require(&format!("{} {{RELATIVE POSITION}}", command), Version(0, 23, 0))?;
+        }
+        Ok(pos)
+    }
+
+    fn read_picture<F>(&mut self, command: &str, path: &str, mut progress: F) -> Result<Option<Picture>>
+        where F: FnMut(usize, usize) -> bool
+    {
+        let mut picture = Picture {
+            data: Vec::new(),
+            mime_type: None,
+        };
+
+        loop {
+            let offset = picture.data.len();
+            self.run_command(command, (path, offset))?;
+            let size = match self.read_binary_chunk(&mut picture)? {
+                Some(size) => size,
+                None => return Ok(None),
+            };
+
+            if !progress(picture.data.len(), size) {
+                return Err(Error::Cancelled);
+            }
+            if picture.data.len() >= size || picture.data.len() == offset {
+                return Ok(Some(picture));
+            }
+
+            let left = size - picture.data.len();
+            if self.auto_binary_limit > self.binary_limit && left > self.binary_limit &&
+               self.capabilities.supports("binarylimit") {
+                let limit = ::std::cmp::max(::std::cmp::min(left, self.auto_binary_limit), MIN_BINARY_LIMIT);
+                match self.set_binary_limit(limit) {
+                    Ok(()) => (),
+                    // keep fetching with the current chunk size
+                    Err(Error::Server(_)) => self.auto_binary_limit = 0,
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    }
+
+    /// Read one chunk of a binary reply, appending data to the picture
+    ///
+    /// Returns total data size, or `None` if the reply has no data.
+    fn read_binary_chunk(&mut self, picture: &mut Picture) -> Result<Option<usize>> {
+        let mut size = None;
+        loop {
+            let line = self.read_line()?;
+            match line.parse::<Reply>()? {
+                Reply::Ok => return Ok(None),
+                Reply::Ack(e) => return Err(self.server_error(e)),
+                Reply::Pair(ref key, ref value) if key == "size" => size = Some(value.parse()?),
+                Reply::Pair(ref key, value) if key == "type" => picture.mime_type = Some(value),
+                Reply::Pair(ref key, ref value) if key == "binary" => {
+                    let len: usize = value.parse()?;
+                    let start = picture.data.len();
+                    picture.data.resize(start + len, 0);
+                    self.socket.read_exact(&mut picture.data[start..])?;
+
+                    let mut newline = [0u8];
+                    self.socket.read_exact(&mut newline)?;
+                    if newline[0] != b'\n' {
+                        return Err(Error::Parse(ParseError::BadPair));
+                    }
+                    self.expect_ok()?;
+                    return size.map(Some).ok_or(Error::Proto(ProtoError::NoField("size")));
+                }
+                Reply::Pair(..) => (),
+            }
+        }
+    }
+
+    fn read_song(&mut self) -> Result<Song> {
+        let mut song: Song = self.feed_struct()?;
+        song.move_tags(&self.capabilities.tagtypes);
+        song.tag_mask = self.tag_mask.clone();
+        Ok(song)
+    }
+}
+
 impl<S: Read + Write> Proto for Client<S> {
     type Stream = S;
 
@@ -668,13 +1039,41 @@ impl<S: Read + Write> Proto for Client<S> {
         Ok(buf)
     }
 
-    fn read_pairs(&mut self) -> Pairs<Lines<&mut BufStream<S>>> {
-        Pairs((&mut self.socket).lines())
+    fn read_pairs(&mut self) -> Pairs<&mut BufStream<S>> {
+        Pairs::new(&mut self.socket, mem::replace(&mut self.pending, Vec::new()))
+    }
+
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.socket.read_exact(buf).map_err(From::from)
+    }
+
+    fn server_error(&self, error: ServerError) -> Error {
+        Error::Server(with_context(error, &self.pending))
     }
 
     fn run_command<I>(&mut self, command: &str, arguments: I) -> Result<()>
         where I: ToArguments
     {
+        self.capabilities.check(command)?;
+        check_argument(command)?;
+        arguments.validate()?;
+
+        match command {
+            "command_list_begin" | "command_list_ok_begin" => {
+                self.pending.clear();
+                self.in_command_list = true;
+            }
+            "command_list_end" => self.in_command_list = false,
+            _ => {
+                if !self.in_command_list {
+                    self.pending.clear();
+                }
+                let mut args = Vec::new();
+                let _ = arguments.to_arguments::<_, ()>(&mut |arg| Ok(args.push(arg.to_owned())));
+                self.pending.push(CommandContext::new(command, args));
+            }
+        }
+
         self.socket
             .write_all(command.as_bytes())
             .and_then(|_| arguments.to_arguments(&mut |arg| write!(self.socket, " {}", Quoted(arg))))
@@ -0,0 +1,171 @@
+//! The module defines playback clock, estimating current song position locally
+//!
+//! `PlaybackClock` is anchored to a `Status` snapshot and a local monotonic timestamp,
+//! so progress bars can get current position at any moment without polling `status()`.
+//! Re-anchor it with `reset()` when `player` idle events arrive, or check it against
+//! an occasional status with `sync()`, which re-anchors only if the estimate drifted.
+//!
+//! ```rust,no_run
+//! # extern crate mpd;
+//! # fn main() {
+//! use mpd::clock::PlaybackClock;
+//!
+//! let mut conn = mpd::Client::connect("127.0.0.1:6600").unwrap();
+//! let clock = PlaybackClock::new(&conn.status().unwrap());
+//! println!("{:?} of {:?}", clock.position(), clock.duration);
+//! # }
+//! ```
+
+use song::Id;
+use status::{State, Status};
+use std::time::Instant;
+use time::Duration;
+
+/// Default drift threshold (in milliseconds)
+pub const DRIFT_THRESHOLD: i64 = 500;
+
+/// Playback position estimate
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlaybackClock {
+    /// playback state
+    pub state: State,
+    /// current song ID
+    pub song: Option<Id>,
+    /// song position at the anchor time
+    pub elapsed: Duration,
+    /// song duration (unknown for streams)
+    pub duration: Option<Duration>,
+    /// audio sample rate, Hz
+    pub rate: Option<u32>,
+    /// local time of the status snapshot
+    pub anchor: Instant,
+    /// max difference between estimated and reported position, before `sync()` re-anchors the clock
+    pub threshold: Duration,
+}
+
+impl PlaybackClock {
+    /// Clock anchored to the status, which was just received
+    pub fn new(status: &Status) -> PlaybackClock {
+        PlaybackClock::at(status, Instant::now())
+    }
+
+    /// Clock anchored to the status, received at given time
+    pub fn at(status: &Status, anchor: Instant) -> PlaybackClock {
+        PlaybackClock {
+            state: status.state,
+            song: status.song.map(|place| place.id),
+            elapsed: status.elapsed.or_else(|| status.time.map(|(elapsed, _)| elapsed)).unwrap_or_else(Duration::zero),
+            duration: status.duration.or_else(|| status.time.map(|(_, total)| total).filter(|total| *total > Duration::zero())),
+            rate: status.audio.map(|audio| audio.rate),
+            anchor: anchor,
+            threshold: Duration::milliseconds(DRIFT_THRESHOLD),
+        }
+    }
+
+    /// Estimated song position now
+    pub fn position(&self) -> Duration {
+        self.position_at(Instant::now())
+    }
+
+    /// Estimated song position at given time (never past the song end)
+    pub fn position_at(&self, now: Instant) -> Duration {
+        let mut position = self.elapsed;
+        if self.state == State::Play && now > self.anchor {
+            position = position + Duration::from_std(now - self.anchor).unwrap_or_else(|_| Duration::zero());
+        }
+        match self.duration {
+            Some(duration) if position > duration => duration,
+            _ => position,
+        }
+    }
+
+    /// Estimated time left until the song end
+    pub fn remaining_at(&self, now: Instant) -> Option<Duration> {
+        self.duration.map(|duration| duration - self.position_at(now))
+    }
+
+    /// Estimated song progress (from 0 to 1)
+    pub fn progress_at(&self, now: Instant) -> Option<f32> {
+        self.duration
+            .filter(|duration| *duration > Duration::zero())
+            .map(|duration| self.position_at(now).num_milliseconds() as f32 / duration.num_milliseconds() as f32)
+    }
+
+    /// Estimated position in audio frames (samples per channel)
+    pub fn frame_at(&self, now: Instant) -> Option<u64> {
+        let position = self.position_at(now).num_microseconds().unwrap_or(0) as u64;
+        self.rate.map(|rate| position * rate as u64 / 1_000_000)
+    }
+
+    /// Re-anchor the clock to a new status (e.g. after `player` idle event)
+    pub fn reset(&mut self, status: &Status) {
+        *self = PlaybackClock { threshold: self.threshold, ..PlaybackClock::new(status) };
+    }
+
+    /// Check the clock against a status, which was just received
+    ///
+    /// The clock is re-anchored if playback state, song or duration changed,
+    /// or estimated position drifted more than the threshold.
+    /// Returns `true` if the clock was re-anchored.
+    pub fn sync(&mut self, status: &Status) -> bool {
+        self.sync_at(status, Instant::now())
+    }
+
+    /// Check the clock against a status, received at given time (see `sync()`)
+    pub fn sync_at(&mut self, status: &Status, now: Instant) -> bool {
+        let clock = PlaybackClock { threshold: self.threshold, ..PlaybackClock::at(status, now) };
+        let drift = self.position_at(now) - clock.elapsed;
+        if clock.state != self.state || clock.song != self.song || clock.duration != self.duration ||
+           drift > self.threshold || -drift > self.threshold {
+            *self = clock;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use song::QueuePlace;
+    use std::time::Duration as StdDuration;
+
+    fn status(state: State, elapsed: i64) -> Status {
+        Status {
+            state: state,
+            song: Some(QueuePlace { id: Id(5), pos: 0, prio: 0 }),
+            elapsed: Some(Duration::milliseconds(elapsed)),
+            duration: Some(Duration::seconds(10)),
+            ..Status::default()
+        }
+    }
+
+    #[test]
+    fn extrapolation() {
+        let now = Instant::now();
+        let later = now + StdDuration::from_millis(1500);
+
+        let clock = PlaybackClock::at(&status(State::Play, 2000), now);
+        assert_eq!(clock.position_at(later), Duration::milliseconds(3500));
+        assert_eq!(clock.remaining_at(later), Some(Duration::milliseconds(6500)));
+        assert_eq!(clock.progress_at(later), Some(0.35));
+        assert_eq!(clock.position_at(now + StdDuration::from_secs(60)), Duration::seconds(10));
+
+        let clock = PlaybackClock::at(&status(State::Pause, 2000), now);
+        assert_eq!(clock.position_at(later), Duration::milliseconds(2000));
+    }
+
+    #[test]
+    fn drift() {
+        let now = Instant::now();
+        let mut clock = PlaybackClock::at(&status(State::Play, 2000), now);
+
+        let later = now + StdDuration::from_secs(1);
+        assert!(!clock.sync_at(&status(State::Play, 3300), later));
+        assert_eq!(clock.anchor, now);
+        assert!(clock.sync_at(&status(State::Play, 3600), later));
+        assert_eq!((clock.anchor, clock.elapsed), (later, Duration::milliseconds(3600)));
+        assert!(clock.sync_at(&status(State::Pause, 3600), later));
+    }
+}
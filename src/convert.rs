@@ -1,29 +1,31 @@
 #![allow(missing_docs)]
 //! These are inner traits to support methods overloading for the `Client`
 
-use error::Error;
+use error::{ArgumentError, Error};
 use output::Output;
 use playlist::Playlist;
 use proto::ToArguments;
-use song::{self, Id, Song};
-use std::collections::BTreeMap;
+use song::{self, Id, QueuePosition, Song};
+use std::convert::TryFrom;
 use std::ops::{Range, RangeFrom, RangeFull, RangeTo};
 
 use time::Duration;
 
+/// Response types built incrementally from reply pairs
+///
+/// Pairs are borrowed from reader's line buffer, so implementations
+/// only allocate for data they keep.
 #[doc(hidden)]
-pub trait FromMap: Sized {
-    fn from_map(map: BTreeMap<String, String>) -> Result<Self, Error>;
-}
-
-#[doc(hidden)]
-pub trait FromIter: Sized {
-    fn from_iter<I: Iterator<Item = Result<(String, String), Error>>>(iter: I) -> Result<Self, Error>;
-}
+pub trait FromIter: Sized + Default {
+    fn feed(&mut self, key: &str, value: &str) -> Result<(), Error>;
 
-impl<T: FromIter> FromMap for T {
-    fn from_map(map: BTreeMap<String, String>) -> Result<Self, Error> {
-        FromIter::from_iter(map.into_iter().map(Ok))
+    fn from_iter<I: Iterator<Item = Result<(String, String), Error>>>(iter: I) -> Result<Self, Error> {
+        let mut result = Self::default();
+        for pair in iter {
+            let (key, value) = pair?;
+            result.feed(&key, &value)?;
+        }
+        Ok(result)
     }
 }
 
@@ -190,6 +192,22 @@ impl IsId for Id {
     }
 }
 
+pub trait ToQueuePosition {
+    fn to_position(self) -> Result<QueuePosition, ArgumentError>;
+}
+
+impl ToQueuePosition for QueuePosition {
+    fn to_position(self) -> Result<QueuePosition, ArgumentError> {
+        Ok(self)
+    }
+}
+
+impl ToQueuePosition for usize {
+    fn to_position(self) -> Result<QueuePosition, ArgumentError> {
+        u32::try_from(self).map(QueuePosition::Absolute).map_err(|_| ArgumentError::out_of_range(self))
+    }
+}
+
 pub trait ToSongId {
     fn to_song_id(&self) -> Id;
 }
@@ -8,48 +8,78 @@
 //!     mostly because protocol version mismatch, network data corruption
 //!     or just bugs in the client),
 //!   - server errors (run-time errors coming from MPD due to some MPD
-//!     errors, like database failures or sound problems)
+//!     errors, like database failures or sound problems),
+//!   - argument errors (invalid command arguments rejected by the client
+//!     before sending, like strings with embedded newlines),
+//!   - cancelled operations (like picture transfer stopped by a progress callback)
 //!
 //! This module defines all necessary infrastructure to represent these kinds or errors.
 
 use std::convert::From;
 use std::error::Error as StdError;
 use std::fmt;
-use std::io::Error as IoError;
+use std::io::{Error as IoError, ErrorKind as IoErrorKind};
 use std::num::{ParseFloatError, ParseIntError};
 use std::result;
 use std::str::FromStr;
 use std::string::ParseError as StringParseError;
 use time::ParseError as TimeParseError;
+use version::Version;
 
 // Server errors {{{
 /// Server error codes, as defined in [libmpdclient](http://www.musicpd.org/doc/libmpdclient/protocol_8h_source.html)
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
 pub enum ErrorCode {
-    /// not a list
-    NotList = 1,
-    /// bad command arguments
-    Argument = 2,
-    /// invalid password
-    Password = 3,
-    /// insufficient permissions
-    Permission = 4,
-    /// unknown command
-    UnknownCmd = 5,
-    /// object doesn't exist
-    NoExist = 50,
-    /// maximum playlist size exceeded
-    PlaylistMax = 51,
-    /// general system error
-    System = 52,
-    /// error loading playlist
-    PlaylistLoad = 53,
-    /// update database is already in progress
-    UpdateAlready = 54,
-    /// player synchronization error
-    PlayerSync = 55,
-    /// object already exists
-    Exist = 56,
+    /// not a list (1)
+    NotList,
+    /// bad command arguments (2)
+    Argument,
+    /// invalid password (3)
+    Password,
+    /// insufficient permissions (4)
+    Permission,
+    /// unknown command (5)
+    UnknownCmd,
+    /// object doesn't exist (50)
+    NoExist,
+    /// maximum playlist size exceeded (51)
+    PlaylistMax,
+    /// general system error (52)
+    System,
+    /// error loading playlist (53)
+    PlaylistLoad,
+    /// update database is already in progress (54)
+    UpdateAlready,
+    /// player synchronization error (55)
+    PlayerSync,
+    /// object already exists (56)
+    Exist,
+    /// error code unknown to this library (probably added in newer MPD version)
+    Unknown(u16),
+}
+
+impl ErrorCode {
+    /// Numeric error code, as sent by server
+    pub fn code(&self) -> u16 {
+        use self::ErrorCode::*;
+        match *self {
+            NotList => 1,
+            Argument => 2,
+            Password => 3,
+            Permission => 4,
+            UnknownCmd => 5,
+
+            NoExist => 50,
+            PlaylistMax => 51,
+            System => 52,
+            PlaylistLoad => 53,
+            UpdateAlready => 54,
+            PlayerSync => 55,
+            Exist => 56,
+
+            Unknown(code) => code,
+        }
+    }
 }
 
 impl FromStr for ErrorCode {
@@ -71,7 +101,7 @@ impl FromStr for ErrorCode {
             55 => Ok(PlayerSync),
             56 => Ok(Exist),
 
-            v => Err(ParseError::BadErrorCode(v)),
+            v => Ok(Unknown(v)),
         }
     }
 }
@@ -93,6 +123,8 @@ impl StdError for ErrorCode {
             UpdateAlready => "already updating",
             PlayerSync => "player syncing",
             Exist => "already exists",
+
+            Unknown(_) => "unknown error",
         }
     }
 }
@@ -114,11 +146,72 @@ pub struct ServerError {
     pub command: String,
     /// detailed error description
     pub detail: String,
+    /// command sent by client, which caused the error (if known)
+    pub context: Option<CommandContext>,
 }
 
 impl fmt::Display for ServerError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{} error (`{}') at {}", self.code, self.detail, self.pos)
+        write!(f, "{} error (`{}') at {}", self.code, self.detail, self.pos)?;
+        if let Some(ref context) = self.context {
+            write!(f, " in `{}'", context)?;
+        }
+        Ok(())
+    }
+}
+
+/// Command sent to server, attached to server errors
+///
+/// Arguments are sanitized: passwords are hidden, control characters are escaped
+/// and long arguments are truncated, so the context is safe to log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandContext {
+    /// command name
+    pub command: String,
+    /// sanitized command arguments
+    pub arguments: Vec<String>,
+}
+
+const MAX_CONTEXT_ARGUMENT_LEN: usize = 64;
+
+impl CommandContext {
+    /// Create command context, sanitizing arguments
+    pub fn new(command: &str, arguments: Vec<String>) -> CommandContext {
+        let arguments = if command == "password" {
+            arguments.into_iter().map(|_| "***".to_owned()).collect()
+        } else {
+            arguments.into_iter().map(|arg| sanitize_argument(&arg)).collect()
+        };
+        CommandContext {
+            command: command.to_owned(),
+            arguments: arguments,
+        }
+    }
+}
+
+fn sanitize_argument(arg: &str) -> String {
+    let mut result = String::with_capacity(arg.len());
+    for (i, c) in arg.chars().enumerate() {
+        if i == MAX_CONTEXT_ARGUMENT_LEN {
+            result.push('…');
+            break;
+        }
+        if c.is_control() {
+            result.extend(c.escape_default());
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+impl fmt::Display for CommandContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.command)?;
+        for arg in &self.arguments {
+            write!(f, " \"{}\"", arg)?;
+        }
+        Ok(())
     }
 }
 
@@ -146,6 +239,7 @@ impl FromStr for ServerError {
                                 pos: pos,
                                 command: command,
                                 detail: detail,
+                                context: None,
                             })
                         } else {
                             Err(ParseError::NoMessage)
@@ -176,11 +270,74 @@ pub enum Error {
     Proto(ProtoError),
     /// server errors (a.k.a. `ACK` responses from server)
     Server(ServerError),
+    /// invalid command arguments, rejected before sending them to server
+    Argument(ArgumentError),
+    /// command is not supported by server (too old or command is not available)
+    Unsupported {
+        /// command name
+        command: String,
+        /// minimal server version supporting the command, if known
+        min_version: Option<Version>,
+    },
+    /// operation cancelled by the caller (e.g. with a progress callback)
+    Cancelled,
 }
 
 /// Shortcut type for MPD results
 pub type Result<T> = result::Result<T, Error>;
 
+impl Error {
+    /// Server error code, if it is a server error
+    pub fn code(&self) -> Option<ErrorCode> {
+        match *self {
+            Error::Server(ref err) => Some(err.code),
+            _ => None,
+        }
+    }
+
+    /// Check if the same request can succeed if repeated later on the same connection
+    ///
+    /// These are interrupted or timed out IO operations, and server errors
+    /// about concurrent database update or player synchronization.
+    pub fn is_retryable(&self) -> bool {
+        match *self {
+            Error::Io(ref err) => {
+                match err.kind() {
+                    IoErrorKind::Interrupted | IoErrorKind::TimedOut | IoErrorKind::WouldBlock => true,
+                    _ => false,
+                }
+            }
+            Error::Server(ServerError { code: ErrorCode::UpdateAlready, .. }) |
+            Error::Server(ServerError { code: ErrorCode::PlayerSync, .. }) => true,
+            _ => false,
+        }
+    }
+
+    /// Check if the connection state is unknown after the error, so it must not be reused
+    ///
+    /// These are IO errors, and parse and protocol errors, which mean the client
+    /// lost sync with the server's replies.
+    pub fn is_fatal(&self) -> bool {
+        match *self {
+            Error::Io(_) | Error::Parse(_) | Error::Proto(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Check if the error is about missing object (song, playlist, sticker etc.)
+    pub fn is_not_found(&self) -> bool {
+        self.code() == Some(ErrorCode::NoExist)
+    }
+
+    /// Check if the error is about insufficient permissions or invalid password
+    pub fn is_permission(&self) -> bool {
+        match self.code() {
+            Some(ErrorCode::Permission) | Some(ErrorCode::Password) => true,
+            _ => false,
+        }
+    }
+}
+
 impl StdError for Error {
     fn cause(&self) -> Option<&StdError> {
         match *self {
@@ -188,6 +345,9 @@ impl StdError for Error {
             Error::Parse(ref err) => Some(err),
             Error::Proto(ref err) => Some(err),
             Error::Server(ref err) => Some(err),
+            Error::Argument(ref err) => Some(err),
+            Error::Unsupported { .. } |
+            Error::Cancelled => None,
         }
     }
     fn description(&self) -> &str {
@@ -196,6 +356,9 @@ impl StdError for Error {
             Error::Parse(ref err) => err.description(),
             Error::Proto(ref err) => err.description(),
             Error::Server(ref err) => err.description(),
+            Error::Argument(ref err) => err.description(),
+            Error::Unsupported { .. } => "unsupported command",
+            Error::Cancelled => "operation cancelled",
         }
     }
 }
@@ -207,6 +370,12 @@ impl fmt::Display for Error {
             Error::Parse(ref err) => err.fmt(f),
             Error::Proto(ref err) => err.fmt(f),
             Error::Server(ref err) => err.fmt(f),
+            Error::Argument(ref err) => err.fmt(f),
+            Error::Unsupported { ref command, min_version: Some(ref version) } => {
+                write!(f, "command `{}' requires MPD {} or newer", command, version)
+            }
+            Error::Unsupported { ref command, min_version: None } => write!(f, "command `{}' is not supported by server", command),
+            Error::Cancelled => f.write_str(self.description()),
         }
     }
 }
@@ -247,6 +416,67 @@ impl From<ServerError> for Error {
         Error::Server(e)
     }
 }
+
+impl From<ArgumentError> for Error {
+    fn from(e: ArgumentError) -> Error {
+        Error::Argument(e)
+    }
+}
+// }}}
+
+// Argument errors {{{
+/// Invalid command arguments
+///
+/// MPD protocol is line based, so an argument with embedded newline
+/// would inject another command. Such arguments are rejected by client
+/// before anything is sent to server. Arguments are kept sanitized
+/// (see `CommandContext`), so errors are safe to log.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgumentError {
+    /// argument contains control character (like newline or NUL)
+    ControlChar(String),
+    /// invalid name of a playlist, channel, tag or sticker
+    BadName(String),
+    /// number out of range of the protocol (like queue position above `u32::MAX`)
+    OutOfRange(String),
+}
+
+impl ArgumentError {
+    /// Create error for argument with control characters
+    pub fn control_char(arg: &str) -> ArgumentError {
+        ArgumentError::ControlChar(sanitize_argument(arg))
+    }
+
+    /// Create error for invalid name
+    pub fn bad_name(name: &str) -> ArgumentError {
+        ArgumentError::BadName(sanitize_argument(name))
+    }
+
+    /// Create error for a number out of range
+    pub fn out_of_range<T: fmt::Display>(value: T) -> ArgumentError {
+        ArgumentError::OutOfRange(value.to_string())
+    }
+}
+
+impl fmt::Display for ArgumentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ArgumentError::ControlChar(ref arg) |
+            ArgumentError::BadName(ref arg) |
+            ArgumentError::OutOfRange(ref arg) => write!(f, "{} (`{}')", self.description(), arg),
+        }
+    }
+}
+
+impl StdError for ArgumentError {
+    fn description(&self) -> &str {
+        match *self {
+            ArgumentError::ControlChar(_) => "control character in argument",
+            ArgumentError::BadName(_) => "invalid name",
+            ArgumentError::OutOfRange(_) => "number out of range",
+        }
+    }
+}
 // }}}
 
 // Parse errors {{{
@@ -293,6 +523,8 @@ pub enum ParseError {
     /// unknown state in state status field
     BadState(String),
     /// unknown error code in `ACK` response
+    ///
+    /// Not used anymore, unknown codes are parsed into `ErrorCode::Unknown`.
     BadErrorCode(usize),
 }
 
@@ -389,3 +621,42 @@ impl StdError for ProtoError {
     }
 }
 // }}}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unknown_error_code() {
+        let err: ServerError = "ACK [60@0] {play} some new error".parse().unwrap();
+        assert_eq!(err.code, ErrorCode::Unknown(60));
+        assert_eq!(err.code.code(), 60);
+        assert_eq!(err.detail, "some new error");
+    }
+
+    #[test]
+    fn context_sanitizing() {
+        let context = CommandContext::new("password", vec!["secret".to_owned()]);
+        assert_eq!(context.arguments, vec!["***"]);
+
+        let context = CommandContext::new("save", vec!["a\nb".to_owned(), "x".repeat(100)]);
+        assert_eq!(context.arguments[0], "a\\nb");
+        assert_eq!(context.arguments[1].chars().count(), MAX_CONTEXT_ARGUMENT_LEN + 1);
+    }
+
+    #[test]
+    fn classification() {
+        let err = Error::Server("ACK [50@0] {load} No such playlist".parse().unwrap());
+        assert!(err.is_not_found());
+        assert!(!err.is_permission());
+        assert!(!err.is_retryable());
+
+        let err = Error::Server("ACK [4@0] {kill} you don't have permission for \"kill\"".parse().unwrap());
+        assert!(err.is_permission());
+
+        let err = Error::Server("ACK [54@0] {update} already updating".parse().unwrap());
+        assert!(err.is_retryable());
+        assert!(!err.is_fatal());
+        assert!(Error::Proto(ProtoError::NotOk).is_fatal());
+    }
+}
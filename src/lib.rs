@@ -35,11 +35,20 @@
 extern crate rustc_serialize;
 extern crate time;
 extern crate bufstream;
+#[macro_use]
+extern crate mpd_derive;
+
+// lets code generated by `mpd-derive` refer to the crate as `::mpd` from inside
+extern crate self as mpd;
+
+#[cfg(test)]
+#[macro_use]
+extern crate quickcheck;
 
-mod macros;
 mod convert;
 pub mod error;
 pub mod version;
+pub mod capabilities;
 pub mod reply;
 pub mod status;
 pub mod song;
@@ -51,19 +60,32 @@ pub mod search;
 pub mod message;
 pub mod idle;
 pub mod mount;
+pub mod picture;
+pub mod raw;
+pub mod proxy;
+pub mod pool;
+pub mod live;
+pub mod clock;
+pub mod update;
 
 mod proto;
 pub mod client;
 
+pub use capabilities::Capabilities;
 pub use client::Client;
 pub use idle::{Idle, Subsystem};
 pub use message::{Channel, Message};
 pub use mount::{Mount, Neighbor};
 pub use output::Output;
-pub use playlist::Playlist;
+pub use picture::Picture;
+pub use playlist::{Playlist, PlaylistLength};
+pub use proto::ToArguments;
+pub use raw::{FromResponse, RawResponse};
+pub use mpd_derive::FromResponse;
 pub use plugin::Plugin;
 pub use search::{Query, Term};
-pub use song::{Id, Song};
-pub use stats::Stats;
-pub use status::{ReplayGain, State, Status};
+pub use song::{Id, QueuePosition, Song, SongRef, Tag, TagMask};
+pub use stats::{Stats, StatsDelta};
+pub use status::{AudioFormat, AudioFormatMask, ReplayGain, SampleFormat, State, Status, StatusChange, StatusDiff};
+pub use update::UpdateJob;
 pub use version::Version;
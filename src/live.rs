@@ -0,0 +1,377 @@
+//! The module defines a "live" client, keeping cached player state up to date
+//!
+//! Interactive applications need one connection for commands, and another one waiting
+//! in `idle` for changes. `LiveClient` owns both: a background thread waits for events
+//! on the idle connection, refreshes cached `Status` (which includes playback options),
+//! current song and outputs, and publishes `Change`s to subscribed channels and callbacks.
+//! Playback position between events is estimated with `PlaybackClock`.
+//!
+//! If the idle connection fails, it's reopened with backoff, and the cached state is
+//! refreshed then, as events could be missed meanwhile.
+//!
+//! Commands run on the other connection with `LiveClient::run()`. The client is a
+//! `Send + Sync` handle, cheap to clone, so it can be used from any thread.
+//! The background thread stops when the last handle is dropped.
+//!
+//! ```rust,no_run
+//! # extern crate mpd;
+//! # fn main() {
+//! use mpd::live::{Change, LiveClient};
+//!
+//! let live = LiveClient::connect("127.0.0.1:6600", None).unwrap();
+//! println!("now playing: {:?}", live.song().and_then(|song| song.title));
+//!
+//! let changes = live.subscribe();
+//! live.run(|client| client.pause(true)).unwrap();
+//! for change in changes {
+//!     match change {
+//!         Change::Status(status) => println!("state: {:?}", status.state),
+//!         Change::Song(song) => println!("now playing: {:?}", song.and_then(|song| song.title)),
+//!         other => println!("{:?}", other),
+//!     }
+//! }
+//! # }
+//! ```
+
+use client::Client;
+use clock::PlaybackClock;
+use error::Result;
+use idle::{Idle, Subsystem};
+use output::Output;
+use song::Song;
+use status::Status;
+use std::cmp;
+use std::net::{Shutdown, SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Subsystems the idle connection waits for
+const SUBSYSTEMS: &'static [Subsystem] = &[Subsystem::Database,
+                                           Subsystem::Update,
+                                           Subsystem::Playlist,
+                                           Subsystem::Queue,
+                                           Subsystem::Player,
+                                           Subsystem::Mixer,
+                                           Subsystem::Output,
+                                           Subsystem::Options,
+                                           Subsystem::Sticker,
+                                           Subsystem::Subscription,
+                                           Subsystem::Message];
+
+/// Subsystems reported as `Change::Status`
+const STATUS_SUBSYSTEMS: &'static [Subsystem] = &[Subsystem::Update,
+                                                  Subsystem::Queue,
+                                                  Subsystem::Player,
+                                                  Subsystem::Mixer,
+                                                  Subsystem::Options];
+
+/// Command connection unused that long (in seconds) is checked with `ping` before use
+const PING_AFTER: u64 = 30;
+
+/// Delay (in ms) before reopening failed idle connection, doubled after every failed attempt
+const RECONNECT_MIN: u64 = 500;
+
+/// Maximal delay (in ms) before reopening failed idle connection
+const RECONNECT_MAX: u64 = 30000;
+
+/// Cached player state
+#[derive(Clone, Debug, PartialEq)]
+pub struct LiveState {
+    /// player status, including playback options
+    pub status: Status,
+    /// playback clock, anchored when the status was received
+    pub clock: PlaybackClock,
+    /// current song
+    pub song: Option<Song>,
+    /// audio outputs
+    pub outputs: Vec<Output>,
+}
+
+/// Change of the player state
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    /// player status changed (playback, volume, options, queue version, database update)
+    Status(Status),
+    /// current song changed
+    Song(Option<Song>),
+    /// outputs changed
+    Outputs(Vec<Output>),
+    /// event of a subsystem without cached state (database, stored playlists, stickers etc.)
+    Event(Subsystem),
+}
+
+/// State shared with the background thread
+struct Shared {
+    state: RwLock<LiveState>,
+    senders: Mutex<Vec<Sender<Change>>>,
+    callbacks: Mutex<Vec<Box<dyn Fn(&Change) + Send>>>,
+    /// idle connection is open
+    alive: AtomicBool,
+    /// last handle was dropped
+    stopped: AtomicBool,
+}
+
+impl Shared {
+    fn publish(&self, change: Change) {
+        for callback in self.callbacks.lock().unwrap().iter() {
+            callback(&change);
+        }
+        self.senders.lock().unwrap().retain(|sender| sender.send(change.clone()).is_ok());
+    }
+}
+
+struct Commands {
+    client: Client,
+    last_used: Instant,
+    broken: bool,
+}
+
+struct Inner {
+    addrs: Vec<SocketAddr>,
+    password: Option<String>,
+    commands: Mutex<Commands>,
+    shared: Arc<Shared>,
+    /// socket of the idle connection, replaced when it's reopened
+    idle_socket: Arc<Mutex<TcpStream>>,
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        // interrupts `idle`, so the background thread stops
+        let socket = self.idle_socket.lock().unwrap();
+        self.shared.stopped.store(true, Ordering::SeqCst);
+        let _ = socket.shutdown(Shutdown::Both);
+    }
+}
+
+/// Client with a background thread keeping player state up to date
+#[derive(Clone)]
+pub struct LiveClient {
+    inner: Arc<Inner>,
+}
+
+/// Connect and log in, returns the client and a handle of its socket
+fn open(addrs: &[SocketAddr], password: Option<&str>) -> Result<(Client, TcpStream)> {
+    let stream = TcpStream::connect(addrs)?;
+    let socket = stream.try_clone()?;
+    let mut client = Client::new(stream)?;
+    if let Some(password) = password {
+        client.login(password)?;
+    }
+    Ok((client, socket))
+}
+
+impl LiveClient {
+    /// Open command and idle connections, logging in with the password, if given
+    pub fn connect<A: ToSocketAddrs>(addr: A, password: Option<&str>) -> Result<LiveClient> {
+        let addrs = addr.to_socket_addrs()?.collect::<Vec<_>>();
+        let (commands, _) = open(&addrs, password)?;
+        let (mut idle, idle_socket) = open(&addrs, password)?;
+
+        let status = idle.status()?;
+        let state = LiveState {
+            clock: PlaybackClock::new(&status),
+            status: status,
+            song: idle.currentsong()?,
+            outputs: idle.outputs()?,
+        };
+        let shared = Arc::new(Shared {
+            state: RwLock::new(state),
+            senders: Mutex::new(Vec::new()),
+            callbacks: Mutex::new(Vec::new()),
+            alive: AtomicBool::new(true),
+            stopped: AtomicBool::new(false),
+        });
+        let idle_socket = Arc::new(Mutex::new(idle_socket));
+
+        let watched = shared.clone();
+        let (watched_addrs, watched_password, watched_socket) = (addrs.clone(), password.map(ToOwned::to_owned), idle_socket.clone());
+        thread::spawn(move || {
+            watch(idle,
+                  &watched,
+                  &watched_addrs,
+                  watched_password.as_ref().map(|p| &**p),
+                  &watched_socket)
+        });
+
+        Ok(LiveClient {
+            inner: Arc::new(Inner {
+                addrs: addrs,
+                password: password.map(ToOwned::to_owned),
+                commands: Mutex::new(Commands {
+                    client: commands,
+                    last_used: Instant::now(),
+                    broken: false,
+                }),
+                shared: shared,
+                idle_socket: idle_socket,
+            }),
+        })
+    }
+
+    /// Run commands on the command connection
+    ///
+    /// Commands from other threads wait until `f` returns. Connection unused for a while
+    /// is checked with `ping` first, and it's reopened if server closed it, or if the
+    /// previous commands failed with fatal error (see `Error::is_fatal()`).
+    pub fn run<T, F>(&self, f: F) -> Result<T>
+        where F: FnOnce(&mut Client) -> Result<T>
+    {
+        let mut commands = self.inner.commands.lock().unwrap();
+        if commands.broken ||
+           (commands.last_used.elapsed() >= Duration::from_secs(PING_AFTER) && commands.client.ping().is_err()) {
+            commands.client = open(&self.inner.addrs, self.inner.password.as_ref().map(|p| &**p))?.0;
+            commands.broken = false;
+        }
+
+        let result = f(&mut commands.client);
+        commands.last_used = Instant::now();
+        if let Err(ref e) = result {
+            commands.broken = e.is_fatal();
+        }
+        result
+    }
+
+    /// Cached player state
+    pub fn state(&self) -> LiveState {
+        self.inner.shared.state.read().unwrap().clone()
+    }
+
+    /// Cached player status
+    pub fn status(&self) -> Status {
+        self.inner.shared.state.read().unwrap().status.clone()
+    }
+
+    /// Playback clock, estimating current song position
+    pub fn clock(&self) -> PlaybackClock {
+        self.inner.shared.state.read().unwrap().clock
+    }
+
+    /// Cached current song
+    pub fn song(&self) -> Option<Song> {
+        self.inner.shared.state.read().unwrap().song.clone()
+    }
+
+    /// Cached outputs
+    pub fn outputs(&self) -> Vec<Output> {
+        self.inner.shared.state.read().unwrap().outputs.clone()
+    }
+
+    /// Subscribe to changes
+    ///
+    /// The channel is closed when the last handle is dropped.
+    pub fn subscribe(&self) -> Receiver<Change> {
+        let (sender, receiver) = channel();
+        self.inner.shared.senders.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// Call a function for every change
+    ///
+    /// Callbacks run in the background thread, after the cached state is updated,
+    /// and must not register other callbacks.
+    pub fn on_change<F>(&self, callback: F)
+        where F: Fn(&Change) + Send + 'static
+    {
+        self.inner.shared.callbacks.lock().unwrap().push(Box::new(callback));
+    }
+
+    /// Check if the idle connection is open
+    ///
+    /// While it's being reopened after an error, cached state may be out of date.
+    pub fn is_alive(&self) -> bool {
+        self.inner.shared.alive.load(Ordering::SeqCst)
+    }
+}
+
+fn watch(mut client: Client, shared: &Shared, addrs: &[SocketAddr], password: Option<&str>, socket: &Mutex<TcpStream>) {
+    loop {
+        while let Ok(subsystems) = client.wait(SUBSYSTEMS) {
+            if refresh(&mut client, shared, &subsystems).is_err() {
+                break;
+            }
+        }
+
+        shared.alive.store(false, Ordering::SeqCst);
+        match reconnect(shared, addrs, password, socket) {
+            Some(reopened) => client = reopened,
+            None => break,
+        }
+        shared.alive.store(true, Ordering::SeqCst);
+    }
+    shared.senders.lock().unwrap().clear();
+}
+
+/// Reopen idle connection with backoff, and refresh all cached state
+///
+/// Returns `None` if the last handle was dropped meanwhile.
+fn reconnect(shared: &Shared, addrs: &[SocketAddr], password: Option<&str>, socket: &Mutex<TcpStream>) -> Option<Client> {
+    let mut delay = RECONNECT_MIN;
+    while !shared.stopped.load(Ordering::SeqCst) {
+        thread::sleep(Duration::from_millis(delay));
+        delay = cmp::min(delay * 2, RECONNECT_MAX);
+        if shared.stopped.load(Ordering::SeqCst) {
+            break;
+        }
+
+        let (mut client, reopened) = match open(addrs, password) {
+            Ok(opened) => opened,
+            Err(_) => continue,
+        };
+        {
+            let mut socket = socket.lock().unwrap();
+            if shared.stopped.load(Ordering::SeqCst) {
+                break;
+            }
+            *socket = reopened;
+        }
+        // player and output events refresh everything, without publishing other events
+        if refresh(&mut client, shared, &[Subsystem::Player, Subsystem::Output]).is_ok() {
+            return Some(client);
+        }
+    }
+    None
+}
+
+/// Update cached state after events, and publish changes
+fn refresh(client: &mut Client, shared: &Shared, subsystems: &[Subsystem]) -> Result<()> {
+    let any = |list: &[Subsystem]| subsystems.iter().any(|subsystem| list.contains(subsystem));
+    let status = if any(STATUS_SUBSYSTEMS) { Some(client.status()?) } else { None };
+    let song = if any(&[Subsystem::Player, Subsystem::Queue]) { Some(client.currentsong()?) } else { None };
+    let outputs = if any(&[Subsystem::Output]) { Some(client.outputs()?) } else { None };
+
+    let mut changes = Vec::new();
+    {
+        let mut state = shared.state.write().unwrap();
+        if let Some(status) = status {
+            state.clock.reset(&status);
+            if status != state.status {
+                state.status = status.clone();
+                changes.push(Change::Status(status));
+            }
+        }
+        if let Some(song) = song {
+            if song != state.song {
+                state.song = song.clone();
+                changes.push(Change::Song(song));
+            }
+        }
+        if let Some(outputs) = outputs {
+            if outputs != state.outputs {
+                state.outputs = outputs.clone();
+                changes.push(Change::Outputs(outputs));
+            }
+        }
+    }
+    changes.extend(subsystems.iter()
+        .filter(|&subsystem| *subsystem != Subsystem::Output && !STATUS_SUBSYSTEMS.contains(subsystem))
+        .map(|&subsystem| Change::Event(subsystem)));
+
+    for change in changes {
+        shared.publish(change);
+    }
+    Ok(())
+}
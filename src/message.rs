@@ -8,15 +8,14 @@
 //! Also client can get asynchronous notifications about new messages from subscribed
 //! channels with `idle` command, by waiting for `message` subsystem events.
 
-use convert::FromMap;
+use error::ArgumentError;
+use proto::ToArguments;
 
-use error::{Error, ProtoError};
-
-use std::collections::BTreeMap;
 use std::fmt;
+use std::result::Result as StdResult;
 
 /// Message
-#[derive(Debug, PartialEq, Clone, RustcEncodable)]
+#[derive(Debug, PartialEq, Clone, RustcEncodable, FromResponse)]
 pub struct Message {
     /// channel
     pub channel: Channel,
@@ -24,19 +23,6 @@ pub struct Message {
     pub message: String,
 }
 
-impl FromMap for Message {
-    fn from_map(map: BTreeMap<String, String>) -> Result<Message, Error> {
-        Ok(Message {
-            channel: Channel(try!(map.get("channel")
-                .map(|v| v.to_owned())
-                .ok_or(Error::Proto(ProtoError::NoField("channel"))))),
-            message: try!(map.get("message")
-                .map(|v| v.to_owned())
-                .ok_or(Error::Proto(ProtoError::NoField("message")))),
-        })
-    }
-}
-
 /// Channel
 #[derive(Debug, PartialEq, PartialOrd, Clone, RustcEncodable)]
 pub struct Channel(String);
@@ -60,6 +46,7 @@ impl Channel {
     /// Create channel with arbitrary name, bypassing name validity checks
     ///
     /// Not recommened! Use `new()` method above instead.
+    /// Invalid names are still rejected by client when sending commands.
     pub unsafe fn new_unchecked(name: String) -> Channel {
         Channel(name)
     }
@@ -75,3 +62,19 @@ impl Channel {
         })
     }
 }
+
+impl ToArguments for Channel {
+    fn to_arguments<F, E>(&self, f: &mut F) -> StdResult<(), E>
+        where F: FnMut(&str) -> StdResult<(), E>
+    {
+        f(&self.0)
+    }
+
+    fn validate(&self) -> StdResult<(), ArgumentError> {
+        if Channel::is_valid_name(&self.0) {
+            Ok(())
+        } else {
+            Err(ArgumentError::bad_name(&self.0))
+        }
+    }
+}
@@ -9,51 +9,22 @@
 //! Possible, but inactive, mounts are named "neighbors" and can be
 //! listed with `neighbors()` method.
 
-use convert::FromMap;
-
-use error::{Error, ProtoError};
-use std::collections::BTreeMap;
-
 /// Mount point
-#[derive(Clone, Debug, PartialEq, RustcEncodable)]
+#[derive(Clone, Debug, PartialEq, RustcEncodable, FromResponse)]
 pub struct Mount {
     /// mount point name
+    #[mpd(key = "mount")]
     pub name: String,
     /// mount storage URI
     pub storage: String,
 }
 
-impl FromMap for Mount {
-    fn from_map(map: BTreeMap<String, String>) -> Result<Mount, Error> {
-        Ok(Mount {
-            name: try!(map.get("mount")
-                .map(|s| s.to_owned())
-                .ok_or(Error::Proto(ProtoError::NoField("mount")))),
-            storage: try!(map.get("storage")
-                .map(|s| s.to_owned())
-                .ok_or(Error::Proto(ProtoError::NoField("storage")))),
-        })
-    }
-}
-
 /// Neighbor
-#[derive(Clone, Debug, PartialEq, RustcEncodable)]
+#[derive(Clone, Debug, PartialEq, RustcEncodable, FromResponse)]
 pub struct Neighbor {
     /// neighbor name
     pub name: String,
     /// neighbor storage URI
+    #[mpd(key = "neighbor")]
     pub storage: String,
 }
-
-impl FromMap for Neighbor {
-    fn from_map(map: BTreeMap<String, String>) -> Result<Neighbor, Error> {
-        Ok(Neighbor {
-            name: try!(map.get("name")
-                .map(|s| s.to_owned())
-                .ok_or(Error::Proto(ProtoError::NoField("name")))),
-            storage: try!(map.get("neighbor")
-                .map(|s| s.to_owned())
-                .ok_or(Error::Proto(ProtoError::NoField("neighbor")))),
-        })
-    }
-}
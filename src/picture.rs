@@ -0,0 +1,26 @@
+//! The module defines data structures for pictures embedded into songs or stored alongside them
+//!
+//! MPD sends pictures (`albumart` and `readpicture` commands) in chunks,
+//! each chunk is a `binary: <size>` pair followed by raw data. Chunk size is limited
+//! by `binarylimit` setting of the connection (8192 bytes by default), so large
+//! pictures can take many round trips over slow links. `Client` raises the limit
+//! for large pictures up to `DEFAULT_AUTO_BINARY_LIMIT` on its own, see
+//! `Client::set_binary_limit()` and `Client::set_auto_binary_limit()` to tune it.
+
+/// Picture data
+#[derive(Debug, Clone, PartialEq)]
+pub struct Picture {
+    /// raw picture data
+    pub data: Vec<u8>,
+    /// picture MIME-type, if known (only `readpicture` reports it)
+    pub mime_type: Option<String>,
+}
+
+/// Default chunk size for binary replies
+pub const DEFAULT_BINARY_LIMIT: usize = 8192;
+
+/// Minimal chunk size for binary replies accepted by MPD
+pub const MIN_BINARY_LIMIT: usize = 64;
+
+/// Default maximal chunk size the client raises binary limit to automatically
+pub const DEFAULT_AUTO_BINARY_LIMIT: usize = 256 * 1024;
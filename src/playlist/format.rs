@@ -0,0 +1,552 @@
+//! The module converts playlists to and from playlist file formats
+//!
+//! Songs (like the queue, or a stored playlist from `Client::playlist()`) can be
+//! exported to M3U, extended M3U, PLS and XSPF files with `export()`.
+//!
+//! Playlist files are parsed into `Entry` lists with `parse()`, and entries are
+//! resolved against the database with `resolve()`: by exact path first,
+//! then by a path suffix (for absolute paths from other players), then by
+//! title and artist tags. Resolved songs can be saved into a stored playlist
+//! with `Resolved::save()`, entries which can't be found are reported back.
+//!
+//! ```rust,no_run
+//! # extern crate mpd;
+//! # use std::fs::File;
+//! # use std::io::Read;
+//! use mpd::playlist::format::{self, Format};
+//!
+//! # fn main() {
+//! let mut conn = mpd::Client::connect("127.0.0.1:6600").unwrap();
+//!
+//! let queue = conn.queue().unwrap();
+//! format::export(Format::Xspf, &queue, File::create("queue.xspf").unwrap()).unwrap();
+//!
+//! let mut text = String::new();
+//! File::open("mix.m3u").unwrap().read_to_string(&mut text).unwrap();
+//! let entries = format::parse(format::detect(&text), &text).unwrap();
+//! let resolved = format::resolve(&mut conn, entries).unwrap();
+//! resolved.save(&mut conn, "mix").unwrap();
+//! println!("not found: {:?}", resolved.unresolved);
+//! # }
+//! ```
+
+use client::Client;
+use error::{Error, ParseError, Result};
+use search::{Query, Term};
+use song::Song;
+use std::collections::BTreeMap;
+use std::fmt::Write as FmtWrite;
+use std::io::{self, Read, Write};
+use time::Duration;
+
+/// Playlist file format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// plain M3U, one location per line
+    M3u,
+    /// extended M3U, with `#EXTINF` lines for durations and titles
+    ExtM3u,
+    /// PLS (INI-like) format
+    Pls,
+    /// XML shareable playlist format
+    Xspf,
+}
+
+impl Format {
+    /// Guess format from file extension (case insensitive, without a dot)
+    ///
+    /// `m3u` files are assumed to be extended M3U, as it's compatible with plain M3U.
+    pub fn from_extension(ext: &str) -> Option<Format> {
+        match &*ext.to_lowercase() {
+            "m3u" | "m3u8" => Some(Format::ExtM3u),
+            "pls" => Some(Format::Pls),
+            "xspf" => Some(Format::Xspf),
+            _ => None,
+        }
+    }
+
+    /// Usual file extension (without a dot)
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            Format::M3u | Format::ExtM3u => "m3u",
+            Format::Pls => "pls",
+            Format::Xspf => "xspf",
+        }
+    }
+}
+
+/// Guess format from playlist file contents
+pub fn detect(input: &str) -> Format {
+    let start = input.trim_start_matches('\u{feff}').trim_start();
+    if start.starts_with("#EXTM3U") {
+        Format::ExtM3u
+    } else if start.get(..10).map_or(false, |s| s.eq_ignore_ascii_case("[playlist]")) {
+        Format::Pls
+    } else if start.starts_with("<?xml") || start.starts_with("<playlist") {
+        Format::Xspf
+    } else {
+        Format::M3u
+    }
+}
+
+/// Playlist file entry
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Entry {
+    /// song path (relative to music directory, or absolute) or URL
+    pub location: String,
+    /// title
+    pub title: Option<String>,
+    /// artist
+    pub artist: Option<String>,
+    /// album
+    pub album: Option<String>,
+    /// duration
+    pub duration: Option<Duration>,
+}
+
+impl<'a> From<&'a Song> for Entry {
+    fn from(song: &'a Song) -> Entry {
+        Entry {
+            location: song.file.clone(),
+            title: song.title.clone().or_else(|| song.name.clone()),
+            artist: song.tags.get("Artist").cloned(),
+            album: song.tags.get("Album").cloned(),
+            duration: song.duration,
+        }
+    }
+}
+
+impl Entry {
+    /// Display title, like `Artist - Title` (used in M3U and PLS formats)
+    fn display_title(&self) -> Option<String> {
+        match (self.artist.as_ref(), self.title.as_ref()) {
+            (Some(artist), Some(title)) => Some(format!("{} - {}", artist, title)),
+            (None, Some(title)) => Some(title.clone()),
+            _ => None,
+        }
+    }
+
+    /// Set title and artist from a display title, like `Artist - Title`
+    fn set_display_title(&mut self, title: &str) {
+        let title = title.trim();
+        if title.is_empty() {
+            return;
+        }
+        match title.find(" - ") {
+            Some(pos) => {
+                self.artist = Some(title[..pos].to_owned());
+                self.title = Some(title[pos + 3..].to_owned());
+            }
+            None => self.title = Some(title.to_owned()),
+        }
+    }
+}
+
+// Export {{{
+/// Write songs as a playlist file
+pub fn export<W: Write>(format: Format, songs: &[Song], out: W) -> io::Result<()> {
+    write(format, &songs.iter().map(Entry::from).collect::<Vec<_>>(), out)
+}
+
+/// Write entries as a playlist file
+pub fn write<W: Write>(format: Format, entries: &[Entry], mut out: W) -> io::Result<()> {
+    out.write_all(to_string(format, entries).as_bytes())
+}
+
+/// Format entries as a playlist file
+pub fn to_string(format: Format, entries: &[Entry]) -> String {
+    let mut out = String::new();
+    match format {
+        Format::M3u => {
+            for entry in entries {
+                let _ = writeln!(out, "{}", entry.location);
+            }
+        }
+        Format::ExtM3u => {
+            out.push_str("#EXTM3U\n");
+            for entry in entries {
+                let _ = writeln!(out,
+                                 "#EXTINF:{},{}\n{}",
+                                 entry.duration.map_or(-1, |d| d.num_seconds()),
+                                 entry.display_title().unwrap_or_default(),
+                                 entry.location);
+            }
+        }
+        Format::Pls => {
+            out.push_str("[playlist]\n");
+            for (n, entry) in entries.iter().enumerate() {
+                let _ = writeln!(out, "File{}={}", n + 1, entry.location);
+                if let Some(title) = entry.display_title() {
+                    let _ = writeln!(out, "Title{}={}", n + 1, title);
+                }
+                let _ = writeln!(out, "Length{}={}", n + 1, entry.duration.map_or(-1, |d| d.num_seconds()));
+            }
+            let _ = writeln!(out, "NumberOfEntries={}\nVersion=2", entries.len());
+        }
+        Format::Xspf => {
+            out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+            out.push_str("<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n  <trackList>\n");
+            for entry in entries {
+                out.push_str("    <track>\n");
+                let _ = writeln!(out, "      <location>{}</location>", escape(&encode_uri(&entry.location)));
+                let fields = [("title", &entry.title), ("creator", &entry.artist), ("album", &entry.album)];
+                for &(tag, value) in &fields {
+                    if let Some(ref value) = *value {
+                        let _ = writeln!(out, "      <{0}>{1}</{0}>", tag, escape(value));
+                    }
+                }
+                if let Some(duration) = entry.duration {
+                    let _ = writeln!(out, "      <duration>{}</duration>", duration.num_milliseconds());
+                }
+                out.push_str("    </track>\n");
+            }
+            out.push_str("  </trackList>\n</playlist>\n");
+        }
+    }
+    out
+}
+// }}}
+
+// Import {{{
+/// Read and parse a playlist file, guessing its format from contents
+pub fn read<R: Read>(mut input: R) -> Result<Vec<Entry>> {
+    let mut text = String::new();
+    input.read_to_string(&mut text)?;
+    parse(detect(&text), &text)
+}
+
+/// Parse playlist file
+pub fn parse(format: Format, input: &str) -> Result<Vec<Entry>> {
+    let input = input.trim_start_matches('\u{feff}');
+    match format {
+        Format::M3u | Format::ExtM3u => Ok(parse_m3u(input)),
+        Format::Pls => parse_pls(input),
+        Format::Xspf => parse_xspf(input),
+    }
+}
+
+fn parse_m3u(input: &str) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut entry = Entry::default();
+
+    for line in input.lines().map(str::trim) {
+        if line.starts_with("#EXTINF:") {
+            let info = &line[8..];
+            let (duration, title) = match info.find(',') {
+                Some(pos) => (&info[..pos], &info[pos + 1..]),
+                None => (info, ""),
+            };
+            // duration can be followed by attributes, like `#EXTINF:241 tvg-id="x",Title`
+            entry.duration = duration.split_whitespace()
+                .next()
+                .and_then(|d| d.parse::<f64>().ok())
+                .filter(|&d| d >= 0.0)
+                .map(|d| Duration::milliseconds((d * 1000.0) as i64));
+            entry.set_display_title(title);
+        } else if !line.is_empty() && !line.starts_with('#') {
+            entry.location = line.to_owned();
+            entries.push(entry);
+            entry = Entry::default();
+        }
+    }
+
+    entries
+}
+
+fn parse_pls(input: &str) -> Result<Vec<Entry>> {
+    let mut entries: BTreeMap<u32, Entry> = BTreeMap::new();
+
+    for line in input.lines().map(str::trim) {
+        let (key, value) = match line.find('=') {
+            Some(pos) => (line[..pos].trim().to_lowercase(), line[pos + 1..].trim()),
+            None => continue,
+        };
+        let (field, number) = match key.find(|c: char| c.is_ascii_digit()) {
+            Some(pos) => (&key[..pos], &key[pos..]),
+            None => continue,
+        };
+        let number = number.parse().map_err(|_| bad_value("PLS entry number", &key))?;
+
+        match field {
+            "file" => entries.entry(number).or_insert_with(Entry::default).location = value.to_owned(),
+            "title" => entries.entry(number).or_insert_with(Entry::default).set_display_title(value),
+            "length" => {
+                entries.entry(number).or_insert_with(Entry::default).duration =
+                    value.parse::<i64>().ok().filter(|&d| d >= 0).map(Duration::seconds)
+            }
+            _ => (),
+        }
+    }
+
+    Ok(entries.into_iter().map(|(_, e)| e).filter(|e| !e.location.is_empty()).collect())
+}
+
+fn parse_xspf(input: &str) -> Result<Vec<Entry>> {
+    if !input.contains("<playlist") {
+        return Err(bad_value("XSPF playlist", "no <playlist> element"));
+    }
+
+    let mut entries = Vec::new();
+    let mut rest = input;
+    while let Some(start) = rest.find("<track>") {
+        let end = rest[start..].find("</track>").ok_or_else(|| bad_value("XSPF playlist", "unclosed <track>"))?;
+        let track = &rest[start + 7..start + end];
+        rest = &rest[start + end + 8..];
+
+        let location = match element(track, "location") {
+            Some(location) => location,
+            None => continue,
+        };
+        entries.push(Entry {
+            location: decode_uri(location.trim_start_matches("file://")),
+            title: element(track, "title"),
+            artist: element(track, "creator"),
+            album: element(track, "album"),
+            duration: element(track, "duration").and_then(|d| d.parse().ok()).map(Duration::milliseconds),
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Get unescaped text of the first element with given tag
+fn element(xml: &str, tag: &str) -> Option<String> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let start = xml.find(&open)? + open.len();
+    let end = xml[start..].find(&close)? + start;
+    Some(unescape(xml[start..end].trim()))
+}
+
+fn bad_value(what: &str, value: &str) -> Error {
+    Error::Parse(ParseError::BadValue(format!("{}: {}", what, value)))
+}
+// }}}
+
+// Resolving {{{
+/// Playlist entries resolved against the database
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Resolved {
+    /// found songs (and URLs, which are kept as is)
+    pub songs: Vec<Song>,
+    /// entries not found in the database
+    pub unresolved: Vec<Entry>,
+}
+
+impl Resolved {
+    /// Append resolved songs to a stored playlist (it's created if it doesn't exist)
+    pub fn save<S: Read + Write>(&self, client: &mut Client<S>, name: &str) -> Result<()> {
+        for song in &self.songs {
+            client.pl_push(name, song)?;
+        }
+        Ok(())
+    }
+}
+
+/// Resolve playlist entries against the database
+///
+/// Entries are looked up by exact path first, then by file name, keeping songs
+/// which path is a suffix of entry location (like `/home/user/Music/Artist/song.flac`
+/// for `Artist/song.flac`), then by title and artist tags, preferring songs
+/// with the closest duration. URLs are kept as is.
+pub fn resolve<S: Read + Write>(client: &mut Client<S>, entries: Vec<Entry>) -> Result<Resolved> {
+    let mut resolved = Resolved::default();
+    for entry in entries {
+        match resolve_entry(client, &entry)? {
+            Some(song) => resolved.songs.push(song),
+            None => resolved.unresolved.push(entry),
+        }
+    }
+    Ok(resolved)
+}
+
+fn resolve_entry<S: Read + Write>(client: &mut Client<S>, entry: &Entry) -> Result<Option<Song>> {
+    if entry.location.contains("://") && !entry.location.starts_with("file://") {
+        return Ok(Some(Song {
+            file: entry.location.clone(),
+            title: entry.title.clone(),
+            duration: entry.duration,
+            ..Song::default()
+        }));
+    }
+
+    let path = entry.location.trim_start_matches("file://").replace('\\', "/");
+    if !path.is_empty() {
+        let mut query = Query::new();
+        if let Some(song) = client.find(query.and(Term::File, &*path), None)?.into_iter().next() {
+            return Ok(Some(song));
+        }
+
+        let name = path.rsplit('/').next().unwrap_or(&path);
+        if !name.is_empty() {
+            let mut query = Query::new();
+            let songs = client.search(query.and(Term::File, name), None)?;
+            if let Some(song) = songs.into_iter().find(|s| path.ends_with(&format!("/{}", s.file))) {
+                return Ok(Some(song));
+            }
+        }
+    }
+
+    if let Some(ref title) = entry.title {
+        let mut query = Query::new();
+        let songs = match entry.artist {
+            Some(ref artist) => {
+                client.find(query.and(Term::Tag("Title".into()), &**title).and(Term::Tag("Artist".into()), &**artist),
+                            None)?
+            }
+            None => client.find(query.and(Term::Tag("Title".into()), &**title), None)?,
+        };
+        return Ok(closest(songs, entry.duration));
+    }
+
+    Ok(None)
+}
+
+/// Pick a song with duration closest to the given one
+fn closest(songs: Vec<Song>, duration: Option<Duration>) -> Option<Song> {
+    match duration {
+        Some(duration) => {
+            songs.into_iter().min_by_key(|s| s.duration.map_or(i64::max_value(), |d| (d - duration).num_milliseconds().abs()))
+        }
+        None => songs.into_iter().next(),
+    }
+}
+// }}}
+
+// Escaping {{{
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn unescape(s: &str) -> String {
+    if s.starts_with("<![CDATA[") && s.ends_with("]]>") {
+        return s[9..s.len() - 3].to_owned();
+    }
+
+    let mut result = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(pos) = rest.find('&') {
+        result.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        let end = match rest.find(';') {
+            Some(end) => end,
+            None => break,
+        };
+        let entity = &rest[1..end];
+        let c = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ if entity.starts_with("#x") => u32::from_str_radix(&entity[2..], 16).ok().and_then(::std::char::from_u32),
+            _ if entity.starts_with('#') => entity[1..].parse().ok().and_then(::std::char::from_u32),
+            _ => None,
+        };
+        match c {
+            Some(c) => {
+                result.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Percent-encode a path to be used as URI, URLs are kept as is
+fn encode_uri(location: &str) -> String {
+    if location.contains("://") {
+        return location.to_owned();
+    }
+    let mut result = String::with_capacity(location.len());
+    for b in location.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => result.push(b as char),
+            _ => {
+                let _ = write!(result, "%{:02X}", b);
+            }
+        }
+    }
+    result
+}
+
+fn decode_uri(uri: &str) -> String {
+    if uri.contains("://") {
+        return uri.to_owned();
+    }
+    let bytes = uri.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = |b: u8| (b as char).to_digit(16);
+            if let (Some(h), Some(l)) = (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                result.push((h * 16 + l) as u8);
+                i += 3;
+                continue;
+            }
+        }
+        result.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&result).into_owned()
+}
+// }}}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entries() -> Vec<Entry> {
+        vec![Entry {
+                 location: "Air/Moon Safari/01 La femme d'argent.flac".to_owned(),
+                 title: Some("La femme d'argent".to_owned()),
+                 artist: Some("Air".to_owned()),
+                 album: Some("Moon Safari & more".to_owned()),
+                 duration: Some(Duration::seconds(429)),
+             },
+             Entry {
+                 location: "http://example.com/stream".to_owned(),
+                 ..Entry::default()
+             }]
+    }
+
+    #[test]
+    fn roundtrip() {
+        for &format in &[Format::ExtM3u, Format::Pls, Format::Xspf] {
+            let text = to_string(format, &entries());
+            assert_eq!(detect(&text), format);
+            let parsed = parse(format, &text).unwrap();
+            assert_eq!(parsed.len(), 2, "{:?}", format);
+            assert_eq!(parsed[0].location, entries()[0].location, "{:?}", format);
+            assert_eq!(parsed[0].title, entries()[0].title, "{:?}", format);
+            assert_eq!(parsed[0].artist, entries()[0].artist, "{:?}", format);
+            assert_eq!(parsed[0].duration, entries()[0].duration, "{:?}", format);
+            assert_eq!(parsed[1].location, "http://example.com/stream");
+        }
+
+        let parsed = parse(Format::Xspf, &to_string(Format::Xspf, &entries())).unwrap();
+        assert_eq!(parsed[0].album, entries()[0].album);
+        assert!(to_string(Format::Xspf, &entries()).contains("Air/Moon%20Safari/01%20La%20femme%20d%27argent.flac"));
+    }
+
+    #[test]
+    fn plain_m3u() {
+        let parsed = parse(Format::M3u, "# comment\r\n/music/a.mp3\r\n\r\nb.ogg\r\n").unwrap();
+        assert_eq!(parsed.iter().map(|e| &*e.location).collect::<Vec<_>>(), vec!["/music/a.mp3", "b.ogg"]);
+        assert_eq!(detect("/music/a.mp3\n"), Format::M3u);
+        assert_eq!(detect("aéééééé/x.mp3\n"), Format::M3u);
+        assert_eq!(detect("[PlayList]\nFile1=a.mp3\n"), Format::Pls);
+    }
+
+    #[test]
+    fn xml_entities() {
+        assert_eq!(unescape("a &amp; b &#233;&#x41; &bogus; &"), "a & b éA &bogus; &");
+        assert_eq!(decode_uri("a%20b%2"), "a b%2");
+    }
+}
@@ -0,0 +1,25 @@
+//! The module defines playlist data structures
+
+pub mod format;
+
+use time::{Duration, Tm};
+
+/// Playlist
+#[derive(Clone, Debug, PartialEq, FromResponse)]
+pub struct Playlist {
+    /// name
+    #[mpd(key = "playlist")]
+    pub name: String,
+    /// last modified (some playlist plugins don't report it)
+    #[mpd(key = "Last-Modified")]
+    pub last_mod: Option<Tm>,
+}
+
+/// Playlist length, as reported by `playlistlength` command
+#[derive(Clone, Copy, Debug, PartialEq, FromResponse)]
+pub struct PlaylistLength {
+    /// number of songs
+    pub songs: u32,
+    /// total duration of songs
+    pub playtime: Duration,
+}
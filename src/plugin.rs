@@ -15,31 +15,23 @@ pub struct Plugin {
 }
 
 impl FromIter for Vec<Plugin> {
-    fn from_iter<I: Iterator<Item = Result<(String, String), Error>>>(iter: I) -> Result<Self, Error> {
-        let mut result = Vec::new();
-        let mut plugin: Option<Plugin> = None;
-        for reply in iter {
-            let (a, b) = try!(reply);
-            match &*a {
-                "plugin" => {
-                    plugin.map(|p| result.push(p));
-
-                    plugin = Some(Plugin {
-                        name: b,
-                        suffixes: Vec::new(),
-                        mime_types: Vec::new(),
-                    });
-                }
-                "mime_type" => {
-                    plugin.as_mut().map(|p| p.mime_types.push(b));
-                }
-                "suffix" => {
-                    plugin.as_mut().map(|p| p.suffixes.push(b));
-                }
-                _ => unreachable!(),
+    fn feed(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        match key {
+            "plugin" => {
+                self.push(Plugin {
+                    name: value.to_owned(),
+                    suffixes: Vec::new(),
+                    mime_types: Vec::new(),
+                })
+            }
+            "mime_type" => {
+                self.last_mut().map(|p| p.mime_types.push(value.to_owned()));
+            }
+            "suffix" => {
+                self.last_mut().map(|p| p.suffixes.push(value.to_owned()));
             }
+            _ => (),
         }
-        plugin.map(|p| result.push(p));
-        Ok(result)
+        Ok(())
     }
 }
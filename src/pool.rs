@@ -0,0 +1,251 @@
+//! The module defines a pool of client connections for multi-threaded services
+//!
+//! `Client` needs `&mut` access for every command, so it can't be shared between
+//! threads. `Pool` lends connections out instead: `Pool::get()` returns an idle
+//! connection (checked with `ping` first) or opens a new one (logging in if the pool
+//! has a password), and waits for a connection to be returned if the pool is full.
+//! Connections unused for longer than idle timeout are closed, before server closes
+//! them on its own `connection_timeout`.
+//!
+//! A connection goes back to the pool when `Pooled` guard is dropped. After IO, parse
+//! or protocol errors connection state is unknown, so run commands with `Pooled::run()`,
+//! which discards the connection after such errors (see `Error::is_fatal()`),
+//! or call `Pooled::discard()`.
+//!
+//! ```rust,no_run
+//! # extern crate mpd;
+//! # fn main() {
+//! use mpd::pool::Pool;
+//! use std::sync::Arc;
+//! use std::thread;
+//!
+//! let pool = Arc::new(Pool::new("127.0.0.1:6600", 4));
+//! let workers: Vec<_> = (0..8)
+//!     .map(|_| {
+//!         let pool = pool.clone();
+//!         thread::spawn(move || {
+//!             let mut conn = pool.get().unwrap();
+//!             conn.run(|client| client.status()).map(|status| status.volume)
+//!         })
+//!     })
+//!     .collect();
+//! for worker in workers {
+//!     println!("{:?}", worker.join().unwrap());
+//! }
+//! # }
+//! ```
+
+use client::Client;
+use error::{Error, Result};
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Default idle timeout (in seconds), half of MPD default `connection_timeout`
+pub const IDLE_TIMEOUT: u64 = 30;
+
+/// Pool of at most `max_size` connections, opened on demand
+#[derive(Debug)]
+pub struct Pool {
+    addr: String,
+    password: Option<String>,
+    max_size: usize,
+    idle_timeout: Option<Duration>,
+    state: Mutex<State>,
+    released: Condvar,
+}
+
+#[derive(Debug)]
+struct State {
+    /// returned connections with the time they were returned, the latest last
+    idle: Vec<(Client, Instant)>,
+    /// connections open, including lent ones
+    open: usize,
+}
+
+impl Pool {
+    /// Create pool of connections to the server address
+    ///
+    /// Panics if `max_size` is 0, as `get()` would wait forever on such pool.
+    pub fn new(addr: &str, max_size: usize) -> Pool {
+        assert!(max_size > 0, "pool max_size must be at least 1");
+        Pool {
+            addr: addr.to_owned(),
+            password: None,
+            max_size: max_size,
+            idle_timeout: Some(Duration::from_secs(IDLE_TIMEOUT)),
+            state: Mutex::new(State {
+                idle: Vec::new(),
+                open: 0,
+            }),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Set password to log in with on connect
+    pub fn set_password(&mut self, password: &str) {
+        self.password = Some(password.to_owned());
+    }
+
+    /// Set how long a connection can stay unused in the pool (`None` to keep it forever)
+    pub fn set_idle_timeout(&mut self, timeout: Option<Duration>) {
+        self.idle_timeout = timeout;
+    }
+
+    /// Server address
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    /// Maximal number of connections
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Number of open connections, including lent ones
+    pub fn size(&self) -> usize {
+        self.state.lock().unwrap().open
+    }
+
+    /// Number of idle connections in the pool
+    pub fn available(&self) -> usize {
+        self.state.lock().unwrap().idle.len()
+    }
+
+    /// Open a new connection, not counted in the pool (e.g. for `idle`)
+    pub fn connect(&self) -> Result<Client> {
+        let mut client = Client::connect(&*self.addr)?;
+        if let Some(ref password) = self.password {
+            client.login(password)?;
+        }
+        Ok(client)
+    }
+
+    /// Take a connection, waiting for one to be returned if the pool is full
+    pub fn get<'a>(&'a self) -> Result<Pooled<'a>> {
+        self.checkout(None)
+    }
+
+    /// Take a connection, waiting at most `timeout` if the pool is full
+    ///
+    /// Fails with `TimedOut` IO error if no connection was returned in time.
+    pub fn get_timeout<'a>(&'a self, timeout: Duration) -> Result<Pooled<'a>> {
+        self.checkout(Some(Instant::now() + timeout))
+    }
+
+    fn checkout<'a>(&'a self, deadline: Option<Instant>) -> Result<Pooled<'a>> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            self.evict(&mut state);
+
+            if let Some((mut client, _)) = state.idle.pop() {
+                drop(state);
+                if client.ping().is_ok() {
+                    return Ok(Pooled::new(self, client));
+                }
+                state = self.state.lock().unwrap();
+                state.open -= 1;
+                continue;
+            }
+
+            if state.open < self.max_size {
+                state.open += 1;
+                drop(state);
+                return match self.connect() {
+                    Ok(client) => Ok(Pooled::new(self, client)),
+                    Err(e) => {
+                        self.closed();
+                        Err(e)
+                    }
+                };
+            }
+
+            state = match deadline {
+                None => self.released.wait(state).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(Error::Io(io::Error::new(io::ErrorKind::TimedOut, "no connection available")));
+                    }
+                    self.released.wait_timeout(state, deadline - now).unwrap().0
+                }
+            };
+        }
+    }
+
+    /// Close connections idle for longer than idle timeout
+    fn evict(&self, state: &mut State) {
+        if let Some(timeout) = self.idle_timeout {
+            let before = state.idle.len();
+            state.idle.retain(|&(_, since)| since.elapsed() < timeout);
+            state.open -= before - state.idle.len();
+        }
+    }
+
+    fn closed(&self) {
+        self.state.lock().unwrap().open -= 1;
+        self.released.notify_one();
+    }
+}
+
+/// Connection lent from the pool, returned back on drop
+#[derive(Debug)]
+pub struct Pooled<'a> {
+    pool: &'a Pool,
+    client: Option<Client>,
+    broken: bool,
+}
+
+impl<'a> Pooled<'a> {
+    fn new(pool: &'a Pool, client: Client) -> Pooled<'a> {
+        Pooled {
+            pool: pool,
+            client: Some(client),
+            broken: false,
+        }
+    }
+
+    /// Run commands on the connection, marking it broken if they fail with fatal error
+    ///
+    /// Broken connection is closed instead of returning it to the pool.
+    pub fn run<T, F>(&mut self, f: F) -> Result<T>
+        where F: FnOnce(&mut Client) -> Result<T>
+    {
+        let result = f(self.client.as_mut().unwrap());
+        if let Err(ref e) = result {
+            self.broken = self.broken || e.is_fatal();
+        }
+        result
+    }
+
+    /// Close the connection instead of returning it to the pool
+    pub fn discard(mut self) {
+        self.broken = true;
+    }
+}
+
+impl<'a> Deref for Pooled<'a> {
+    type Target = Client;
+    fn deref(&self) -> &Client {
+        self.client.as_ref().unwrap()
+    }
+}
+
+impl<'a> DerefMut for Pooled<'a> {
+    fn deref_mut(&mut self) -> &mut Client {
+        self.client.as_mut().unwrap()
+    }
+}
+
+impl<'a> Drop for Pooled<'a> {
+    fn drop(&mut self) {
+        let client = self.client.take().unwrap();
+        if self.broken {
+            self.pool.closed();
+        } else {
+            self.pool.state.lock().unwrap().idle.push((client, Instant::now()));
+            self.pool.released.notify_one();
+        }
+    }
+}
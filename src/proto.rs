@@ -2,34 +2,92 @@
 #![allow(missing_docs)]
 
 use bufstream::BufStream;
-use convert::{FromIter, FromMap};
-use error::{Error, ProtoError, Result, ParseError};
+use raw::FromResponse;
+use error::{ArgumentError, CommandContext, Error, ParseError, ProtoError, Result, ServerError};
 
-use reply::Reply;
+use convert::FromIter;
+use reply::{Reply, parse_pair};
 use std::collections::BTreeMap;
 use std::fmt;
-use std::io::{self, Lines, Read, Write};
+use std::io::{self, BufRead, Read, Write};
 use std::result::Result as StdResult;
 use std::str::FromStr;
 
-pub struct Pairs<I>(pub I);
+/// Reply pairs reader
+///
+/// It reads reply lines into a single reused buffer, so pairs can be
+/// either borrowed from it with `next_pair()` without any allocations,
+/// or iterated as owned strings.
+///
+/// Commands sent with the request are attached to server errors
+/// (`ACK` replies) to tell which command failed.
+pub struct Pairs<R> {
+    reader: R,
+    line: String,
+    context: Vec<CommandContext>,
+    done: bool,
+}
+
+impl<R: BufRead> Pairs<R> {
+    pub fn new(reader: R, context: Vec<CommandContext>) -> Pairs<R> {
+        Pairs {
+            reader: reader,
+            line: String::new(),
+            context: context,
+            done: false,
+        }
+    }
 
-impl<I> Iterator for Pairs<I>
-    where I: Iterator<Item = io::Result<String>>
-{
+    /// Read next pair borrowed from the line buffer, returns `None` at the end of reply
+    pub fn next_pair(&mut self) -> Option<Result<(&str, &str)>> {
+        if self.done {
+            return None;
+        }
+
+        self.line.clear();
+        match self.reader.read_line(&mut self.line) {
+            Ok(0) => {
+                self.done = true;
+                return Some(Err(Error::Io(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed in the middle of reply"))));
+            }
+            Ok(_) => (),
+            Err(e) => {
+                self.done = true;
+                return Some(Err(Error::Io(e)));
+            }
+        }
+
+        let line = self.line.trim_end_matches('\n');
+        if line == "OK" || line == "list_OK" {
+            self.done = true;
+            None
+        } else if line.starts_with("ACK ") {
+            self.done = true;
+            Some(match line.parse::<ServerError>() {
+                Ok(e) => Err(Error::Server(with_context(e, &self.context))),
+                Err(e) => Err(Error::Parse(e)),
+            })
+        } else {
+            Some(parse_pair(line).ok_or(Error::Parse(ParseError::BadPair)))
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for Pairs<R> {
     type Item = Result<(String, String)>;
     fn next(&mut self) -> Option<Result<(String, String)>> {
-        let reply: Option<Result<Reply>> = self.0
-            .next()
-            .map(|v| v.map_err(Error::Io).and_then(|s| s.parse::<Reply>().map_err(Error::Parse)));
-        match reply {
-            Some(Ok(Reply::Pair(a, b))) => Some(Ok((a, b))),
-            None |
-            Some(Ok(Reply::Ok)) => None,
-            Some(Ok(Reply::Ack(e))) => Some(Err(Error::Server(e))),
-            Some(Err(e)) => Some(Err(e)),
-        }
+        self.next_pair().map(|r| r.map(|(a, b)| (a.to_owned(), b.to_owned())))
+    }
+}
+
+/// Attach context of a failed command to server error
+///
+/// Error position points to a command in a command list, or it's 0 for a single command.
+pub fn with_context(mut error: ServerError, commands: &[CommandContext]) -> ServerError {
+    if error.context.is_none() {
+        error.context = commands.get(error.pos as usize).cloned();
     }
+    error
 }
 
 pub struct Maps<'a, I: 'a> {
@@ -40,9 +98,7 @@ pub struct Maps<'a, I: 'a> {
     first: bool,
 }
 
-impl<'a, I> Iterator for Maps<'a, I>
-    where I: Iterator<Item = io::Result<String>>
-{
+impl<'a, I: BufRead> Iterator for Maps<'a, I> {
     type Item = Result<BTreeMap<String, String>>;
     fn next(&mut self) -> Option<Result<BTreeMap<String, String>>> {
         if self.done {
@@ -81,9 +137,7 @@ impl<'a, I> Iterator for Maps<'a, I>
     }
 }
 
-impl<I> Pairs<I>
-    where I: Iterator<Item = io::Result<String>>
-{
+impl<I: BufRead> Pairs<I> {
     pub fn split<'a, 'b: 'a>(&'a mut self, f: &'b str) -> Maps<'a, I> {
         Maps {
             pairs: self,
@@ -101,32 +155,95 @@ pub trait Proto {
     type Stream: Read + Write;
 
     fn read_line(&mut self) -> Result<String>;
-    fn read_pairs(&mut self) -> Pairs<Lines<&mut BufStream<Self::Stream>>>;
+    fn read_pairs(&mut self) -> Pairs<&mut BufStream<Self::Stream>>;
+    /// Read exactly `buf.len()` raw bytes (binary reply data)
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<()>;
 
     fn run_command<I>(&mut self, command: &str, arguments: I) -> Result<()> where I: ToArguments;
 
+    /// Convert server error into `Error`, attaching context of a command which caused it
+    fn server_error(&self, error: ServerError) -> Error {
+        Error::Server(error)
+    }
+
+    /// Call a function for every reply pair, borrowed from the line buffer
+    ///
+    /// If the function fails, the rest of the reply is still read,
+    /// so the connection stays usable, and the first error is returned.
+    fn read_pairs_with<F>(&mut self, mut f: F) -> Result<()>
+        where F: FnMut(&str, &str) -> Result<()>
+    {
+        let mut pairs = self.read_pairs();
+        let mut result = Ok(());
+        while let Some(pair) = pairs.next_pair() {
+            if result.is_ok() {
+                result = pair.and_then(|(a, b)| f(a, b));
+            }
+        }
+        result
+    }
+
     fn read_structs<'a, T>(&'a mut self, key: &'static str) -> Result<Vec<T>>
-        where T: 'a + FromMap
+        where T: 'a + FromResponse
     {
-        self.read_pairs().split(key).map(|v| v.and_then(FromMap::from_map)).collect()
+        let mut result = Vec::new();
+        let mut group: Vec<(String, String)> = Vec::new();
+        self.read_pairs_with(|a, b| {
+            if a == key && !group.is_empty() {
+                result.push(T::from_response(group.drain(..).map(Ok))?);
+            }
+            if a == key || !group.is_empty() {
+                group.push((a.to_owned(), b.to_owned()));
+            }
+            Ok(())
+        })?;
+        if !group.is_empty() {
+            result.push(T::from_response(group.drain(..).map(Ok))?);
+        }
+        Ok(result)
+    }
+
+    /// Read list of structs, feeding them with borrowed pairs directly
+    fn feed_structs<T: FromIter>(&mut self, key: &'static str) -> Result<Vec<T>> {
+        let mut result = Vec::new();
+        let mut current: Option<T> = None;
+        self.read_pairs_with(|a, b| {
+            if a == key {
+                result.extend(current.take());
+                current = Some(T::default());
+            }
+            match current {
+                Some(ref mut value) => value.feed(a, b),
+                None => Ok(()),
+            }
+        })?;
+        result.extend(current);
+        Ok(result)
+    }
+
+    /// Read a struct, feeding it with borrowed pairs directly
+    fn feed_struct<T: FromIter>(&mut self) -> Result<T> {
+        let mut result = T::default();
+        self.read_pairs_with(|a, b| result.feed(a, b))?;
+        Ok(result)
     }
 
     fn read_list(&mut self, key: &'static str) -> Result<Vec<String>> {
-        self.read_pairs()
-            .filter(|r| {
-                r.as_ref()
-                    .map(|&(ref a, _)| *a == key)
-                    .unwrap_or(true)
-            })
-            .map(|r| r.map(|(_, b)| b))
-            .collect()
+        let mut result = Vec::new();
+        self.read_pairs_with(|a, b| {
+            if a == key {
+                result.push(b.to_owned());
+            }
+            Ok(())
+        })?;
+        Ok(result)
     }
 
     fn read_struct<'a, T>(&'a mut self) -> Result<T>
-        where T: 'a + FromIter,
+        where T: 'a + FromResponse,
               Self::Stream: 'a
     {
-        FromIter::from_iter(self.read_pairs())
+        T::from_response(self.read_pairs())
     }
 
     fn drain(&mut self) -> Result<()> {
@@ -145,7 +262,7 @@ pub trait Proto {
 
         match line.parse::<Reply>() {
             Ok(Reply::Ok) => Ok(()),
-            Ok(Reply::Ack(e)) => Err(Error::Server(e)),
+            Ok(Reply::Ack(e)) => Err(self.server_error(e)),
             Ok(_) => Err(Error::Proto(ProtoError::NotOk)),
             Err(e) => Err(From::from(e)),
         }
@@ -157,7 +274,7 @@ pub trait Proto {
         match line.parse::<Reply>() {
             Ok(Reply::Pair(a, b)) => Ok((a, b)),
             Ok(Reply::Ok) => Err(Error::Proto(ProtoError::NotPair)),
-            Ok(Reply::Ack(e)) => Err(Error::Server(e)),
+            Ok(Reply::Ack(e)) => Err(self.server_error(e)),
             Err(e) => Err(Error::Parse(e)),
         }
     }
@@ -177,8 +294,44 @@ pub trait Proto {
 }
 
 
+/// Types which can be sent to MPD as command arguments
+///
+/// It is implemented for strings, numbers, tuples and slices of arguments,
+/// as well as for the crate's types used as arguments (like `Query` or `Channel`),
+/// so any of them can be passed to `Client::raw()`.
 pub trait ToArguments {
+    /// Feed arguments one by one into a callback, they are quoted on sending
     fn to_arguments<F, E>(&self, &mut F) -> StdResult<(), E> where F: FnMut(&str) -> StdResult<(), E>;
+
+    /// Check arguments before sending them to server
+    ///
+    /// Default implementation rejects arguments with control characters,
+    /// types with more specific rules (like names) override it.
+    fn validate(&self) -> StdResult<(), ArgumentError> {
+        self.to_arguments(&mut check_argument)
+    }
+}
+
+/// Reject arguments with control characters
+///
+/// Quoting handles `"` and `\\`, but newline (or any other control character)
+/// can't be escaped in MPD protocol and would inject another command.
+pub fn check_argument(arg: &str) -> StdResult<(), ArgumentError> {
+    if arg.chars().any(char::is_control) {
+        Err(ArgumentError::control_char(arg))
+    } else {
+        Ok(())
+    }
+}
+
+/// Check playlist, sticker or tag name: it can't be empty or contain control characters
+/// and some protocol-specific characters (like `/` for playlists or `=` for stickers)
+pub fn check_name(name: &str, forbidden: &[char]) -> StdResult<(), ArgumentError> {
+    if name.is_empty() || name.chars().any(|c| c.is_control() || forbidden.contains(&c)) {
+        Err(ArgumentError::bad_name(name))
+    } else {
+        Ok(())
+    }
 }
 
 impl ToArguments for () {
@@ -197,6 +350,40 @@ impl<'a> ToArguments for &'a str {
     }
 }
 
+/// Stored playlist name argument
+///
+/// Playlist names can't contain `/` in addition to control characters.
+pub struct PlaylistName<'a>(pub &'a str);
+
+impl<'a> ToArguments for PlaylistName<'a> {
+    fn to_arguments<F, E>(&self, f: &mut F) -> StdResult<(), E>
+        where F: FnMut(&str) -> StdResult<(), E>
+    {
+        f(self.0)
+    }
+
+    fn validate(&self) -> StdResult<(), ArgumentError> {
+        check_name(self.0, &['/'])
+    }
+}
+
+/// Sticker name argument
+///
+/// Sticker names can't contain `=`, as it separates name from value in `sticker` replies.
+pub struct StickerName<'a>(pub &'a str);
+
+impl<'a> ToArguments for StickerName<'a> {
+    fn to_arguments<F, E>(&self, f: &mut F) -> StdResult<(), E>
+        where F: FnMut(&str) -> StdResult<(), E>
+    {
+        f(self.0)
+    }
+
+    fn validate(&self) -> StdResult<(), ArgumentError> {
+        check_name(self.0, &['='])
+    }
+}
+
 macro_rules! argument_for_display {
     ( $x:path ) => {
         impl ToArguments for $x {
@@ -218,7 +405,7 @@ argument_for_display!{::status::ReplayGain}
 argument_for_display!{String}
 argument_for_display!{::song::Id}
 argument_for_display!{::song::Range}
-argument_for_display!{::message::Channel}
+argument_for_display!{::song::QueuePosition}
 
 macro_rules! argument_for_tuple {
     ( $($t:ident: $T: ident),+ ) => {
@@ -232,6 +419,14 @@ macro_rules! argument_for_tuple {
                      )*
                     Ok(())
                 }
+
+            fn validate(&self) -> StdResult<(), ArgumentError> {
+                let ($(ref $t,)*) = *self;
+                $(
+                    $t.validate()?;
+                 )*
+                Ok(())
+            }
         }
     };
 }
@@ -249,6 +444,13 @@ impl<'a, T: ToArguments> ToArguments for &'a [T] {
         }
         Ok(())
     }
+
+    fn validate(&self) -> StdResult<(), ArgumentError> {
+        for arg in *self {
+            arg.validate()?
+        }
+        Ok(())
+    }
 }
 
 pub struct Quoted<'a, D: fmt::Display + 'a + ?Sized>(pub &'a D);
@@ -265,3 +467,141 @@ impl<'a, D: fmt::Display + 'a + ?Sized> fmt::Display for Quoted<'a, D> {
 }
 
 // }}}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use client::Client;
+    use std::cell::RefCell;
+    use std::io::{self, Cursor, Read, Write};
+    use std::rc::Rc;
+
+    struct MockStream(Cursor<Vec<u8>>, Rc<RefCell<Vec<u8>>>);
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.1.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn unquote(s: &str) -> String {
+        let mut result = String::new();
+        let mut chars = s[1..s.len() - 1].chars();
+        while let Some(c) = chars.next() {
+            result.push(if c == '\\' { chars.next().unwrap() } else { c });
+        }
+        result
+    }
+
+    quickcheck! {
+        fn control_chars_rejected(arg: String) -> bool {
+            check_argument(&arg).is_ok() == !arg.chars().any(char::is_control)
+        }
+
+        fn quoted_roundtrip(arg: String) -> bool {
+            let quoted = Quoted(&*arg).to_string();
+            check_argument(&arg).is_err() || (!quoted.contains('\n') && unquote(&quoted) == arg)
+        }
+
+        fn single_command_line(name: String, value: String) -> bool {
+            let output = Rc::new(RefCell::new(Vec::new()));
+            let stream = MockStream(Cursor::new(b"OK MPD 0.21.0\n".to_vec()), output.clone());
+            let mut client = Client::new(stream).unwrap();
+
+            let result = client.run_command("sticker set", ("song", "a.flac", StickerName(&name), &*value));
+            let lines = output.borrow().iter().filter(|&&b| b == b'\n').count();
+            match result {
+                Ok(()) => lines == 1 && check_name(&name, &['=']).is_ok() && check_argument(&value).is_ok(),
+                Err(Error::Argument(_)) => lines == 0,
+                Err(_) => false,
+            }
+        }
+    }
+    #[test]
+    fn borrowed_pairs() {
+        let mut pairs = Pairs::new(Cursor::new(&b"file: a.flac\nTitle: a: b\nOK\nfile: b.flac\n"[..]), Vec::new());
+        assert_eq!(pairs.next_pair().unwrap().unwrap(), ("file", "a.flac"));
+        assert_eq!(pairs.next_pair().unwrap().unwrap(), ("Title", "a: b"));
+        assert!(pairs.next_pair().is_none());
+        assert!(pairs.next_pair().is_none());
+
+        let mut pairs = Pairs::new(Cursor::new(&b"file: a.flac\n"[..]), Vec::new());
+        assert!(pairs.next().unwrap().is_ok());
+        assert!(pairs.next().unwrap().is_err());
+        assert!(pairs.next().is_none());
+    }
+
+    #[test]
+    fn reply_drained_on_error() {
+        let input = b"OK MPD 0.21.0\nfile: a.flac\nTime: x\nfile: b.flac\nTime: 1\nOK\nvolume: 10\nOK\n";
+        let mut client = Client::new(MockStream(Cursor::new(input.to_vec()), Rc::new(RefCell::new(Vec::new())))).unwrap();
+        assert!(client.feed_structs::<::song::Song>("file").is_err());
+        assert_eq!(client.read_list("volume").unwrap(), vec!["10"]);
+    }
+
+    #[test]
+    fn reply_drained_on_bad_pair() {
+        let input = b"OK MPD 0.21.0\nfile: a.flac\nbad line\nfile: b.flac\nOK\nvolume: 10\nOK\n";
+        let mut client = Client::new(MockStream(Cursor::new(input.to_vec()), Rc::new(RefCell::new(Vec::new())))).unwrap();
+        match client.read_pairs_with(|_, _| Ok(())) {
+            Err(Error::Parse(ParseError::BadPair)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(client.read_list("volume").unwrap(), vec!["10"]);
+    }
+
+    #[test]
+    fn playlist_search_line() {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let input = b"OK MPD 0.24.0\nfile: a.flac\nOK\n";
+        let mut client = Client::new(MockStream(Cursor::new(input.to_vec()), output.clone())).unwrap();
+        let mut query = ::search::Query::new();
+        let query = query.and(::search::Term::Tag("artist".into()), "Air")
+            .and(::search::Term::Tag("album".into()), "Moon \"Safari\"");
+        assert_eq!(client.pl_search("mix", query, (0, 2)).unwrap().len(), 1);
+        assert_eq!(String::from_utf8(output.borrow().clone()).unwrap(),
+                   r#"searchplaylist "mix" "((artist contains \"Air\") AND (album contains \"Moon \\\"Safari\\\"\"))" "window" "0:2""#.to_owned() + "\n");
+    }
+
+    #[test]
+    fn sticker_get() {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let input = b"OK MPD 0.21.0\nsticker: rating=a=b\nOK\n";
+        let mut client = Client::new(MockStream(Cursor::new(input.to_vec()), output.clone())).unwrap();
+        assert_eq!(client.sticker("song", "a.flac", "rating").unwrap(), "a=b");
+        assert_eq!(String::from_utf8(output.borrow().clone()).unwrap(), "sticker get \"song\" \"a.flac\" \"rating\"\n");
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn position_out_of_range() {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut client = Client::new(MockStream(Cursor::new(b"OK MPD 0.23.0\n".to_vec()), output.clone())).unwrap();
+        let song = ::song::Song { file: "a.flac".to_owned(), ..Default::default() };
+        match client.insert(&song, u32::max_value() as usize + 1) {
+            Err(Error::Argument(ArgumentError::OutOfRange(ref value))) if value == "4294967296" => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(output.borrow().is_empty());
+    }
+
+    #[test]
+    fn picture_cancelled() {
+        let input = b"OK MPD 0.21.0\nsize: 8\ntype: image/png\nbinary: 4\nabcd\nOK\nvolume: 10\nOK\n";
+        let mut client = Client::new(MockStream(Cursor::new(input.to_vec()), Rc::new(RefCell::new(Vec::new())))).unwrap();
+        match client.albumart_progress(&::song::Song { file: "a.flac".to_owned(), ..Default::default() }, |received, total| received == total) {
+            Err(Error::Cancelled) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(client.read_list("volume").unwrap(), vec!["10"]);
+    }
+}
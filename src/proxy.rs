@@ -0,0 +1,1172 @@
+//! The module implements MPD protocol proxy with per-user command ACLs
+//!
+//! `Proxy` speaks MPD protocol to downstream clients and forwards their commands
+//! to a single upstream `Client`. Every command is checked against `Rules` of the
+//! user, selected with `password` command (the password is checked by the proxy
+//! and never forwarded), or guest rules for clients which didn't log in (nothing
+//! but `ping`, `password` and `close` by default). Denied commands get `ACK [4@pos]`
+//! permission error back, just like MPD does, and are listed by `notcommands`.
+//!
+//! Upstream connection is shared, so `idle` is emulated: pending events are fetched
+//! from upstream with `idle` + `noidle` pair while some downstream clients idle,
+//! and are queued for every downstream client separately.
+//!
+//! `Proxy::multiplex()` creates a proxy running commands on several upstream
+//! connections instead (each command or command list on any free one), with one
+//! more connection waiting in `idle` for events for all downstream clients.
+//! Command lists are forwarded as command lists, so they run atomically upstream.
+//!
+//! ```rust,no_run
+//! # extern crate mpd;
+//! # fn main() {
+//! use mpd::proxy::{Acl, Proxy, Rules, User};
+//! use std::net::TcpListener;
+//!
+//! let acl = Acl {
+//!     guest: Rules::allow(vec!["status", "currentsong", "playlistinfo", "idle"]),
+//!     users: vec![User {
+//!         name: "kitchen".to_owned(),
+//!         password: "secret".to_owned(),
+//!         rules: Rules { read_only_playlists: true, max_volume: Some(60), ..Rules::default() },
+//!     }],
+//! };
+//! let client = mpd::Client::connect("127.0.0.1:6600").unwrap();
+//! let proxy = Proxy::new(client, acl);
+//! proxy.listen(TcpListener::bind("127.0.0.1:6601").unwrap()).unwrap();
+//! # }
+//! ```
+
+use client::Client;
+use error::{Error, Result};
+use proto::Proto;
+use reply::Reply;
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::mem;
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::thread;
+use std::time::Duration;
+
+/// Commands modifying stored playlists, denied with `Rules::read_only_playlists`
+pub const PLAYLIST_WRITE: &'static [&'static str] = &["save",
+                                                      "rm",
+                                                      "rename",
+                                                      "playlistadd",
+                                                      "playlistclear",
+                                                      "playlistdelete",
+                                                      "playlistmove",
+                                                      "searchaddpl"];
+
+/// Commands changing state of the (shared) upstream connection
+const PER_CONNECTION: &'static [&'static str] = &["binarylimit", "subscribe", "unsubscribe", "readmessages", "protocol"];
+
+/// Default interval of upstream event polling while some clients idle
+pub const POLL_INTERVAL: u64 = 250;
+
+/// Free upstream connections are pinged that often (in ms), so they are not closed on timeout
+const KEEPALIVE: u64 = 30000;
+
+// Rules {{{
+/// Access rules of a single user
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Rules {
+    /// allowed commands (all commands if `None`)
+    pub allow: Option<BTreeSet<String>>,
+    /// denied commands
+    pub deny: BTreeSet<String>,
+    /// deny commands modifying stored playlists (see `PLAYLIST_WRITE`)
+    pub read_only_playlists: bool,
+    /// maximal volume the user can set
+    pub max_volume: Option<i8>,
+}
+
+impl Rules {
+    /// Rules allowing only listed commands
+    pub fn allow<I>(commands: I) -> Rules
+        where I: IntoIterator,
+              I::Item: Into<String>
+    {
+        Rules { allow: Some(commands.into_iter().map(Into::into).collect()), ..Rules::default() }
+    }
+
+    /// Rules denying all commands (`ping`, `password` and `close` are still handled by the proxy)
+    pub fn none() -> Rules {
+        Rules::allow(Vec::<String>::new())
+    }
+
+    /// Check if command is allowed (without looking at its arguments)
+    pub fn allows(&self, command: &str) -> bool {
+        !(self.read_only_playlists && PLAYLIST_WRITE.contains(&command)) && !self.deny.contains(command) &&
+        self.allow.as_ref().map_or(true, |allow| allow.contains(command))
+    }
+
+    /// Check if the volume can be set
+    pub fn allows_volume(&self, volume: i32) -> bool {
+        self.max_volume.map_or(true, |max| volume <= max as i32)
+    }
+}
+
+/// Proxy user
+#[derive(Clone, Debug, PartialEq)]
+pub struct User {
+    /// user name
+    pub name: String,
+    /// password, used with `password` command
+    pub password: String,
+    /// access rules
+    pub rules: Rules,
+}
+
+/// Access control list: users and guest rules
+#[derive(Clone, Debug, PartialEq)]
+pub struct Acl {
+    /// rules for clients, which didn't log in
+    pub guest: Rules,
+    /// users, selected by password
+    pub users: Vec<User>,
+}
+
+impl Default for Acl {
+    /// No users, and guests can't run any commands
+    fn default() -> Acl {
+        Acl {
+            guest: Rules::none(),
+            users: Vec::new(),
+        }
+    }
+}
+
+impl Acl {
+    /// Find user by password
+    pub fn login(&self, password: &str) -> Option<&User> {
+        self.users.iter().find(|user| user.password == password)
+    }
+}
+// }}}
+
+// Command line {{{
+/// Downstream command, split into name and arguments
+#[derive(Clone, Debug, PartialEq)]
+struct Command {
+    name: String,
+    args: Vec<String>,
+}
+
+impl Command {
+    /// Split command line the way MPD does, unescaping quoted arguments
+    fn parse(line: &str) -> ::std::result::Result<Command, &'static str> {
+        let mut chars = line.trim_start().chars().peekable();
+
+        let mut name = String::new();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                break;
+            }
+            if !(c.is_ascii_alphanumeric() || c == '_') {
+                return Err("Malformed command name");
+            }
+            name.push(c);
+            chars.next();
+        }
+        if name.is_empty() {
+            return Err("No command given");
+        }
+
+        let mut args = Vec::new();
+        loop {
+            while chars.peek().map_or(false, |c| c.is_whitespace()) {
+                chars.next();
+            }
+
+            let mut arg = String::new();
+            match chars.next() {
+                None => break,
+                Some('"') => {
+                    loop {
+                        match chars.next() {
+                            Some('"') => break,
+                            Some('\\') => arg.push(chars.next().ok_or("Missing closing '\"'")?),
+                            Some(c) => arg.push(c),
+                            None => return Err("Missing closing '\"'"),
+                        }
+                    }
+                    if chars.peek().map_or(false, |c| !c.is_whitespace()) {
+                        return Err("Space expected after closing '\"'");
+                    }
+                }
+                Some(c) => {
+                    arg.push(c);
+                    while let Some(&c) = chars.peek() {
+                        if c.is_whitespace() {
+                            break;
+                        }
+                        if c == '"' {
+                            return Err("Invalid unquoted character");
+                        }
+                        arg.push(c);
+                        chars.next();
+                    }
+                }
+            }
+            args.push(arg);
+        }
+
+        Ok(Command {
+            name: name,
+            args: args,
+        })
+    }
+}
+
+/// Append `ACK` line to the reply
+fn ack(out: &mut Vec<u8>, code: u16, pos: usize, command: &str, message: &str) {
+    let _ = writeln!(out, "ACK [{}@{}] {{{}}} {}", code, pos, command, message);
+}
+
+fn deny(out: &mut Vec<u8>, pos: usize, command: &str) {
+    ack(out, 4, pos, command, &format!("you don't have permission for \"{}\"", command));
+}
+// }}}
+
+// Upstream {{{
+/// Read reply to a forwarded command, copying reply pairs (and binary data) to the output
+fn read_reply<S: Read + Write>(client: &mut Client<S>, out: &mut Vec<u8>) -> Result<()> {
+    loop {
+        let line = client.read_line()?;
+        match line.parse::<Reply>()? {
+            Reply::Ok => return Ok(()),
+            Reply::Ack(e) => return Err(client.server_error(e)),
+            Reply::Pair(key, value) => {
+                let _ = writeln!(out, "{}: {}", key, value);
+                if key == "binary" {
+                    // data is followed by a newline
+                    let len: usize = value.parse()?;
+                    let start = out.len();
+                    out.resize(start + len + 1, 0);
+                    client.read_bytes(&mut out[start..])?;
+                }
+            }
+        }
+    }
+}
+
+/// Read events until the end of `idle` reply
+fn read_changes<S: Read + Write>(client: &mut Client<S>) -> Result<Vec<String>> {
+    let mut events = Vec::new();
+    loop {
+        let line = client.read_line()?;
+        match line.parse::<Reply>()? {
+            Reply::Ok => return Ok(events),
+            Reply::Ack(e) => return Err(client.server_error(e)),
+            Reply::Pair(ref key, value) if key == "changed" => events.push(value),
+            Reply::Pair(..) => (),
+        }
+    }
+}
+
+/// Fetch events, which occurred upstream since the last call
+fn changes<S: Read + Write>(client: &mut Client<S>) -> Result<Vec<String>> {
+    // `idle` returns at once if there are pending events, and `noidle` is then ignored
+    client.run_command("idle", ())?;
+    client.run_command("noidle", ())?;
+    read_changes(client)
+}
+
+fn disconnected() -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::NotConnected, "no upstream connections left"))
+}
+// }}}
+
+// Proxy {{{
+enum Input {
+    Line(String),
+    Changed(Vec<String>),
+    Closed,
+}
+
+struct Upstream<S: Read + Write> {
+    /// connections not in use
+    free: Vec<Client<S>>,
+    /// working connections, including ones in use
+    open: usize,
+}
+
+/// MPD protocol proxy
+pub struct Proxy<S: Read + Write = TcpStream> {
+    upstream: Mutex<Upstream<S>>,
+    released: Condvar,
+    /// connection waiting for events, until `watch()` takes it
+    idle_client: Mutex<Option<Client<S>>>,
+    multiplexed: AtomicBool,
+    acl: Acl,
+    version: String,
+    sessions: Mutex<Vec<Sender<Input>>>,
+    idlers: AtomicUsize,
+    poll_interval: Duration,
+}
+
+impl<S: Read + Write> Proxy<S> {
+    /// Create proxy forwarding commands to the upstream client
+    ///
+    /// Upstream events are polled with the same connection.
+    pub fn new(client: Client<S>, acl: Acl) -> Proxy<S> {
+        let version = client.version.to_string();
+        Proxy::build(vec![client], None, version, acl)
+    }
+
+    /// Create proxy multiplexing downstream clients onto several upstream connections
+    ///
+    /// Every command (or command list) runs on a free connection from `clients`,
+    /// while `idle` connection waits for events for all downstream clients at once.
+    pub fn multiplex(clients: Vec<Client<S>>, idle: Client<S>, acl: Acl) -> Proxy<S> {
+        let version = idle.version.to_string();
+        Proxy::build(clients, Some(idle), version, acl)
+    }
+
+    fn build(clients: Vec<Client<S>>, idle: Option<Client<S>>, version: String, acl: Acl) -> Proxy<S> {
+        Proxy {
+            upstream: Mutex::new(Upstream {
+                open: clients.len(),
+                free: clients,
+            }),
+            released: Condvar::new(),
+            multiplexed: AtomicBool::new(idle.is_some()),
+            idle_client: Mutex::new(idle),
+            acl: acl,
+            version: version,
+            sessions: Mutex::new(Vec::new()),
+            idlers: AtomicUsize::new(0),
+            poll_interval: Duration::from_millis(POLL_INTERVAL),
+        }
+    }
+
+    /// Set interval of upstream event polling while some clients idle
+    pub fn set_poll_interval(&mut self, interval: Duration) {
+        self.poll_interval = interval;
+    }
+
+    /// Access control list
+    pub fn acl(&self) -> &Acl {
+        &self.acl
+    }
+
+    /// Take a free upstream connection, waiting for one if all are busy
+    fn checkout<'a>(&'a self) -> Result<Checkout<'a, S>> {
+        let mut upstream = self.upstream.lock().unwrap();
+        loop {
+            if let Some(client) = upstream.free.pop() {
+                return Ok(Checkout {
+                    proxy: self,
+                    client: Some(client),
+                });
+            }
+            if upstream.open == 0 {
+                return Err(disconnected());
+            }
+            upstream = self.released.wait(upstream).unwrap();
+        }
+    }
+
+    fn broadcast(&self, events: Vec<String>) {
+        if !events.is_empty() {
+            self.sessions.lock().unwrap().retain(|session| session.send(Input::Changed(events.clone())).is_ok());
+        }
+    }
+
+    /// Fetch pending upstream events and queue them for all downstream clients
+    ///
+    /// Does nothing for multiplexing proxy, which gets events on its idle connection.
+    pub fn poll(&self) -> Result<()> {
+        if self.multiplexed.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        let mut client = self.checkout()?;
+        match changes(&mut *client) {
+            Ok(events) => {
+                drop(client);
+                self.broadcast(events);
+                Ok(())
+            }
+            Err(e) => {
+                client.discard();
+                Err(e)
+            }
+        }
+    }
+
+    /// Watch upstream events, only returns on upstream error
+    ///
+    /// Multiplexing proxy waits for events on its idle connection (and falls back
+    /// to polling if it fails), otherwise events are polled while some downstream
+    /// clients idle. Should run in a background thread, if clients are served
+    /// with `serve()`, or idling clients never get notified.
+    pub fn watch(&self) -> Result<()> {
+        self.wait();
+        loop {
+            thread::sleep(self.poll_interval);
+            if self.idlers.load(Ordering::SeqCst) > 0 {
+                self.poll()?;
+            }
+        }
+    }
+
+    /// Broadcast events from the idle connection until it fails, then switch to polling
+    fn wait(&self) {
+        let idle = self.idle_client.lock().unwrap().take();
+        if let Some(mut client) = idle {
+            while let Ok(events) = client.run_command("idle", ()).and_then(|_| read_changes(&mut client)) {
+                self.broadcast(events);
+            }
+            self.multiplexed.store(false, Ordering::SeqCst);
+        }
+    }
+
+    /// Ping free upstream connections in a loop, only returns when all of them fail
+    ///
+    /// Keeps upstream from closing unused connections on timeout.
+    pub fn keepalive(&self) -> Result<()> {
+        loop {
+            thread::sleep(Duration::from_millis(KEEPALIVE));
+            let mut upstream = self.upstream.lock().unwrap();
+            for mut client in mem::replace(&mut upstream.free, Vec::new()) {
+                match client.ping() {
+                    Ok(()) => upstream.free.push(client),
+                    Err(_) => upstream.open -= 1,
+                }
+            }
+            self.released.notify_all();
+            if upstream.open == 0 {
+                return Err(disconnected());
+            }
+        }
+    }
+
+    /// Serve single downstream client until it disconnects
+    ///
+    /// Reader is consumed by a separate thread, so `noidle` can interrupt idling.
+    pub fn serve<R, W>(&self, reader: R, mut writer: W) -> Result<()>
+        where R: Read + Send + 'static,
+              W: Write
+    {
+        let (sender, receiver) = channel();
+        self.sessions.lock().unwrap().push(sender.clone());
+        thread::spawn(move || {
+            for line in BufReader::new(reader).lines() {
+                match line {
+                    Ok(line) => {
+                        if sender.send(Input::Line(line)).is_err() {
+                            return;
+                        }
+                    }
+                    Err(_) => break,
+                }
+            }
+            let _ = sender.send(Input::Closed);
+        });
+
+        writeln!(writer, "OK MPD {}", self.version)?;
+        writer.flush()?;
+
+        let mut session = Session {
+            proxy: self,
+            rules: &self.acl.guest,
+            events: Vec::new(),
+            idle: None,
+            list: None,
+        };
+        let result = session.run(&receiver, &mut writer);
+        if session.idle.is_some() {
+            self.idlers.fetch_sub(1, Ordering::SeqCst);
+        }
+        result
+    }
+}
+
+impl<S: Read + Write + Send + 'static> Proxy<S> {
+    /// Accept downstream clients, serving each one in a separate thread
+    pub fn listen(self, listener: TcpListener) -> Result<()> {
+        let proxy = Arc::new(self);
+        let watcher = proxy.clone();
+        thread::spawn(move || watcher.watch());
+        let pinger = proxy.clone();
+        thread::spawn(move || pinger.keepalive());
+
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let proxy = proxy.clone();
+            thread::spawn(move || -> Result<()> {
+                let reader = stream.try_clone()?;
+                let result = proxy.serve(reader, &stream);
+                let _ = stream.shutdown(Shutdown::Both);
+                result
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Upstream connection in use, returned back on drop
+struct Checkout<'a, S: 'a + Read + Write> {
+    proxy: &'a Proxy<S>,
+    client: Option<Client<S>>,
+}
+
+impl<'a, S: 'a + Read + Write> Checkout<'a, S> {
+    /// Close broken connection instead of returning it
+    fn discard(mut self) {
+        self.client = None;
+    }
+}
+
+impl<'a, S: 'a + Read + Write> Deref for Checkout<'a, S> {
+    type Target = Client<S>;
+    fn deref(&self) -> &Client<S> {
+        self.client.as_ref().unwrap()
+    }
+}
+
+impl<'a, S: 'a + Read + Write> DerefMut for Checkout<'a, S> {
+    fn deref_mut(&mut self) -> &mut Client<S> {
+        self.client.as_mut().unwrap()
+    }
+}
+
+impl<'a, S: 'a + Read + Write> Drop for Checkout<'a, S> {
+    fn drop(&mut self) {
+        let mut upstream = self.proxy.upstream.lock().unwrap();
+        match self.client.take() {
+            Some(client) => {
+                upstream.free.push(client);
+                self.proxy.released.notify_one();
+            }
+            None => {
+                upstream.open -= 1;
+                self.proxy.released.notify_all();
+            }
+        }
+    }
+}
+// }}}
+
+// Session {{{
+struct Session<'a, S: 'a + Read + Write> {
+    proxy: &'a Proxy<S>,
+    rules: &'a Rules,
+    /// events queued since the last `idle`
+    events: Vec<String>,
+    /// subsystems the client idles for
+    idle: Option<Vec<String>>,
+    /// command list being collected, and whether it was started with `command_list_ok_begin`
+    list: Option<(bool, Vec<String>)>,
+}
+
+impl<'a, S: 'a + Read + Write> Session<'a, S> {
+    fn run<W: Write>(&mut self, input: &Receiver<Input>, writer: &mut W) -> Result<()> {
+        while let Ok(input) = input.recv() {
+            let mut out = Vec::new();
+            let result = match input {
+                Input::Line(line) => self.line(&line, &mut out),
+                Input::Changed(events) => {
+                    for event in events {
+                        if !self.events.contains(&event) {
+                            self.events.push(event);
+                        }
+                    }
+                    self.notify(&mut out, false);
+                    Ok(true)
+                }
+                Input::Closed => Ok(false),
+            };
+
+            if !out.is_empty() {
+                writer.write_all(&out)?;
+                writer.flush()?;
+            }
+            if !result? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle a line, returns `false` if the connection should be closed
+    fn line(&mut self, line: &str, out: &mut Vec<u8>) -> Result<bool> {
+        if self.idle.is_some() {
+            // MPD closes connection on any other command while idle
+            if line.trim() == "noidle" {
+                self.notify(out, true);
+                return Ok(true);
+            }
+            return Ok(false);
+        }
+
+        if let Some((ok, mut lines)) = self.list.take() {
+            if line.trim() == "command_list_end" {
+                return self.execute(&lines, Some(ok), out);
+            }
+            lines.push(line.to_owned());
+            self.list = Some((ok, lines));
+            return Ok(true);
+        }
+
+        match line.trim() {
+            "command_list_begin" => self.list = Some((false, Vec::new())),
+            "command_list_ok_begin" => self.list = Some((true, Vec::new())),
+            // ignored while not idle
+            "noidle" => (),
+            _ => return self.execute(&[line.to_owned()], None, out),
+        }
+        Ok(true)
+    }
+
+    /// Reply with events matching idle mask, leaving idle mode
+    ///
+    /// Without `force` nothing is sent until there are some matching events.
+    fn notify(&mut self, out: &mut Vec<u8>, force: bool) {
+        let matched: Vec<String> = match self.idle {
+            Some(ref mask) => {
+                self.events
+                    .iter()
+                    .filter(|&event| mask.is_empty() || mask.contains(event))
+                    .cloned()
+                    .collect()
+            }
+            None => return,
+        };
+        if matched.is_empty() && !force {
+            return;
+        }
+
+        for event in &matched {
+            let _ = writeln!(out, "changed: {}", event);
+        }
+        out.extend_from_slice(b"OK\n");
+        self.events.retain(|event| !matched.contains(event));
+        self.idle = None;
+        self.proxy.idlers.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// Execute a single command or a command list, stopping at the first error
+    ///
+    /// Commands are checked first, then the ones before the first failing command
+    /// are forwarded upstream as a single command list, so commands of other clients
+    /// can't run in between.
+    fn execute(&mut self, lines: &[String], list: Option<bool>, out: &mut Vec<u8>) -> Result<bool> {
+        let proxy = self.proxy;
+        let mut steps = Vec::new();
+        // reply of the failed command, sent after replies to the preceding ones
+        let mut failed = Vec::new();
+        let mut close = false;
+        let completed = {
+            let mut client: Option<Checkout<S>> = None;
+            let mut rules = self.rules;
+            // upstream commands, fetched if the user asks for `notcommands`
+            let mut commands = None;
+            // volume after the volume changes checked so far
+            let mut volume = None;
+            for (pos, line) in lines.iter().enumerate() {
+                let command = match Command::parse(line) {
+                    Ok(command) => command,
+                    Err(message) => {
+                        ack(&mut failed, 5, pos, "", message);
+                        break;
+                    }
+                };
+
+                match &*command.name {
+                    "close" => {
+                        close = true;
+                        break;
+                    }
+                    "ping" => steps.push((pos, Step::Local(rules))),
+                    "password" => {
+                        match self.login(&command, pos, &mut failed) {
+                            Some(user) => rules = user,
+                            None => break,
+                        }
+                        steps.push((pos, Step::Local(rules)));
+                    }
+                    "idle" if list.is_none() => {
+                        return self.idle(command, out).map(|_| true);
+                    }
+                    "idle" | "noidle" => {
+                        ack(&mut failed, 2, pos, &command.name, "not allowed in command list");
+                        break;
+                    }
+                    _ => {
+                        if client.is_none() {
+                            match proxy.checkout() {
+                                Ok(checkout) => client = Some(checkout),
+                                Err(e) => {
+                                    ack(out, 52, pos, &command.name, &e.to_string());
+                                    return Err(e);
+                                }
+                            }
+                        }
+                        let checked = check(rules, client.as_mut().unwrap(), &command, pos, &mut volume, &mut failed);
+                        let checked = match checked {
+                            Ok(true) if command.name == "notcommands" && commands.is_none() => {
+                                match client.as_mut().unwrap().commands() {
+                                    Ok(all) => {
+                                        commands = Some(all);
+                                        Ok(true)
+                                    }
+                                    Err(e) => fail(e, pos, &command.name, &mut failed),
+                                }
+                            }
+                            checked => checked,
+                        };
+                        match checked {
+                            Ok(true) => steps.push((pos, Step::Forward(command, rules))),
+                            Ok(false) => break,
+                            Err(_) => {
+                                client.take().map(Checkout::discard);
+                                steps.clear();
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+
+            let first = steps.iter()
+                .filter_map(|&(pos, ref step)| match *step {
+                    Step::Forward(ref command, _) => Some((pos, command.name.clone())),
+                    Step::Local(_) => None,
+                })
+                .next();
+            match (client, first) {
+                (Some(mut client), Some((pos, name))) => {
+                    let result = match send(&mut client, &mut steps, list.is_some(), &mut failed) {
+                        Ok(()) => self.reply(Some(&mut client), &steps, list, commands.as_ref().map(|c| &c[..]), out),
+                        Err(e) => {
+                            ack(out, 52, pos, &name, "upstream connection failed");
+                            Err(e)
+                        }
+                    };
+                    result.unwrap_or_else(|_| {
+                        client.discard();
+                        false
+                    })
+                }
+                _ => self.reply(None, &steps, list, None, out).unwrap_or(false),
+            }
+        };
+
+        if completed {
+            if close {
+                return Ok(false);
+            }
+            if failed.is_empty() {
+                out.extend_from_slice(b"OK\n");
+            } else {
+                out.extend_from_slice(&failed);
+            }
+        }
+
+        if steps.iter().any(|&(_, ref step)| step.is_forward()) && proxy.idlers.load(Ordering::SeqCst) > 0 {
+            proxy.poll()?;
+        }
+        Ok(true)
+    }
+
+    /// Reply to sent commands, returns `false` if one of them failed
+    ///
+    /// Switches to rules of the user logging in with `password` in the list.
+    /// Only errors, which make upstream connection unusable, are returned.
+    fn reply(&mut self,
+             mut client: Option<&mut Client<S>>,
+             steps: &[(usize, Step<'a>)],
+             list: Option<bool>,
+             commands: Option<&[String]>,
+             out: &mut Vec<u8>)
+             -> Result<bool> {
+        let mut last = None;
+        for &(pos, ref step) in steps {
+            match *step {
+                Step::Local(rules) => self.rules = rules,
+                Step::Forward(ref command, rules) => {
+                    let client = client.as_mut().unwrap();
+                    last = Some((pos, &*command.name));
+                    let start = out.len();
+                    let result = read_reply(client, out);
+                    filter(rules, &command.name, commands, start, out);
+                    if let Err(e) = result {
+                        return fail(e, pos, &command.name, out);
+                    }
+                }
+            }
+            if list == Some(true) {
+                out.extend_from_slice(b"list_OK\n");
+            }
+        }
+        if let (Some((pos, name)), Some(_)) = (last, list) {
+            // `OK` of the command list itself
+            if let Err(e) = read_reply(client.unwrap(), &mut Vec::new()) {
+                return fail(e, pos, name, out);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Log in with `password` command, returns rules of the user
+    fn login(&self, command: &Command, pos: usize, out: &mut Vec<u8>) -> Option<&'a Rules> {
+        if command.args.len() != 1 {
+            ack(out, 2, pos, "password", "wrong number of arguments for \"password\"");
+            return None;
+        }
+        match self.proxy.acl.login(&command.args[0]) {
+            Some(user) => Some(&user.rules),
+            None => {
+                ack(out, 3, pos, "password", "incorrect password");
+                None
+            }
+        }
+    }
+
+    fn idle(&mut self, command: Command, out: &mut Vec<u8>) -> Result<()> {
+        if !self.rules.allows("idle") {
+            deny(out, 0, "idle");
+            return Ok(());
+        }
+        self.idle = Some(command.args);
+        self.proxy.idlers.fetch_add(1, Ordering::SeqCst);
+        self.notify(out, false);
+        if self.idle.is_some() {
+            // new events come back through the session channel
+            self.proxy.poll()?;
+        }
+        Ok(())
+    }
+}
+
+/// Command list entry
+enum Step<'a> {
+    /// command handled by the proxy, and rules to use from then on
+    Local(&'a Rules),
+    /// command forwarded upstream, and rules of the user
+    Forward(Command, &'a Rules),
+}
+
+impl<'a> Step<'a> {
+    fn is_forward(&self) -> bool {
+        match *self {
+            Step::Forward(..) => true,
+            Step::Local(_) => false,
+        }
+    }
+}
+
+/// Check if the command can be forwarded, returns `false` if it can't
+///
+/// `volume` is the volume set by the preceding commands of a command list (if any),
+/// so relative changes add up.
+fn check<S: Read + Write>(rules: &Rules,
+                          client: &mut Client<S>,
+                          command: &Command,
+                          pos: usize,
+                          volume: &mut Option<i32>,
+                          out: &mut Vec<u8>)
+                          -> Result<bool> {
+    let name = &*command.name;
+    if !rules.allows(name) {
+        deny(out, pos, name);
+        return Ok(false);
+    }
+    if PER_CONNECTION.contains(&name) || (name == "tagtypes" && !command.args.is_empty()) {
+        ack(out, 5, pos, name, "not supported by proxy");
+        return Ok(false);
+    }
+
+    if rules.max_volume.is_some() && (name == "setvol" || name == "volume") {
+        // MPD may accept arguments, which don't parse here (like " 80"), so they are denied
+        let change = match command.args.get(0).and_then(|arg| arg.parse::<i32>().ok()) {
+            Some(change) => change,
+            None => {
+                deny(out, pos, name);
+                return Ok(false);
+            }
+        };
+        let new = if name == "setvol" {
+            change
+        } else {
+            let current = match *volume {
+                Some(current) => current,
+                None => {
+                    match client.status() {
+                        Ok(status) => status.volume as i32,
+                        Err(e) => return fail(e, pos, name, out),
+                    }
+                }
+            };
+            current + change
+        };
+        if !rules.allows_volume(new) {
+            deny(out, pos, name);
+            return Ok(false);
+        }
+        *volume = Some(new);
+    }
+    Ok(true)
+}
+
+/// Send forwarded commands upstream, wrapped in a command list if `list` is set
+///
+/// If a command can't be sent (e.g. it's unsupported), it fails and the rest
+/// of the list is dropped. Only errors, which make upstream connection unusable,
+/// are returned.
+fn send<S: Read + Write>(client: &mut Client<S>, steps: &mut Vec<(usize, Step)>, list: bool, failed: &mut Vec<u8>) -> Result<()> {
+    if list {
+        client.run_command("command_list_ok_begin", ())?;
+    }
+    for i in 0..steps.len() {
+        let result = match steps[i].1 {
+            Step::Forward(ref command, _) => client.run_command(&command.name, &command.args[..]),
+            Step::Local(_) => Ok(()),
+        };
+        if let Err(e) = result {
+            if let Error::Io(_) = e {
+                return Err(e);
+            }
+            if let (pos, Step::Forward(ref command, _)) = steps[i] {
+                failed.clear();
+                let _ = fail(e, pos, &command.name, failed);
+            }
+            steps.truncate(i);
+            break;
+        }
+    }
+    if list {
+        client.run_command("command_list_end", ())?;
+    }
+    Ok(())
+}
+
+/// Hide commands the user can't run from `commands` reply (starting at `start`),
+/// and add them to `notcommands` reply
+fn filter(rules: &Rules, name: &str, commands: Option<&[String]>, start: usize, out: &mut Vec<u8>) {
+    match name {
+        "commands" => {
+            let reply = out.split_off(start);
+            for line in String::from_utf8_lossy(&reply).lines() {
+                if !line.starts_with("command: ") || rules.allows(&line[9..]) {
+                    let _ = writeln!(out, "{}", line);
+                }
+            }
+        }
+        "notcommands" => {
+            for command in commands.unwrap_or(&[]).iter().filter(|command| !rules.allows(command)) {
+                let _ = writeln!(out, "command: {}", command);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Reply with `ACK` for a failed command
+///
+/// Only errors, which make upstream connection unusable, are returned.
+fn fail(error: Error, pos: usize, name: &str, out: &mut Vec<u8>) -> Result<bool> {
+    match error {
+        Error::Server(e) => ack(out, e.code.code(), pos, &e.command, &e.detail),
+        Error::Argument(e) => ack(out, 2, pos, name, &e.to_string()),
+        Error::Unsupported { .. } => ack(out, 5, pos, name, &format!("unknown command \"{}\"", name)),
+        e => {
+            ack(out, 52, pos, name, "upstream connection failed");
+            return Err(e);
+        }
+    }
+    Ok(false)
+}
+// }}}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+    use std::io::{self, Cursor};
+    use std::rc::Rc;
+
+    struct MockStream(Cursor<Vec<u8>>, Rc<RefCell<Vec<u8>>>);
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.1.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn command(name: &str, args: &[&str]) -> Command {
+        Command {
+            name: name.to_owned(),
+            args: args.iter().map(|&arg| arg.to_owned()).collect(),
+        }
+    }
+
+    #[test]
+    fn parse_command() {
+        assert_eq!(Command::parse("status"), Ok(command("status", &[])));
+        assert_eq!(Command::parse("  find  artist \"A \\\"B\\\" \\\\ C\" "),
+                   Ok(command("find", &["artist", "A \"B\" \\ C"])));
+        assert_eq!(Command::parse("add \"\""), Ok(command("add", &[""])));
+        assert!(Command::parse("").is_err());
+        assert!(Command::parse("add \"a").is_err());
+        assert!(Command::parse("add \"a\"b").is_err());
+        assert!(Command::parse("add a\"b").is_err());
+        assert!(Command::parse("{add}").is_err());
+    }
+
+    #[test]
+    fn rules() {
+        let rules = Rules { read_only_playlists: true, max_volume: Some(50), ..Rules::allow(vec!["save", "play"]) };
+        assert!(rules.allows("play"));
+        assert!(!rules.allows("save"));
+        assert!(!rules.allows("clear"));
+        assert!(rules.allows_volume(50));
+        assert!(!rules.allows_volume(51));
+        assert!(Rules::default().allows("rm"));
+        assert!(!Rules::none().allows("status"));
+        assert!(!Acl::default().guest.allows("status"));
+    }
+
+    #[test]
+    fn session() {
+        let acl = Acl {
+            guest: Rules::allow(vec!["status"]),
+            users: vec![User {
+                name: "admin".to_owned(),
+                password: "secret".to_owned(),
+                rules: Rules { read_only_playlists: true, max_volume: Some(50), ..Rules::default() },
+            }],
+        };
+        let upstream = Rc::new(RefCell::new(Vec::new()));
+        let replies = b"OK MPD 0.23.0\nvolume: 10\nOK\nOK\nvolume: 40\nlist_OK\nOK\n";
+        let client = Client::new(MockStream(Cursor::new(replies.to_vec()), upstream.clone())).unwrap();
+        let proxy = Proxy::new(client, acl);
+
+        let input = "status\nclear\npassword wrong\npassword secret\nsetvol 80\nsetvol 40\n\
+                     command_list_ok_begin\nstatus\nsave x\ncommand_list_end\nclose\n";
+        let mut output = Vec::new();
+        proxy.serve(Cursor::new(input.as_bytes().to_vec()), &mut output).unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(),
+                   "OK MPD 0.23.0\nvolume: 10\nOK\nACK [4@0] {clear} you don't have permission for \"clear\"\n\
+                    ACK [3@0] {password} incorrect password\nOK\n\
+                    ACK [4@0] {setvol} you don't have permission for \"setvol\"\nOK\n\
+                    volume: 40\nlist_OK\nACK [4@1] {save} you don't have permission for \"save\"\n");
+        assert_eq!(String::from_utf8(upstream.borrow().clone()).unwrap(),
+                   "status\nsetvol \"40\"\ncommand_list_ok_begin\nstatus\ncommand_list_end\n");
+    }
+
+    #[test]
+    fn volume_limit() {
+        let acl = Acl { guest: Rules { max_volume: Some(60), ..Rules::default() }, ..Acl::default() };
+        let upstream = Rc::new(RefCell::new(Vec::new()));
+        let replies = b"OK MPD 0.23.0\nvolume: 40\nOK\nlist_OK\nOK\nlist_OK\nlist_OK\nOK\n";
+        let client = Client::new(MockStream(Cursor::new(replies.to_vec()), upstream.clone())).unwrap();
+        let proxy = Proxy::new(client, acl);
+
+        // relative changes in a command list add up
+        let input = "setvol \" 80\"\nvolume x\ncommand_list_ok_begin\nvolume 15\nvolume 15\ncommand_list_end\n\
+                     command_list_begin\nsetvol 30\nvolume +20\nvolume 15\ncommand_list_end\nclose\n";
+        let mut output = Vec::new();
+        proxy.serve(Cursor::new(input.as_bytes().to_vec()), &mut output).unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(),
+                   "OK MPD 0.23.0\nACK [4@0] {setvol} you don't have permission for \"setvol\"\n\
+                    ACK [4@0] {volume} you don't have permission for \"volume\"\n\
+                    list_OK\nACK [4@1] {volume} you don't have permission for \"volume\"\n\
+                    ACK [4@2] {volume} you don't have permission for \"volume\"\n");
+        assert_eq!(String::from_utf8(upstream.borrow().clone()).unwrap(),
+                   "command_list_begin\nstatus\nreplay_gain_status\ncommand_list_end\n\
+                    command_list_ok_begin\nvolume \"15\"\ncommand_list_end\n\
+                    command_list_ok_begin\nsetvol \"30\"\nvolume \"+20\"\ncommand_list_end\n");
+    }
+
+    #[test]
+    fn denied_commands() {
+        let acl = Acl { guest: Rules::allow(vec!["commands", "notcommands", "status"]), ..Acl::default() };
+        let upstream = Rc::new(RefCell::new(Vec::new()));
+        let replies = b"OK MPD 0.23.0\n\
+                        command: commands\ncommand: notcommands\ncommand: play\ncommand: status\nOK\n\
+                        command: commands\ncommand: notcommands\ncommand: play\ncommand: status\nOK\n\
+                        command: kill\nOK\n";
+        let client = Client::new(MockStream(Cursor::new(replies.to_vec()), upstream.clone())).unwrap();
+        let proxy = Proxy::new(client, acl);
+
+        let mut output = Vec::new();
+        proxy.serve(Cursor::new(b"commands\nnotcommands\nclose\n".to_vec()), &mut output).unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(),
+                   "OK MPD 0.23.0\ncommand: commands\ncommand: notcommands\ncommand: status\nOK\n\
+                    command: kill\ncommand: play\nOK\n");
+        assert_eq!(String::from_utf8(upstream.borrow().clone()).unwrap(),
+                   "commands\ncommands\nnotcommands\n");
+    }
+
+    #[test]
+    fn multiplex() {
+        let upstream = Rc::new(RefCell::new(Vec::new()));
+        let replies = b"OK MPD 0.23.0\nvolume: 40\nlist_OK\nlist_OK\nOK\n";
+        let client = Client::new(MockStream(Cursor::new(replies.to_vec()), upstream.clone())).unwrap();
+        let idled = Rc::new(RefCell::new(Vec::new()));
+        let events = b"OK MPD 0.23.0\nchanged: player\nOK\nchanged: mixer\nchanged: player\nOK\n";
+        let idle = Client::new(MockStream(Cursor::new(events.to_vec()), idled.clone())).unwrap();
+        let proxy = Proxy::multiplex(vec![client], idle, Acl { guest: Rules::default(), ..Acl::default() });
+
+        let input = "command_list_ok_begin\nstatus\nping\nsetvol 10\ncommand_list_end\nclose\n";
+        let mut output = Vec::new();
+        proxy.serve(Cursor::new(input.as_bytes().to_vec()), &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(),
+                   "OK MPD 0.23.0\nvolume: 40\nlist_OK\nlist_OK\nlist_OK\nOK\n");
+        assert_eq!(String::from_utf8(upstream.borrow().clone()).unwrap(),
+                   "command_list_ok_begin\nstatus\nsetvol \"10\"\ncommand_list_end\n");
+
+        // events from the idle connection are queued for every session
+        let (first, second) = (channel(), channel());
+        proxy.sessions.lock().unwrap().extend(vec![first.0.clone(), second.0.clone()]);
+        proxy.wait();
+        assert!(!proxy.multiplexed.load(Ordering::SeqCst));
+        assert_eq!(String::from_utf8(idled.borrow().clone()).unwrap(), "idle\nidle\nidle\n");
+
+        proxy.idlers.store(1, Ordering::SeqCst);
+        let mut session = Session {
+            proxy: &proxy,
+            rules: &proxy.acl.guest,
+            events: Vec::new(),
+            idle: Some(vec!["mixer".to_owned()]),
+            list: None,
+        };
+        first.0.send(Input::Closed).unwrap();
+        let mut output = Vec::new();
+        session.run(&first.1, &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "changed: mixer\nOK\n");
+        assert_eq!(session.events, vec!["player"]);
+        assert_eq!(proxy.idlers.load(Ordering::SeqCst), 0);
+
+        let mut session = Session {
+            proxy: &proxy,
+            rules: &proxy.acl.guest,
+            events: Vec::new(),
+            idle: None,
+            list: None,
+        };
+        second.0.send(Input::Closed).unwrap();
+        let mut output = Vec::new();
+        session.run(&second.1, &mut output).unwrap();
+        assert!(output.is_empty());
+        assert_eq!(session.events, vec!["player", "mixer"]);
+    }
+}
@@ -0,0 +1,328 @@
+//! The module defines raw command responses
+//!
+//! When MPD gets a command this crate doesn't wrap yet, it can still be called
+//! with `Client::raw()` method. It sends a command with any arguments implementing
+//! `ToArguments` trait, and returns all received `key: value` pairs as a `RawResponse`.
+//!
+//! The response can be iterated as pairs, grouped by a separator key (like `file`
+//! for songs lists), or decoded into any type implementing `FromResponse` trait.
+//! The trait can be derived with `#[derive(FromResponse)]` (see `mpd-derive` crate
+//! for supported attributes), values are parsed with `FromValue` trait.
+//!
+//! ```rust,no_run
+//! # extern crate mpd;
+//! # fn main() {
+//! let mut conn = mpd::Client::connect("127.0.0.1:6600").unwrap();
+//! let reply = conn.raw("getvol", ()).unwrap();
+//! println!("volume: {:?}", reply.field("volume"));
+//! # }
+//! ```
+
+use convert::FromIter;
+use error::{Error, ParseError, Result};
+use message::Channel;
+use song::Id;
+use status::{AudioFormat, ReplayGain, State};
+use std::slice;
+use time::{Duration, ParseError as TimeParseError, Timespec, Tm, at_utc, strptime};
+use version::Version;
+
+/// Types which can be built from MPD response pairs
+///
+/// It is implemented for all the crate's response types (like `Song` or `Status`),
+/// and can be implemented for user types to decode raw command responses.
+pub trait FromResponse: Sized {
+    /// Build value from an iterator over `key: value` pairs
+    fn from_response<I: Iterator<Item = Result<(String, String)>>>(iter: I) -> Result<Self>;
+}
+
+impl<T: FromIter> FromResponse for T {
+    fn from_response<I: Iterator<Item = Result<(String, String)>>>(iter: I) -> Result<T> {
+        FromIter::from_iter(iter)
+    }
+}
+
+/// Types which can be parsed from a single MPD response value
+pub trait FromValue: Sized {
+    /// Parse value
+    fn from_value(value: &str) -> Result<Self>;
+}
+
+macro_rules! value_from_str {
+    ( $($t:ty),* ) => {
+        $(
+            impl FromValue for $t {
+                fn from_value(value: &str) -> Result<$t> {
+                    value.parse().map_err(|e| Error::Parse(From::from(e)))
+                }
+            }
+        )*
+    };
+}
+value_from_str!{String, u8, u16, u32, u64, usize, i8, i16, i32, i64, f32, f64, State, ReplayGain, AudioFormat, Version}
+
+/// Boolean values are sent as `1` and `0`
+impl FromValue for bool {
+    fn from_value(value: &str) -> Result<bool> {
+        Ok(value == "1")
+    }
+}
+
+/// Durations are sent as (possibly fractional) number of seconds
+impl FromValue for Duration {
+    fn from_value(value: &str) -> Result<Duration> {
+        value.parse::<f64>()
+            .map(|v| Duration::milliseconds((v * 1000.0) as i64))
+            .map_err(|e| Error::Parse(ParseError::BadFloat(e)))
+    }
+}
+
+/// Timestamps are sent as number of seconds since epoch
+impl FromValue for Timespec {
+    fn from_value(value: &str) -> Result<Timespec> {
+        Ok(Timespec::new(value.parse()?, 0))
+    }
+}
+
+/// Date/times are sent in ISO 8601 format (like `Last-Modified` field)
+///
+/// Fractional seconds and time zone offsets are respected,
+/// the result is always in UTC.
+impl FromValue for Tm {
+    fn from_value(value: &str) -> Result<Tm> {
+        if value.len() < 19 || !value.is_char_boundary(19) {
+            return Err(Error::Parse(ParseError::BadTime(TimeParseError::InvalidTime)));
+        }
+        let (datetime, mut rest) = value.split_at(19);
+        let mut time = strptime(datetime, "%Y-%m-%dT%H:%M:%S")?.to_timespec();
+
+        if rest.starts_with('.') {
+            let digits = rest[1..].find(|c: char| !c.is_ascii_digit()).map_or(rest.len(), |p| p + 1);
+            let fraction = &rest[1..digits];
+            if fraction.is_empty() {
+                return Err(Error::Parse(ParseError::BadTime(TimeParseError::InvalidSecond)));
+            }
+            time.nsec = fraction.chars().chain("00000000".chars()).take(9).collect::<String>().parse()?;
+            rest = &rest[digits..];
+        }
+
+        let offset = match rest {
+            "" | "Z" => 0,
+            _ => {
+                let sign = match rest.as_bytes()[0] {
+                    b'+' => 1,
+                    b'-' => -1,
+                    _ => return Err(Error::Parse(ParseError::BadTime(TimeParseError::InvalidZoneOffset))),
+                };
+                let digits = rest[1..].replace(':', "");
+                match (digits.len(), digits.get(..2).and_then(|h| h.parse::<i64>().ok()),
+                       digits.get(2..).and_then(|m| m.parse::<i64>().ok())) {
+                    (4, Some(h), Some(m)) => sign * (h * 3600 + m * 60),
+                    _ => return Err(Error::Parse(ParseError::BadTime(TimeParseError::InvalidZoneOffset))),
+                }
+            }
+        };
+        time.sec -= offset;
+
+        Ok(at_utc(time))
+    }
+}
+
+impl FromValue for Id {
+    fn from_value(value: &str) -> Result<Id> {
+        Ok(Id(value.parse()?))
+    }
+}
+
+impl FromValue for Channel {
+    fn from_value(value: &str) -> Result<Channel> {
+        Ok(unsafe { Channel::new_unchecked(value.to_owned()) })
+    }
+}
+
+/// Raw command response
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RawResponse(pub Vec<(String, String)>);
+
+impl RawResponse {
+    /// Iterate over `key: value` pairs
+    pub fn pairs(&self) -> Pairs {
+        Pairs(self.0.iter())
+    }
+
+    /// Get value of the first pair with given key
+    pub fn field(&self, key: &str) -> Option<&str> {
+        self.0.iter().find(|&&(ref k, _)| k == key).map(|&(_, ref v)| &**v)
+    }
+
+    /// Get values of all pairs with given key
+    pub fn fields<'a>(&'a self, key: &'a str) -> Vec<&'a str> {
+        self.0.iter().filter(|&&(ref k, _)| k == key).map(|&(_, ref v)| &**v).collect()
+    }
+
+    /// Split response into groups of pairs, each group starting with `key`
+    ///
+    /// Pairs before the first `key` are skipped, so the grouping works
+    /// the same way as for lists of songs (grouped by `file` key),
+    /// outputs (grouped by `outputid` key) etc.
+    pub fn split<'a>(&'a self, key: &'a str) -> Groups<'a> {
+        let start = self.0.iter().position(|&(ref k, _)| k == key).unwrap_or(self.0.len());
+        Groups {
+            rest: &self.0[start..],
+            key: key,
+        }
+    }
+
+    /// Decode whole response into some type
+    pub fn decode<T: FromResponse>(&self) -> Result<T> {
+        T::from_response(self.0.iter().cloned().map(Ok))
+    }
+
+    /// Decode response groups (see `split()`) into a list of values of some type
+    pub fn decode_all<T: FromResponse>(&self, key: &str) -> Result<Vec<T>> {
+        self.split(key).map(|group| T::from_response(group.iter().cloned().map(Ok))).collect()
+    }
+}
+
+impl IntoIterator for RawResponse {
+    type Item = (String, String);
+    type IntoIter = ::std::vec::IntoIter<(String, String)>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a RawResponse {
+    type Item = (&'a str, &'a str);
+    type IntoIter = Pairs<'a>;
+    fn into_iter(self) -> Pairs<'a> {
+        self.pairs()
+    }
+}
+
+/// Iterator over raw response pairs
+pub struct Pairs<'a>(slice::Iter<'a, (String, String)>);
+
+impl<'a> Iterator for Pairs<'a> {
+    type Item = (&'a str, &'a str);
+    fn next(&mut self) -> Option<(&'a str, &'a str)> {
+        self.0.next().map(|&(ref k, ref v)| (&**k, &**v))
+    }
+}
+
+/// Iterator over raw response groups (see `RawResponse::split()`)
+pub struct Groups<'a> {
+    rest: &'a [(String, String)],
+    key: &'a str,
+}
+
+impl<'a> Iterator for Groups<'a> {
+    type Item = &'a [(String, String)];
+    fn next(&mut self) -> Option<&'a [(String, String)]> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let key = self.key;
+        let end = self.rest[1..].iter().position(|&(ref k, _)| k == key).map_or(self.rest.len(), |p| p + 1);
+        let (group, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        Some(group)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use error::{Error, ProtoError, Result};
+    use std::collections::BTreeMap;
+
+    fn response() -> RawResponse {
+        RawResponse(vec![("ignored".to_owned(), "1".to_owned()),
+                         ("partition".to_owned(), "default".to_owned()),
+                         ("partition".to_owned(), "kitchen".to_owned()),
+                         ("state".to_owned(), "play".to_owned())])
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Partition(String);
+
+    impl FromResponse for Partition {
+        fn from_response<I: Iterator<Item = Result<(String, String)>>>(iter: I) -> Result<Partition> {
+            for pair in iter {
+                let (key, value) = pair?;
+                if key == "partition" {
+                    return Ok(Partition(value));
+                }
+            }
+            Err(Error::Proto(ProtoError::NoField("partition")))
+        }
+    }
+
+    #[derive(Debug, PartialEq, FromResponse)]
+    struct Derived {
+        #[mpd(key = "partition")]
+        name: String,
+        #[mpd(key = "outputname", repeated)]
+        outputs: Vec<String>,
+        #[mpd(optional)]
+        volume: i8,
+        #[mpd(key = "state", parse_with = "parse_state")]
+        playing: Option<bool>,
+        #[mpd(rest)]
+        other: BTreeMap<String, String>,
+    }
+
+    fn parse_state(value: &str) -> Result<bool> {
+        Ok(value == "play")
+    }
+
+    #[test]
+    fn derived_response() {
+        let response = RawResponse(vec![("partition".to_owned(), "default".to_owned()),
+                                        ("outputname".to_owned(), "alsa".to_owned()),
+                                        ("outputname".to_owned(), "httpd".to_owned()),
+                                        ("state".to_owned(), "play".to_owned()),
+                                        ("mood".to_owned(), "happy".to_owned())]);
+        let derived = response.decode::<Derived>().unwrap();
+        assert_eq!(derived.name, "default");
+        assert_eq!(derived.outputs, vec!["alsa", "httpd"]);
+        assert_eq!(derived.volume, 0);
+        assert_eq!(derived.playing, Some(true));
+        assert_eq!(derived.other.get("mood").map(|s| &**s), Some("happy"));
+
+        match RawResponse(vec![]).decode::<Derived>() {
+            Err(Error::Proto(ProtoError::NoField("partition"))) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn split_groups() {
+        let response = response();
+        let groups = response.split("partition").collect::<Vec<_>>();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].len(), 1);
+        assert_eq!(groups[1].len(), 2);
+        assert_eq!(response.split("missing").count(), 0);
+    }
+
+    #[test]
+    fn decode_user_type() {
+        let response = response();
+        assert_eq!(response.field("partition"), Some("default"));
+        assert_eq!(response.fields("partition"), vec!["default", "kitchen"]);
+        assert_eq!(response.decode::<Partition>().unwrap(), Partition("default".to_owned()));
+        assert_eq!(response.decode_all::<Partition>("partition").unwrap(),
+                   vec![Partition("default".to_owned()), Partition("kitchen".to_owned())]);
+    }
+
+    #[test]
+    fn timestamps() {
+        let parse = |s| Tm::from_value(s).map(|t| t.to_timespec());
+        assert_eq!(parse("2016-03-12T18:20:11Z").unwrap(), Timespec::new(1457806811, 0));
+        assert_eq!(parse("2016-03-12T18:20:11.25Z").unwrap(), Timespec::new(1457806811, 250000000));
+        assert_eq!(parse("2016-03-12T20:20:11+02:00").unwrap(), Timespec::new(1457806811, 0));
+        assert!(parse("2016-03-12T18:20:11+2").is_err());
+        assert!(parse("2016-03-12").is_err());
+    }
+}
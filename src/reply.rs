@@ -26,11 +26,12 @@ impl FromStr for Reply {
         } else if let Ok(ack) = s.parse::<ServerError>() {
             Ok(Reply::Ack(ack))
         } else {
-            let mut splits = s.splitn(2, ':');
-            match (splits.next(), splits.next()) {
-                (Some(a), Some(b)) => Ok(Reply::Pair(a.to_owned(), b.trim().to_owned())),
-                _ => Err(ParseError::BadPair),
-            }
+            parse_pair(s).map(|(a, b)| Reply::Pair(a.to_owned(), b.to_owned())).ok_or(ParseError::BadPair)
         }
     }
 }
+
+/// Split `field: value` reply line into field and value, borrowing them from the line
+pub fn parse_pair(s: &str) -> Option<(&str, &str)> {
+    s.find(':').map(|pos| (&s[..pos], s[pos + 1..].trim()))
+}
@@ -1,7 +1,8 @@
 #![allow(missing_docs)]
 // TODO: unfinished functionality
 
-use proto::ToArguments;
+use error::ArgumentError;
+use proto::{ToArguments, check_argument, check_name};
 use std::borrow::Cow;
 use std::convert::Into;
 use std::fmt;
@@ -59,6 +60,64 @@ impl<'a> Query<'a> {
         self.filters.push(Filter::new(term, value));
         self
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    /// Query as a single filter expression argument, see `Expression`
+    pub fn expression<'b>(&'b self, op: &'static str) -> Expression<'a, 'b> {
+        Expression {
+            query: self,
+            op: op,
+        }
+    }
+}
+
+/// Query as a filter expression, like `((artist contains "x") AND (album contains "y"))`
+///
+/// Commands added in newer MPD versions (like `searchplaylist`) take a single
+/// filter expression argument instead of `tag value` pairs. Tags and file names
+/// are compared with given operator (like `==` or `contains`), `base` and
+/// `modified-since` terms use their own syntax.
+pub struct Expression<'a: 'b, 'b> {
+    query: &'b Query<'a>,
+    op: &'static str,
+}
+
+impl<'a, 'b> fmt::Display for Expression<'a, 'b> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn value(f: &mut fmt::Formatter, value: &str) -> fmt::Result {
+            f.write_str("\"")?;
+            for c in value.chars() {
+                if c == '"' || c == '\\' {
+                    f.write_str("\\")?;
+                }
+                write!(f, "{}", c)?;
+            }
+            f.write_str("\"")
+        }
+
+        let many = self.query.filters.len() > 1;
+        if many {
+            f.write_str("(")?;
+        }
+        for (i, filter) in self.query.filters.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" AND ")?;
+            }
+            match filter.typ {
+                Term::Base | Term::LastMod => write!(f, "({} ", filter.typ)?,
+                _ => write!(f, "({} {} ", filter.typ, self.op)?,
+            }
+            value(f, &filter.what)?;
+            f.write_str(")")?;
+        }
+        if many {
+            f.write_str(")")?;
+        }
+        Ok(())
+    }
 }
 
 impl<'a> fmt::Display for Term<'a> {
@@ -79,6 +138,13 @@ impl<'a> ToArguments for &'a Term<'a> {
     {
         f(&self.to_string())
     }
+
+    fn validate(&self) -> StdResult<(), ArgumentError> {
+        match **self {
+            Term::Tag(ref tag) => check_name(tag, &[' ', '"', '\\', '\'']),
+            _ => Ok(()),
+        }
+    }
 }
 
 impl<'a> ToArguments for &'a Filter<'a> {
@@ -88,6 +154,11 @@ impl<'a> ToArguments for &'a Filter<'a> {
         (&self.typ).to_arguments(f)?;
         f(&self.what)
     }
+
+    fn validate(&self) -> StdResult<(), ArgumentError> {
+        (&self.typ).validate()?;
+        check_argument(&self.what)
+    }
 }
 
 impl<'a> ToArguments for &'a Query<'a> {
@@ -99,6 +170,25 @@ impl<'a> ToArguments for &'a Query<'a> {
         }
         Ok(())
     }
+
+    fn validate(&self) -> StdResult<(), ArgumentError> {
+        for filter in &self.filters {
+            filter.validate()?
+        }
+        Ok(())
+    }
+}
+
+impl<'a, 'b> ToArguments for Expression<'a, 'b> {
+    fn to_arguments<F, E>(&self, f: &mut F) -> StdResult<(), E>
+        where F: FnMut(&str) -> StdResult<(), E>
+    {
+        f(&self.to_string())
+    }
+
+    fn validate(&self) -> StdResult<(), ArgumentError> {
+        self.query.validate()
+    }
 }
 
 impl ToArguments for Window {
@@ -139,4 +229,18 @@ mod test {
         let output = collect(&*finished);
         assert_eq!(output, vec!["albumartist", "Mac DeMarco", "album", "Salad Days"]);
     }
+
+    #[test]
+    fn filter_expression_format() {
+        let mut query = Query::new();
+        let finished = query.and(Term::Tag("artist".into()), "Mac DeMarco");
+        assert_eq!(finished.expression("==").to_string(), r#"(artist == "Mac DeMarco")"#);
+
+        let mut query = Query::new();
+        let finished = query.and(Term::Any, r#"say "hi" \o/"#)
+            .and(Term::Base, "albums")
+            .and(Term::LastMod, "2020-01-01T00:00:00Z");
+        assert_eq!(finished.expression("contains").to_string(),
+                   r#"((any contains "say \"hi\" \\o/") AND (base "albums") AND (modified-since "2020-01-01T00:00:00Z"))"#);
+    }
 }
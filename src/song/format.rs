@@ -0,0 +1,480 @@
+//! The module formats songs and player status with mpc-style templates
+//!
+//! `%name%` is replaced with a field value: a tag (like `%artist%` or `%album%`),
+//! or one of the special fields listed in `Fields` implementations. Text in `[...]`
+//! is printed only if some field in it is known and none is missing, `|` separates
+//! alternatives (the first non-empty one is printed), and `&` joins parts which
+//! all must be non-empty. Special characters are escaped with `\`.
+//!
+//! Field values can be padded and truncated with `%name:spec%`, where spec is
+//! `[<|>][width][.max]`: `%title:30%` pads title to 30 characters, `%track:>2%`
+//! aligns track number to the right, and `%artist:.20%` cuts artist to at
+//! most 20 characters.
+//!
+//! Templates are parsed once and can be rendered for any `Fields` source:
+//! `Song`, `SongRef`, `Status`, or a `(song, status)` pair for status bars.
+//!
+//! ```rust,no_run
+//! # extern crate mpd;
+//! use mpd::song::format::Template;
+//!
+//! # fn main() {
+//! let mut conn = mpd::Client::connect("127.0.0.1:6600").unwrap();
+//! let line: Template = "[%artist% - ]%title%|%file%".parse().unwrap();
+//! let bar: Template = "%state% %elapsed%/%duration% [%artist:.20% - ]%title%".parse().unwrap();
+//!
+//! for song in conn.queue().unwrap() {
+//!     println!("{}", line.render(&song));
+//! }
+//!
+//! let status = conn.status().unwrap();
+//! if let Some(song) = conn.currentsong().unwrap() {
+//!     println!("{}", bar.render(&(&song, &status)));
+//! }
+//! # }
+//! ```
+
+use error::ParseError;
+use song::{QueuePlace, Song, SongRef};
+use status::{State, Status};
+use std::borrow::Cow;
+use std::iter::Peekable;
+use std::str::{Chars, FromStr};
+use time::{Duration, Tm, strftime};
+
+/// Default mpc song format
+pub const DEFAULT: &'static str = "[%name%: &[%artist% - ]%title%]|%name%|[%artist% - ]%title%|%file%";
+
+// Fields {{{
+/// Source of field values for templates
+pub trait Fields {
+    /// Get field value by name, `None` if the field is unknown or missing
+    fn field(&self, name: &str) -> Option<Cow<str>>;
+}
+
+impl<'a, T: Fields + ?Sized> Fields for &'a T {
+    fn field(&self, name: &str) -> Option<Cow<str>> {
+        (**self).field(name)
+    }
+}
+
+/// Fields are looked up in the first source, then in the second one
+impl<A: Fields, B: Fields> Fields for (A, B) {
+    fn field(&self, name: &str) -> Option<Cow<str>> {
+        self.0.field(name).or_else(|| self.1.field(name))
+    }
+}
+
+/// Song fields are tags (case insensitive) and:
+///
+/// - `file`: song path, `filename`: its last component,
+/// - `time` or `duration`: duration as `m:ss` (or `h:mm:ss`),
+/// - `position` (1-based), `id` and `prio`: place in the queue,
+/// - `mtime` and `mdate`: last modification time and date,
+/// - `added`: date the song was added to the database,
+/// - `format`: audio format, like `44100:16:2`.
+impl Fields for Song {
+    fn field(&self, name: &str) -> Option<Cow<str>> {
+        match name {
+            "file" => Some(Cow::Borrowed(&*self.file)),
+            "filename" => Some(Cow::Borrowed(filename(&self.file))),
+            "time" | "duration" => self.duration.map(|d| Cow::Owned(format_duration(d))),
+            "mtime" => self.last_mod.and_then(|t| date(&t, "%c")),
+            "mdate" => self.last_mod.and_then(|t| date(&t, "%x")),
+            "added" => self.added.and_then(|t| date(&t, "%x")),
+            "format" => self.format.map(|f| Cow::Owned(f.to_string())),
+            "position" | "id" | "prio" => place_field(self.place, name),
+            _ => self.tag(name).value().map(Cow::Borrowed),
+        }
+    }
+}
+
+/// Same fields as for `Song`
+impl<'a> Fields for SongRef<'a> {
+    fn field(&self, name: &str) -> Option<Cow<str>> {
+        match name {
+            "file" => Some(Cow::Borrowed(self.file())),
+            "filename" => Some(Cow::Borrowed(filename(self.file()))),
+            "time" | "duration" => self.duration().map(|d| Cow::Owned(format_duration(d))),
+            "mtime" => self.last_mod().and_then(|t| date(&t, "%c")),
+            "mdate" => self.last_mod().and_then(|t| date(&t, "%x")),
+            "added" => self.added().and_then(|t| date(&t, "%x")),
+            "format" => self.format().map(|f| Cow::Owned(f.to_string())),
+            "position" | "id" | "prio" => place_field(self.place(), name),
+            _ => self.tag(name).value().map(Cow::Borrowed),
+        }
+    }
+}
+
+/// Status fields are:
+///
+/// - `state`: `playing`, `paused` or `stopped`,
+/// - `elapsed`, `duration` (or `time`) and `remaining` time of the current song,
+///   and `percent` of it played,
+/// - `position` (1-based) and `id` of the current song, `length` of the queue,
+/// - `volume` (missing if there's no mixer), `bitrate`, `audio` and `crossfade`,
+/// - `repeat`, `random`, `single` and `consume` as `on` or `off`,
+/// - `updating` (database update job ID) and `error`.
+impl Fields for Status {
+    fn field(&self, name: &str) -> Option<Cow<str>> {
+        let on_off = |value| Some(Cow::Borrowed(if value { "on" } else { "off" }));
+        let elapsed = self.elapsed.or_else(|| self.time.map(|t| t.0));
+        let duration = self.duration.or_else(|| self.time.map(|t| t.1));
+
+        match name {
+            "state" => {
+                Some(Cow::Borrowed(match self.state {
+                    State::Play => "playing",
+                    State::Pause => "paused",
+                    State::Stop => "stopped",
+                }))
+            }
+            "elapsed" => elapsed.map(|d| Cow::Owned(format_duration(d))),
+            "time" | "duration" => duration.map(|d| Cow::Owned(format_duration(d))),
+            "remaining" => {
+                match (elapsed, duration) {
+                    (Some(e), Some(d)) if d >= e => Some(Cow::Owned(format_duration(d - e))),
+                    _ => None,
+                }
+            }
+            "percent" => {
+                match (elapsed, duration) {
+                    (Some(e), Some(d)) if d > Duration::zero() => {
+                        Some(Cow::Owned((e.num_milliseconds() * 100 / d.num_milliseconds()).to_string()))
+                    }
+                    _ => None,
+                }
+            }
+            "position" | "id" => place_field(self.song, name),
+            "length" => Some(Cow::Owned(self.queue_len.to_string())),
+            "volume" if self.volume >= 0 => Some(Cow::Owned(self.volume.to_string())),
+            "bitrate" => self.bitrate.map(|b| Cow::Owned(b.to_string())),
+            "audio" => self.audio.map(|a| Cow::Owned(a.to_string())),
+            "crossfade" => self.crossfade.map(|d| Cow::Owned(d.num_seconds().to_string())),
+            "repeat" => on_off(self.repeat),
+            "random" => on_off(self.random),
+            "single" => on_off(self.single),
+            "consume" => on_off(self.consume),
+            "updating" => self.updating_db.map(|id| Cow::Owned(id.to_string())),
+            "error" => self.error.as_ref().map(|e| Cow::Borrowed(&**e)),
+            _ => None,
+        }
+    }
+}
+
+fn filename(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+fn date(tm: &Tm, format: &str) -> Option<Cow<'static, str>> {
+    strftime(format, tm).ok().map(Cow::Owned)
+}
+
+fn place_field(place: Option<QueuePlace>, name: &str) -> Option<Cow<'static, str>> {
+    place.map(|p| {
+        Cow::Owned(match name {
+            "position" => (p.pos + 1).to_string(),
+            "id" => p.id.to_string(),
+            _ => p.prio.to_string(),
+        })
+    })
+}
+
+/// Format duration like mpc does: `m:ss` or `h:mm:ss`
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.num_seconds();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+// }}}
+
+// Template {{{
+#[derive(Debug, Clone, PartialEq)]
+enum Item {
+    Text(String),
+    Field(String, Spec),
+    Group(Alternatives),
+}
+
+/// Padding and truncation of a field value
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+struct Spec {
+    right: bool,
+    width: usize,
+    max: Option<usize>,
+}
+
+/// Alternatives separated by `|`, each made of parts joined with `&`
+type Alternatives = Vec<Vec<Vec<Item>>>;
+
+/// Compiled format template
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template(Alternatives);
+
+impl Template {
+    /// Parse template
+    pub fn parse(template: &str) -> Result<Template, ParseError> {
+        let mut chars = template.chars().peekable();
+        let alts = try!(parse_alts(&mut chars));
+        match chars.next() {
+            None => Ok(Template(alts)),
+            Some(c) => Err(ParseError::BadValue(format!("unexpected `{}' in format", c))),
+        }
+    }
+
+    /// Render template, unknown or missing fields render to an empty string
+    pub fn render<F: Fields>(&self, fields: &F) -> String {
+        let mut out = String::new();
+        self.render_into(fields, &mut out);
+        out
+    }
+
+    /// Render template, appending result to the string
+    ///
+    /// Returns `false` if nothing was rendered.
+    pub fn render_into<F: Fields>(&self, fields: &F, out: &mut String) -> bool {
+        render_alts(&self.0, fields, out, false)
+    }
+}
+
+impl Default for Template {
+    fn default() -> Template {
+        Template::parse(DEFAULT).unwrap()
+    }
+}
+
+impl FromStr for Template {
+    type Err = ParseError;
+    fn from_str(s: &str) -> Result<Template, ParseError> {
+        Template::parse(s)
+    }
+}
+
+fn parse_alts(chars: &mut Peekable<Chars>) -> Result<Alternatives, ParseError> {
+    let mut alts = vec![vec![Vec::new()]];
+    let mut text = String::new();
+
+    loop {
+        let c = match chars.peek() {
+            None | Some(&']') => break,
+            Some(&c) => c,
+        };
+        chars.next();
+
+        let item = match c {
+            '\\' => {
+                text.push(match chars.next() {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some(c) => c,
+                    None => '\\',
+                });
+                continue;
+            }
+            '%' => {
+                let mut field = String::new();
+                loop {
+                    match chars.next() {
+                        Some('%') => break,
+                        Some(c) => field.push(c),
+                        None => return Err(ParseError::BadValue("unterminated `%' in format".to_owned())),
+                    }
+                }
+                match field.find(':') {
+                    Some(pos) => Item::Field(field[..pos].to_owned(), try!(parse_spec(&field[pos + 1..]))),
+                    None => Item::Field(field, Spec::default()),
+                }
+            }
+            '[' => {
+                let group = try!(parse_alts(chars));
+                if chars.next() != Some(']') {
+                    return Err(ParseError::BadValue("unterminated `[' in format".to_owned()));
+                }
+                Item::Group(group)
+            }
+            '|' => {
+                flush(&mut text, &mut alts);
+                alts.push(vec![Vec::new()]);
+                continue;
+            }
+            '&' => {
+                flush(&mut text, &mut alts);
+                alts.last_mut().unwrap().push(Vec::new());
+                continue;
+            }
+            c => {
+                text.push(c);
+                continue;
+            }
+        };
+
+        flush(&mut text, &mut alts);
+        alts.last_mut().unwrap().last_mut().unwrap().push(item);
+    }
+
+    flush(&mut text, &mut alts);
+    Ok(alts)
+}
+
+fn parse_spec(spec: &str) -> Result<Spec, ParseError> {
+    let bad_spec = || ParseError::BadValue(format!("invalid field spec `{}' in format", spec));
+
+    let (right, rest) = if spec.starts_with('>') {
+        (true, &spec[1..])
+    } else if spec.starts_with('<') {
+        (false, &spec[1..])
+    } else {
+        (false, spec)
+    };
+
+    let (width, max) = match rest.find('.') {
+        Some(pos) => (&rest[..pos], Some(&rest[pos + 1..])),
+        None => (rest, None),
+    };
+
+    Ok(Spec {
+        right: right,
+        width: if width.is_empty() { 0 } else { try!(width.parse().map_err(|_| bad_spec())) },
+        max: match max {
+            Some(max) => Some(try!(max.parse().map_err(|_| bad_spec()))),
+            None => None,
+        },
+    })
+}
+
+fn flush(text: &mut String, alts: &mut Alternatives) {
+    if !text.is_empty() {
+        let item = Item::Text(::std::mem::replace(text, String::new()));
+        alts.last_mut().unwrap().last_mut().unwrap().push(item);
+    }
+}
+
+/// Render the first alternative which renders to something
+///
+/// All parts joined with `&` must be non-empty, and groups must have
+/// some known field in them (plain text at the top level is always rendered).
+fn render_alts<F: Fields>(alts: &Alternatives, fields: &F, out: &mut String, group: bool) -> bool {
+    for parts in alts {
+        let start = out.len();
+        let rendered = parts.iter().all(|items| {
+            let part = out.len();
+            match render_items(items, fields, out) {
+                Some(found) => out.len() > part && (found || !group),
+                None => false,
+            }
+        });
+        if rendered {
+            return true;
+        }
+        out.truncate(start);
+    }
+    false
+}
+
+/// Render items, returns `None` if some field outside of groups is missing,
+/// or whether some field was rendered otherwise
+fn render_items<F: Fields>(items: &[Item], fields: &F, out: &mut String) -> Option<bool> {
+    let mut found = false;
+    for item in items {
+        match *item {
+            Item::Text(ref text) => out.push_str(text),
+            Item::Field(ref name, spec) => {
+                let value = fields.field(name)?;
+                push_field(out, &value, spec);
+                found = true;
+            }
+            Item::Group(ref alts) => found |= render_alts(alts, fields, out, true),
+        }
+    }
+    Some(found)
+}
+
+fn push_field(out: &mut String, value: &str, spec: Spec) {
+    let value = match spec.max {
+        Some(max) => {
+            match value.char_indices().nth(max) {
+                Some((end, _)) => &value[..end],
+                None => value,
+            }
+        }
+        None => value,
+    };
+
+    let padding = spec.width.saturating_sub(value.chars().count());
+    if spec.right {
+        out.extend((0..padding).map(|_| ' '));
+        out.push_str(value);
+    } else {
+        out.push_str(value);
+        out.extend((0..padding).map(|_| ' '));
+    }
+}
+// }}}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use song::{Id, QueuePlace, Song};
+    use status::{State, Status};
+    use time::Duration;
+
+    fn song() -> Song {
+        let mut song = Song::default();
+        song.file = "albums/Salad Days/01 Salad Days.flac".to_owned();
+        song.title = Some("Salad Days".to_owned());
+        song.tags.insert("Artist".to_owned(), "Mac DeMarco".to_owned());
+        song.duration = Some(Duration::milliseconds(145500));
+        song.place = Some(QueuePlace { id: Id(7), pos: 2, prio: 0 });
+        song
+    }
+
+    fn render(template: &str, song: &Song) -> String {
+        Template::parse(template).unwrap().render(song)
+    }
+
+    #[test]
+    fn groups() {
+        let mut song = song();
+        assert_eq!(render(DEFAULT, &song), "Mac DeMarco - Salad Days");
+        assert_eq!(render("[%album% - ]%title%", &song), "Salad Days");
+        assert_eq!(render("[[%artist%&%album%] - ]%title%", &song), "Salad Days");
+        assert_eq!(render("[x]%filename%", &song), "01 Salad Days.flac");
+        assert_eq!(render("%album%|%artist%", &song), "Mac DeMarco");
+        assert_eq!(render("%album%|nothing", &song), "nothing");
+        assert_eq!(render("\\[%position%\\] #%id%", &song), "[3] #7");
+
+        song.name = Some("Radio".to_owned());
+        assert_eq!(render(DEFAULT, &song), "Radio: Mac DeMarco - Salad Days");
+        song.tags.clear();
+        song.title = None;
+        assert_eq!(render(DEFAULT, &song), "Radio");
+    }
+
+    #[test]
+    fn specs() {
+        let song = song();
+        assert_eq!(render("%artist:15%/", &song), "Mac DeMarco    /");
+        assert_eq!(render("%artist:>15%/", &song), "    Mac DeMarco/");
+        assert_eq!(render("%artist:.3%/", &song), "Mac/");
+        assert_eq!(render("%artist:>5.3%/%time%", &song), "  Mac/2:25");
+        assert!(Template::parse("%artist:x%").is_err());
+        assert!(Template::parse("[%artist%").is_err());
+        assert!(Template::parse("%artist").is_err());
+    }
+
+    #[test]
+    fn status() {
+        let mut status = Status::default();
+        status.state = State::Play;
+        status.elapsed = Some(Duration::seconds(60));
+        status.duration = Some(Duration::seconds(4000));
+        status.volume = -1;
+        let template = Template::parse("\\[%state%\\] %elapsed%/%duration%[ vol %volume%\\%] %title%").unwrap();
+        assert_eq!(template.render(&(&song(), &status)), "[playing] 1:00/2:25 Salad Days");
+        assert_eq!(template.render(&status), "");
+        assert_eq!(Template::parse("%remaining% %percent%").unwrap().render(&status), "1:05:40 1");
+    }
+}
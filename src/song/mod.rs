@@ -0,0 +1,675 @@
+//! The module defines song structs and methods.
+
+pub mod format;
+
+use convert::FromIter;
+
+use error::{Error, ParseError};
+use raw::FromValue;
+use rustc_serialize::{Decodable, Decoder, Encodable, Encoder};
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use status::AudioFormat;
+use time::{Duration, Tm};
+
+/// Song ID
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Default)]
+pub struct Id(pub u32);
+
+impl Encodable for Id {
+    fn encode<S: Encoder>(&self, e: &mut S) -> Result<(), S::Error> {
+        self.0.encode(e)
+    }
+}
+
+impl Decodable for Id {
+    fn decode<S: Decoder>(d: &mut S) -> Result<Id, S::Error> {
+        d.read_u32().map(Id)
+    }
+}
+
+impl fmt::Display for Id {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Song place in the queue
+#[derive(Debug, Copy, Clone, PartialEq, Default, RustcEncodable)]
+pub struct QueuePlace {
+    /// song ID
+    pub id: Id,
+    /// absolute zero-based song position
+    pub pos: u32,
+    /// song priority, if present, defaults to 0
+    pub prio: u8,
+}
+
+impl QueuePlace {
+    /// Set position of a (possibly missing) queue place, parsing it from response value
+    ///
+    /// Queue place is sent as several fields (like `Pos` and `Id`),
+    /// so these functions are used to build one value from them.
+    pub fn merge_pos(place: &mut Option<QueuePlace>, value: &str) -> Result<(), Error> {
+        place.get_or_insert_with(QueuePlace::default).pos = value.parse()?;
+        Ok(())
+    }
+
+    /// Set ID of a (possibly missing) queue place, parsing it from response value
+    pub fn merge_id(place: &mut Option<QueuePlace>, value: &str) -> Result<(), Error> {
+        place.get_or_insert_with(QueuePlace::default).id = Id(value.parse()?);
+        Ok(())
+    }
+
+    /// Set priority of a (possibly missing) queue place, parsing it from response value
+    pub fn merge_prio(place: &mut Option<QueuePlace>, value: &str) -> Result<(), Error> {
+        place.get_or_insert_with(QueuePlace::default).prio = value.parse()?;
+        Ok(())
+    }
+}
+
+/// Position to insert or move songs to in the queue
+///
+/// Relative positions are resolved by MPD against the current song when
+/// the command runs, so they don't race with other clients changing the queue.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum QueuePosition {
+    /// absolute zero-based position
+    Absolute(u32),
+    /// position after the current song (`AfterCurrent(0)` is right after it, i.e. "play next")
+    AfterCurrent(u32),
+    /// position before the current song (`BeforeCurrent(0)` is right before it)
+    BeforeCurrent(u32),
+}
+
+impl QueuePosition {
+    /// Check if position is relative to the current song
+    pub fn is_relative(&self) -> bool {
+        match *self {
+            QueuePosition::Absolute(_) => false,
+            _ => true,
+        }
+    }
+}
+
+impl fmt::Display for QueuePosition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            QueuePosition::Absolute(pos) => pos.fmt(f),
+            QueuePosition::AfterCurrent(pos) => write!(f, "+{}", pos),
+            QueuePosition::BeforeCurrent(pos) => write!(f, "-{}", pos),
+        }
+    }
+}
+
+/// Song range
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Range(pub Duration, pub Option<Duration>);
+
+impl Encodable for Range {
+    fn encode<S: Encoder>(&self, e: &mut S) -> Result<(), S::Error> {
+        e.emit_tuple(2, |e| {
+            e.emit_tuple_arg(0, |e| e.emit_i64(self.0.num_seconds()))?;
+            e.emit_tuple_arg(1, |e| {
+                e.emit_option(|e| match self.1 {
+                    Some(d) => e.emit_option_some(|e| d.num_seconds().encode(e)),
+                    None => e.emit_option_none(),
+                })
+            })
+        })
+    }
+}
+
+impl Default for Range {
+    fn default() -> Range {
+        Range(Duration::seconds(0), None)
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.num_seconds().fmt(f)?;
+        f.write_str(":")?;
+        if let Some(v) = self.1 {
+            v.num_seconds().fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Range {
+    type Err = ParseError;
+    fn from_str(s: &str) -> Result<Range, ParseError> {
+        let mut splits = s.split('-').flat_map(|v| v.parse().into_iter());
+        match (splits.next(), splits.next()) {
+            (Some(s), Some(e)) => Ok(Range(Duration::seconds(s), Some(Duration::seconds(e)))),
+            (None, Some(e)) => Ok(Range(Duration::zero(), Some(Duration::seconds(e)))),
+            (Some(s), None) => Ok(Range(Duration::seconds(s), None)),
+            (None, None) => Ok(Range(Duration::zero(), None)),
+        }
+    }
+}
+
+/// Tag types requested by a connection
+///
+/// MPD allows a client to limit tags it receives with `tagtypes` commands
+/// (see `Client::tagtypes_disable()` and friends), so a tag missing from
+/// a song can mean either it's absent, or it wasn't requested.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TagMask {
+    /// all tags are requested (the default)
+    All,
+    /// only these tags (lowercased) are requested
+    Only(Arc<BTreeSet<String>>),
+}
+
+impl Default for TagMask {
+    fn default() -> TagMask {
+        TagMask::All
+    }
+}
+
+impl TagMask {
+    /// Build mask from a list of requested tag names
+    pub fn only<I, S>(tags: I) -> TagMask
+        where I: IntoIterator<Item = S>,
+              S: AsRef<str>
+    {
+        TagMask::Only(Arc::new(tags.into_iter().map(|t| t.as_ref().to_lowercase()).collect()))
+    }
+
+    /// Check if tag is requested (case insensitive)
+    pub fn is_requested(&self, tag: &str) -> bool {
+        match *self {
+            TagMask::All => true,
+            TagMask::Only(ref tags) => tags.contains(&*tag.to_lowercase()),
+        }
+    }
+}
+
+/// Song tag lookup result
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tag<'a> {
+    /// tag is present
+    Value(&'a str),
+    /// tag was requested, but song doesn't have it
+    Absent,
+    /// tag was not requested by the connection, so it's unknown if song has it
+    NotRequested,
+}
+
+impl<'a> Tag<'a> {
+    /// Get tag value, if it's present
+    pub fn value(self) -> Option<&'a str> {
+        match self {
+            Tag::Value(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+/// Song data
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Song {
+    /// filename
+    pub file: String,
+    /// name (for streams)
+    pub name: Option<String>,
+    /// title
+    pub title: Option<String>,
+    /// last modification time
+    pub last_mod: Option<Tm>,
+    /// time the song was added to the database (MPD 0.24+)
+    pub added: Option<Tm>,
+    /// duration (with millisecond precision, if server sends `duration` field)
+    pub duration: Option<Duration>,
+    /// audio format of the file
+    pub format: Option<AudioFormat>,
+    /// place in the queue (if queued for playback)
+    pub place: Option<QueuePlace>,
+    /// range to play (if queued for playback and range was set)
+    pub range: Option<Range>,
+    /// arbitrary tags, like album, artist etc
+    pub tags: BTreeMap<String, String>,
+    /// other file metadata, not known to be tags and not parsed into fields
+    pub metadata: BTreeMap<String, String>,
+    /// tags requested by the connection the song was received with
+    pub tag_mask: TagMask,
+}
+
+impl Song {
+    /// Look up tag by name (case insensitive)
+    ///
+    /// Unlike looking up `tags` directly, this method tells if missing tag
+    /// is absent from the song, or just not requested by the connection.
+    pub fn tag(&self, name: &str) -> Tag {
+        let value = if name.eq_ignore_ascii_case("title") {
+            self.title.as_ref()
+        } else if name.eq_ignore_ascii_case("name") {
+            self.name.as_ref()
+        } else {
+            self.tags.iter().find(|&(k, _)| k.eq_ignore_ascii_case(name)).map(|(_, v)| v)
+        };
+
+        match value {
+            Some(v) => Tag::Value(v),
+            None if self.tag_mask.is_requested(name) => Tag::Absent,
+            None => Tag::NotRequested,
+        }
+    }
+
+    /// Move fields, which are tag types supported by server, from `metadata` to `tags`
+    ///
+    /// Songs are parsed with tags known to this crate (see `is_tag()`), `Client`
+    /// calls this method with server tag types once capabilities are probed.
+    pub fn move_tags(&mut self, tagtypes: &BTreeSet<String>) {
+        if tagtypes.is_empty() {
+            return;
+        }
+        let tags = self.metadata.keys().filter(|key| is_tag(key, tagtypes)).cloned().collect::<Vec<_>>();
+        for key in tags {
+            let value = self.metadata.remove(&key).unwrap();
+            self.tags.insert(key, value);
+        }
+    }
+}
+
+impl Encodable for Song {
+    fn encode<S: Encoder>(&self, e: &mut S) -> Result<(), S::Error> {
+        e.emit_struct("Song", 11, |e| {
+            e.emit_struct_field("file", 0, |e| self.file.encode(e))?;
+            e.emit_struct_field("name", 1, |e| self.name.encode(e))?;
+            e.emit_struct_field("title", 2, |e| self.title.encode(e))?;
+            e.emit_struct_field("last_mod", 3, |e| {
+                    e.emit_option(|e| match self.last_mod {
+                        Some(m) => e.emit_option_some(|e| m.to_timespec().sec.encode(e)),
+                        None => e.emit_option_none(),
+                    })
+                })?;
+            e.emit_struct_field("duration", 4, |e| {
+                    e.emit_option(|e| match self.duration {
+                        Some(d) => e.emit_option_some(|e| d.num_seconds().encode(e)),
+                        None => e.emit_option_none(),
+                    })
+                })?;
+            e.emit_struct_field("place", 5, |e| self.place.encode(e))?;
+            e.emit_struct_field("range", 6, |e| self.range.encode(e))?;
+            e.emit_struct_field("tags", 7, |e| self.tags.encode(e))?;
+            e.emit_struct_field("added", 8, |e| {
+                    e.emit_option(|e| match self.added {
+                        Some(m) => e.emit_option_some(|e| m.to_timespec().sec.encode(e)),
+                        None => e.emit_option_none(),
+                    })
+                })?;
+            e.emit_struct_field("format", 9, |e| self.format.encode(e))?;
+            e.emit_struct_field("metadata", 10, |e| self.metadata.encode(e))?;
+            Ok(())
+        })
+    }
+}
+
+impl FromIter for Song {
+    /// update song from a reply pair
+    fn feed(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        match key {
+            "file" => self.file = value.to_owned(),
+            "Title" => self.title = Some(value.to_owned()),
+            "Last-Modified" => self.last_mod = Some(Tm::from_value(value)?),
+            "added" => self.added = Some(Tm::from_value(value)?),
+            "Name" => self.name = Some(value.to_owned()),
+            // `Time` is rounded to seconds, so it's used only if there's no `duration`
+            "Time" => {
+                if self.duration.is_none() {
+                    self.duration = Some(Duration::seconds(value.parse()?))
+                }
+            }
+            "duration" => self.duration = Some(Duration::from_value(value)?),
+            // the format is informational, so values not understood by `AudioFormat` are not an error
+            "Format" => self.format = value.parse().ok(),
+            "Range" => self.range = Some(try!(value.parse())),
+            "Id" => QueuePlace::merge_id(&mut self.place, value)?,
+            "Pos" => QueuePlace::merge_pos(&mut self.place, value)?,
+            "Prio" => QueuePlace::merge_prio(&mut self.place, value)?,
+            _ if is_tag(key, &BTreeSet::new()) => {
+                self.tags.insert(key.to_owned(), value.to_owned());
+            }
+            _ => {
+                self.metadata.insert(key.to_owned(), value.to_owned());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Tag types known to MPD (as of 0.24)
+pub const TAGS: &'static [&'static str] = &["Artist",
+                                            "ArtistSort",
+                                            "Album",
+                                            "AlbumSort",
+                                            "AlbumArtist",
+                                            "AlbumArtistSort",
+                                            "Title",
+                                            "TitleSort",
+                                            "Track",
+                                            "Name",
+                                            "Genre",
+                                            "Mood",
+                                            "Date",
+                                            "OriginalDate",
+                                            "Composer",
+                                            "ComposerSort",
+                                            "Performer",
+                                            "Conductor",
+                                            "Work",
+                                            "Ensemble",
+                                            "Movement",
+                                            "MovementNumber",
+                                            "ShowMovement",
+                                            "Location",
+                                            "Grouping",
+                                            "Comment",
+                                            "Disc",
+                                            "Label",
+                                            "MUSICBRAINZ_ARTISTID",
+                                            "MUSICBRAINZ_ALBUMID",
+                                            "MUSICBRAINZ_ALBUMARTISTID",
+                                            "MUSICBRAINZ_TRACKID",
+                                            "MUSICBRAINZ_RELEASETRACKID",
+                                            "MUSICBRAINZ_RELEASEGROUPID",
+                                            "MUSICBRAINZ_WORKID"];
+
+/// Check if song field is a tag (case insensitive)
+///
+/// Tags are looked up in `TAGS` list and in tag types supported by server
+/// (see `Capabilities::tagtypes`), so tags added in MPD versions newer than
+/// this crate knows are recognized once server capabilities are probed.
+pub fn is_tag(key: &str, tagtypes: &BTreeSet<String>) -> bool {
+    TAGS.iter().any(|t| t.eq_ignore_ascii_case(key)) || tagtypes.iter().any(|t| t.eq_ignore_ascii_case(key))
+}
+
+/// Check if song field is file metadata (not a tag, nor a song field, like `file` or `Pos`)
+fn is_metadata(key: &str, tagtypes: &BTreeSet<String>) -> bool {
+    match key {
+        "file" | "Last-Modified" | "added" | "Time" | "duration" | "Format" | "Range" | "Id" | "Pos" | "Prio" => false,
+        _ => !is_tag(key, tagtypes),
+    }
+}
+
+fn is_other_tag(key: &str, tagtypes: &BTreeSet<String>) -> bool {
+    key != "Title" && key != "Name" && is_tag(key, tagtypes)
+}
+
+/// Borrowed song view
+///
+/// It's produced by `Client::for_each_song()` and borrows all the data
+/// from a buffer reused for every song in a reply. Accessors mirror `Song`
+/// fields, values are parsed on access, and values which fail to parse
+/// are reported as missing. Use `to_song()` to get an owned `Song`.
+#[derive(Debug, Clone, Copy)]
+pub struct SongRef<'a> {
+    text: &'a str,
+    spans: &'a [(usize, usize, usize)],
+    tag_mask: &'a TagMask,
+    tagtypes: &'a BTreeSet<String>,
+}
+
+impl<'a> SongRef<'a> {
+    /// Iterate over all `key: value` pairs of the song
+    pub fn pairs(&self) -> SongRefPairs<'a> {
+        SongRefPairs {
+            text: self.text,
+            spans: self.spans.iter(),
+            tagtypes: self.tagtypes,
+            filter: |_, _| true,
+        }
+    }
+
+    /// Get first value of a field by exact key
+    pub fn get(&self, key: &str) -> Option<&'a str> {
+        self.pairs().find(|&(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    /// filename
+    pub fn file(&self) -> &'a str {
+        self.get("file").unwrap_or("")
+    }
+
+    /// name (for streams)
+    pub fn name(&self) -> Option<&'a str> {
+        self.get("Name")
+    }
+
+    /// title
+    pub fn title(&self) -> Option<&'a str> {
+        self.get("Title")
+    }
+
+    /// last modification time
+    pub fn last_mod(&self) -> Option<Tm> {
+        self.get("Last-Modified").and_then(|v| Tm::from_value(v).ok())
+    }
+
+    /// time the song was added to the database (MPD 0.24+)
+    pub fn added(&self) -> Option<Tm> {
+        self.get("added").and_then(|v| Tm::from_value(v).ok())
+    }
+
+    /// duration (with millisecond precision, if server sends `duration` field)
+    pub fn duration(&self) -> Option<Duration> {
+        self.get("duration")
+            .and_then(|v| Duration::from_value(v).ok())
+            .or_else(|| self.get("Time").and_then(|v| v.parse().ok()).map(Duration::seconds))
+    }
+
+    /// audio format of the file
+    pub fn format(&self) -> Option<AudioFormat> {
+        self.get("Format").and_then(|v| v.parse().ok())
+    }
+
+    /// place in the queue (if queued for playback)
+    pub fn place(&self) -> Option<QueuePlace> {
+        let mut place = None;
+        for (key, value) in self.pairs() {
+            let result = match key {
+                "Id" => QueuePlace::merge_id(&mut place, value),
+                "Pos" => QueuePlace::merge_pos(&mut place, value),
+                "Prio" => QueuePlace::merge_prio(&mut place, value),
+                _ => Ok(()),
+            };
+            if result.is_err() {
+                return None;
+            }
+        }
+        place
+    }
+
+    /// range to play (if queued for playback and range was set)
+    pub fn range(&self) -> Option<Range> {
+        self.get("Range").and_then(|v| v.parse().ok())
+    }
+
+    /// Iterate over arbitrary tags, like album, artist etc
+    pub fn tags(&self) -> SongRefPairs<'a> {
+        SongRefPairs {
+            filter: is_other_tag,
+            ..self.pairs()
+        }
+    }
+
+    /// Iterate over other file metadata, see `Song::metadata`
+    pub fn metadata(&self) -> SongRefPairs<'a> {
+        SongRefPairs {
+            filter: is_metadata,
+            ..self.pairs()
+        }
+    }
+
+    /// Look up tag by name (case insensitive), see `Song::tag()`
+    pub fn tag(&self, name: &str) -> Tag<'a> {
+        let value = if name.eq_ignore_ascii_case("title") {
+            self.title()
+        } else if name.eq_ignore_ascii_case("name") {
+            self.name()
+        } else {
+            self.tags().find(|&(k, _)| k.eq_ignore_ascii_case(name)).map(|(_, v)| v)
+        };
+
+        match value {
+            Some(v) => Tag::Value(v),
+            None if self.tag_mask.is_requested(name) => Tag::Absent,
+            None => Tag::NotRequested,
+        }
+    }
+
+    /// Build owned song
+    pub fn to_song(&self) -> Result<Song, Error> {
+        let mut song = Song::default();
+        for (key, value) in self.pairs() {
+            song.feed(key, value)?;
+        }
+        song.move_tags(self.tagtypes);
+        song.tag_mask = self.tag_mask.clone();
+        Ok(song)
+    }
+}
+
+/// Iterator over `SongRef` pairs (or some of them, like tags)
+pub struct SongRefPairs<'a> {
+    text: &'a str,
+    spans: ::std::slice::Iter<'a, (usize, usize, usize)>,
+    tagtypes: &'a BTreeSet<String>,
+    filter: fn(&str, &BTreeSet<String>) -> bool,
+}
+
+impl<'a> Iterator for SongRefPairs<'a> {
+    type Item = (&'a str, &'a str);
+    fn next(&mut self) -> Option<(&'a str, &'a str)> {
+        while let Some(&(k, v, e)) = self.spans.next() {
+            let key = &self.text[k..v];
+            if (self.filter)(key, self.tagtypes) {
+                return Some((key, &self.text[v..e]));
+            }
+        }
+        None
+    }
+}
+
+/// Reusable buffer for song pairs, `SongRef`s borrow from it
+#[derive(Debug, Default)]
+pub(crate) struct SongBuf {
+    text: String,
+    spans: Vec<(usize, usize, usize)>,
+}
+
+impl SongBuf {
+    pub fn push(&mut self, key: &str, value: &str) {
+        let start = self.text.len();
+        self.text.push_str(key);
+        self.text.push_str(value);
+        self.spans.push((start, start + key.len(), self.text.len()));
+    }
+
+    pub fn clear(&mut self) {
+        self.text.clear();
+        self.spans.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    pub fn get<'a>(&'a self, tag_mask: &'a TagMask, tagtypes: &'a BTreeSet<String>) -> SongRef<'a> {
+        SongRef {
+            text: &self.text,
+            spans: &self.spans,
+            tag_mask: tag_mask,
+            tagtypes: tagtypes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use status::SampleFormat;
+
+    #[test]
+    fn tag_lookup() {
+        let mut song = Song::default();
+        song.title = Some("Salad Days".to_owned());
+        song.tags.insert("Artist".to_owned(), "Mac DeMarco".to_owned());
+        song.tag_mask = TagMask::only(&["artist", "title"]);
+
+        assert_eq!(song.tag("title"), Tag::Value("Salad Days"));
+        assert_eq!(song.tag("ARTIST"), Tag::Value("Mac DeMarco"));
+        assert_eq!(song.tag("Title").value(), Some("Salad Days"));
+        song.tags.clear();
+        assert_eq!(song.tag("Artist"), Tag::Absent);
+        assert_eq!(song.tag("Album"), Tag::NotRequested);
+    }
+
+    #[test]
+    fn borrowed_song() {
+        let mut buf = SongBuf::default();
+        for &(k, v) in &[("file", "a.flac"),
+                         ("Time", "241"),
+                         ("duration", "240.822"),
+                         ("Format", "44100:24:2"),
+                         ("Artist", "Air"),
+                         ("ReplayGain", "-3.1"),
+                         ("Pos", "3"),
+                         ("Id", "7")] {
+            buf.push(k, v);
+        }
+        let mask = TagMask::only(&["artist"]);
+        let tagtypes = BTreeSet::new();
+        let song = buf.get(&mask, &tagtypes);
+
+        assert_eq!(song.file(), "a.flac");
+        assert_eq!(song.duration(), Some(Duration::milliseconds(240822)));
+        assert_eq!(song.format().map(|f| f.sample), Some(SampleFormat::Bits(24)));
+        assert_eq!(song.tag("artist"), Tag::Value("Air"));
+        assert_eq!(song.tag("Album"), Tag::NotRequested);
+        assert_eq!(song.tags().collect::<Vec<_>>(), vec![("Artist", "Air")]);
+        assert_eq!(song.metadata().collect::<Vec<_>>(), vec![("ReplayGain", "-3.1")]);
+        assert_eq!(song.place().map(|p| (p.pos, p.id)), Some((3, Id(7))));
+
+        let owned = song.to_song().unwrap();
+        assert_eq!(owned.file, "a.flac");
+        assert_eq!(owned.tags.len(), 1);
+        assert_eq!(owned.metadata.len(), 1);
+        assert_eq!(owned.duration, Some(Duration::milliseconds(240822)));
+    }
+
+    #[test]
+    fn server_tagtypes() {
+        let mut buf = SongBuf::default();
+        for &(k, v) in &[("file", "a.flac"), ("Artist", "Air"), ("Language", "fr"), ("ReplayGain", "-3.1")] {
+            buf.push(k, v);
+        }
+        let mask = TagMask::All;
+        let tagtypes = BTreeSet::new();
+        assert_eq!(buf.get(&mask, &tagtypes).metadata().count(), 2);
+
+        let tagtypes = vec!["Artist".to_owned(), "language".to_owned()].into_iter().collect();
+        let song = buf.get(&mask, &tagtypes);
+        assert_eq!(song.tags().collect::<Vec<_>>(), vec![("Artist", "Air"), ("Language", "fr")]);
+        assert_eq!(song.metadata().collect::<Vec<_>>(), vec![("ReplayGain", "-3.1")]);
+        assert_eq!(song.tag("language"), Tag::Value("fr"));
+
+        let owned = song.to_song().unwrap();
+        assert_eq!(owned.tags.get("Language").map(|v| &**v), Some("fr"));
+        assert_eq!(owned.metadata.len(), 1);
+    }
+
+    #[test]
+    fn queue_positions() {
+        assert_eq!(QueuePosition::Absolute(3).to_string(), "3");
+        assert_eq!(QueuePosition::AfterCurrent(0).to_string(), "+0");
+        assert_eq!(QueuePosition::BeforeCurrent(1).to_string(), "-1");
+        assert!(!QueuePosition::Absolute(0).is_relative());
+    }
+}
@@ -1,13 +1,11 @@
 //! The module describes DB and playback statistics
 
-use convert::FromIter;
-
-use error::Error;
 use rustc_serialize::{Encodable, Encoder};
 use time::{Duration, Timespec};
 
 /// DB and playback statistics
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, FromResponse)]
+#[mpd(default)]
 pub struct Stats {
     /// number of artists in DB
     pub artists: u32,
@@ -25,6 +23,40 @@ pub struct Stats {
     pub db_update: Timespec,
 }
 
+impl Stats {
+    /// Change of DB statistics since an older snapshot
+    pub fn delta(&self, old: &Stats) -> StatsDelta {
+        StatsDelta {
+            artists: self.artists as i64 - old.artists as i64,
+            albums: self.albums as i64 - old.albums as i64,
+            songs: self.songs as i64 - old.songs as i64,
+            db_playtime: self.db_playtime - old.db_playtime,
+        }
+    }
+}
+
+/// Change of DB statistics (e.g. after DB update)
+///
+/// Values are net changes: positive if more items were added than removed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StatsDelta {
+    /// change of number of artists
+    pub artists: i64,
+    /// change of number of albums
+    pub albums: i64,
+    /// change of number of songs
+    pub songs: i64,
+    /// change of total playback time for all songs in DB
+    pub db_playtime: Duration,
+}
+
+impl StatsDelta {
+    /// Check if nothing changed
+    pub fn is_empty(&self) -> bool {
+        self.artists == 0 && self.albums == 0 && self.songs == 0 && self.db_playtime == Duration::zero()
+    }
+}
+
 impl Encodable for Stats {
     fn encode<S: Encoder>(&self, e: &mut S) -> Result<(), S::Error> {
         e.emit_struct("Stats", 7, |e| {
@@ -54,25 +86,16 @@ impl Default for Stats {
     }
 }
 
-impl FromIter for Stats {
-    /// build stats from iterator
-    fn from_iter<I: Iterator<Item = Result<(String, String), Error>>>(iter: I) -> Result<Stats, Error> {
-        let mut result = Stats::default();
-
-        for res in iter {
-            let line = try!(res);
-            match &*line.0 {
-                "artists" => result.artists = try!(line.1.parse()),
-                "albums" => result.albums = try!(line.1.parse()),
-                "songs" => result.songs = try!(line.1.parse()),
-                "uptime" => result.uptime = Duration::seconds(try!(line.1.parse())),
-                "playtime" => result.playtime = Duration::seconds(try!(line.1.parse())),
-                "db_playtime" => result.db_playtime = Duration::seconds(try!(line.1.parse())),
-                "db_update" => result.db_update = Timespec::new(try!(line.1.parse()), 0),
-                _ => (),
-            }
-        }
+#[cfg(test)]
+mod test {
+    use super::*;
 
-        Ok(result)
+    #[test]
+    fn stats_delta() {
+        let old = Stats { artists: 10, albums: 20, songs: 200, ..Stats::default() };
+        let new = Stats { artists: 9, albums: 21, songs: 215, db_playtime: Duration::seconds(60), ..old };
+        assert!(old.delta(&old).is_empty());
+        assert_eq!(new.delta(&old),
+                   StatsDelta { artists: -1, albums: 1, songs: 15, db_playtime: Duration::seconds(60) });
     }
 }
@@ -1,16 +1,16 @@
 //! The module defines MPD status data structures
 
-use convert::FromIter;
-
 use error::{Error, ParseError};
 use rustc_serialize::{Encodable, Encoder};
-use song::{Id, QueuePlace};
+use song::QueuePlace;
 use std::fmt;
+use std::slice;
 use std::str::FromStr;
 use time::Duration;
 
 /// MPD status
-#[derive(Debug, PartialEq, Clone, Default)]
+#[derive(Debug, PartialEq, Clone, Default, FromResponse)]
+#[mpd(default)]
 pub struct Status {
     /// volume (0-100, or -1 if volume is unavailable (e.g. for HTTPD output type)
     pub volume: i8,
@@ -23,16 +23,23 @@ pub struct Status {
     /// consume mode
     pub consume: bool,
     /// queue version number
+    #[mpd(key = "playlist")]
     pub queue_version: u32,
     /// queue length
+    #[mpd(key = "playlistlength")]
     pub queue_len: u32,
     /// playback state
     pub state: State,
     /// currently playing song place in the queue
+    #[mpd(key = "song", merge_with = "QueuePlace::merge_pos")]
+    #[mpd(key = "songid", merge_with = "QueuePlace::merge_id")]
     pub song: Option<QueuePlace>,
     /// next song to play place in the queue
+    #[mpd(key = "nextsong", merge_with = "QueuePlace::merge_pos")]
+    #[mpd(key = "nextsongid", merge_with = "QueuePlace::merge_id")]
     pub nextsong: Option<QueuePlace>,
     /// time current song played, and total song duration (in seconds resolution)
+    #[mpd(parse_with = "parse_time")]
     pub time: Option<(Duration, Duration)>,
     /// elapsed play time current song played (in milliseconds resolution)
     pub elapsed: Option<Duration>,
@@ -41,10 +48,13 @@ pub struct Status {
     /// current song bitrate, kbps
     pub bitrate: Option<u32>,
     /// crossfade timeout, seconds
+    #[mpd(key = "xfade")]
     pub crossfade: Option<Duration>,
     /// mixramp threshold, dB
+    #[mpd(skip)]
     pub mixrampdb: f32,
     /// mixramp duration, seconds
+    #[mpd(skip)]
     pub mixrampdelay: Option<Duration>,
     /// current audio playback format
     pub audio: Option<AudioFormat>,
@@ -53,6 +63,7 @@ pub struct Status {
     /// last player error (if happened, can be reset with `clearerror()` method)
     pub error: Option<String>,
     /// replay gain mode
+    #[mpd(key = "replay_gain_mode")]
     pub replaygain: Option<ReplayGain>,
 }
 
@@ -120,135 +131,378 @@ impl Encodable for Status {
     }
 }
 
-impl FromIter for Status {
-    fn from_iter<I: Iterator<Item = Result<(String, String), Error>>>(iter: I) -> Result<Status, Error> {
-        let mut result = Status::default();
-
-        for res in iter {
-            let line = try!(res);
-            match &*line.0 {
-                "volume" => result.volume = try!(line.1.parse()),
-
-                "repeat" => result.repeat = &*line.1 == "1",
-                "random" => result.random = &*line.1 == "1",
-                "single" => result.single = &*line.1 == "1",
-                "consume" => result.consume = &*line.1 == "1",
-
-                "playlist" => result.queue_version = try!(line.1.parse()),
-                "playlistlength" => result.queue_len = try!(line.1.parse()),
-                "state" => result.state = try!(line.1.parse()),
-                "songid" => {
-                    match result.song {
-                        None => {
-                            result.song = Some(QueuePlace {
-                                id: Id(try!(line.1.parse())),
-                                pos: 0,
-                                prio: 0,
-                            })
-                        }
-                        Some(ref mut place) => place.id = Id(try!(line.1.parse())),
-                    }
-                }
-                "song" => {
-                    match result.song {
-                        None => {
-                            result.song = Some(QueuePlace {
-                                pos: try!(line.1.parse()),
-                                id: Id(0),
-                                prio: 0,
-                            })
-                        }
-                        Some(ref mut place) => place.pos = try!(line.1.parse()),
-                    }
-                }
-                "nextsongid" => {
-                    match result.nextsong {
-                        None => {
-                            result.nextsong = Some(QueuePlace {
-                                id: Id(try!(line.1.parse())),
-                                pos: 0,
-                                prio: 0,
-                            })
-                        }
-                        Some(ref mut place) => place.id = Id(try!(line.1.parse())),
-                    }
-                }
-                "nextsong" => {
-                    match result.nextsong {
-                        None => {
-                            result.nextsong = Some(QueuePlace {
-                                pos: try!(line.1.parse()),
-                                id: Id(0),
-                                prio: 0,
-                            })
-                        }
-                        Some(ref mut place) => place.pos = try!(line.1.parse()),
-                    }
-                }
-                "time" => {
-                    result.time = try!({
-                        let mut splits = line.1.splitn(2, ':').map(|v| v.parse().map_err(ParseError::BadInteger).map(Duration::seconds));
-                        match (splits.next(), splits.next()) {
-                            (Some(Ok(a)), Some(Ok(b))) => Ok(Some((a, b))),
-                            (Some(Err(e)), _) |
-                            (_, Some(Err(e))) => Err(e),
-                            _ => Ok(None),
-                        }
-                    })
-                }
-                // TODO" => float errors don't work on stable
-                "elapsed" => {
-                    result.elapsed = line.1
-                        .parse::<f32>()
-                        .ok()
-                        .map(|v| Duration::milliseconds((v * 1000.0) as i64))
+fn parse_time(value: &str) -> Result<(Duration, Duration), Error> {
+    let mut splits = value.splitn(2, ':').map(|v| v.parse().map_err(ParseError::BadInteger).map(Duration::seconds));
+    match (splits.next(), splits.next()) {
+        (Some(Ok(a)), Some(Ok(b))) => Ok((a, b)),
+        (Some(Err(e)), _) |
+        (_, Some(Err(e))) => Err(Error::Parse(e)),
+        _ => Err(Error::Parse(ParseError::BadValue(value.to_owned()))),
+    }
+}
+
+// Status diff {{{
+impl Status {
+    /// Find out what changed since an older status snapshot
+    ///
+    /// Playback progress (`time`, `elapsed` and `bitrate`) is not reported,
+    /// as it changes all the time while playing.
+    pub fn diff(&self, old: &Status) -> StatusDiff {
+        let mut changes = Vec::new();
+        {
+            let mut changed = |field: bool, change: StatusChange| {
+                if field {
+                    changes.push(change);
                 }
-                "duration" => result.duration = Some(Duration::seconds(try!(line.1.parse()))),
-                "bitrate" => result.bitrate = Some(try!(line.1.parse())),
-                "xfade" => result.crossfade = Some(Duration::seconds(try!(line.1.parse()))),
-                // "mixrampdb" => 0.0, //get_field!(map, "mixrampdb"),
-                // "mixrampdelay" => None, //get_field!(map, opt "mixrampdelay").map(|v: f64| Duration::milliseconds((v * 1000.0) as i64)),
-                "audio" => result.audio = Some(try!(line.1.parse())),
-                "updating_db" => result.updating_db = Some(try!(line.1.parse())),
-                "error" => result.error = Some(line.1.to_owned()),
-                "replay_gain_mode" => result.replaygain = Some(try!(line.1.parse())),
-                _ => (),
+            };
+            changed(self.state != old.state, StatusChange::State(self.state));
+            changed(self.song != old.song, StatusChange::Song(self.song));
+            changed(self.nextsong != old.nextsong, StatusChange::NextSong(self.nextsong));
+            changed(self.duration != old.duration, StatusChange::Duration(self.duration));
+            changed(self.queue_version != old.queue_version || self.queue_len != old.queue_len,
+                    StatusChange::Queue {
+                        version: self.queue_version,
+                        len: self.queue_len,
+                    });
+            changed(self.volume != old.volume, StatusChange::Volume(self.volume));
+            changed(self.repeat != old.repeat, StatusChange::Repeat(self.repeat));
+            changed(self.random != old.random, StatusChange::Random(self.random));
+            changed(self.single != old.single, StatusChange::Single(self.single));
+            changed(self.consume != old.consume, StatusChange::Consume(self.consume));
+            changed(self.crossfade != old.crossfade, StatusChange::Crossfade(self.crossfade));
+            changed(self.mixrampdb != old.mixrampdb, StatusChange::MixRampDb(self.mixrampdb));
+            changed(self.mixrampdelay != old.mixrampdelay, StatusChange::MixRampDelay(self.mixrampdelay));
+            changed(self.replaygain != old.replaygain, StatusChange::ReplayGain(self.replaygain));
+            changed(self.audio != old.audio, StatusChange::Audio(self.audio));
+        }
+
+        match (&old.error, &self.error) {
+            (&Some(ref old), &Some(ref new)) if old == new => (),
+            (_, &Some(ref new)) => changes.push(StatusChange::Error(new.clone())),
+            (&Some(_), &None) => changes.push(StatusChange::ErrorCleared),
+            (&None, &None) => (),
+        }
+
+        if old.updating_db != self.updating_db {
+            if let Some(job) = old.updating_db {
+                changes.push(StatusChange::UpdateFinished(job));
+            }
+            if let Some(job) = self.updating_db {
+                changes.push(StatusChange::UpdateStarted(job));
             }
         }
 
-        Ok(result)
+        StatusDiff { changes: changes }
     }
 }
 
+/// Changed status field with its new value
+#[derive(Debug, Clone, PartialEq)]
+pub enum StatusChange {
+    /// playback state
+    State(State),
+    /// current song (or its place in the queue)
+    Song(Option<QueuePlace>),
+    /// next song
+    NextSong(Option<QueuePlace>),
+    /// current song duration
+    Duration(Option<Duration>),
+    /// queue contents
+    Queue {
+        /// new queue version
+        version: u32,
+        /// new queue length
+        len: u32,
+    },
+    /// volume
+    Volume(i8),
+    /// repeat mode
+    Repeat(bool),
+    /// random mode
+    Random(bool),
+    /// single mode
+    Single(bool),
+    /// consume mode
+    Consume(bool),
+    /// crossfade timeout
+    Crossfade(Option<Duration>),
+    /// mixramp threshold
+    MixRampDb(f32),
+    /// mixramp duration
+    MixRampDelay(Option<Duration>),
+    /// replay gain mode
+    ReplayGain(Option<ReplayGain>),
+    /// audio playback format (changes when song format or outputs change)
+    Audio(Option<AudioFormat>),
+    /// new player error
+    Error(String),
+    /// player error was cleared
+    ErrorCleared,
+    /// database update job started
+    UpdateStarted(u32),
+    /// database update job finished
+    UpdateFinished(u32),
+}
+
+/// Changes between two status snapshots, in the order of `StatusChange` variants
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct StatusDiff {
+    /// changed fields
+    pub changes: Vec<StatusChange>,
+}
+
+impl StatusDiff {
+    /// Check if nothing changed
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Iterate over changes
+    pub fn iter(&self) -> slice::Iter<StatusChange> {
+        self.changes.iter()
+    }
+
+    /// Check if playback options (repeat, random, single, consume, crossfade, mixramp or replay gain) changed
+    pub fn options(&self) -> bool {
+        self.iter().any(|change| match *change {
+            StatusChange::Repeat(_) |
+            StatusChange::Random(_) |
+            StatusChange::Single(_) |
+            StatusChange::Consume(_) |
+            StatusChange::Crossfade(_) |
+            StatusChange::MixRampDb(_) |
+            StatusChange::MixRampDelay(_) |
+            StatusChange::ReplayGain(_) => true,
+            _ => false,
+        })
+    }
+}
+
+impl IntoIterator for StatusDiff {
+    type Item = StatusChange;
+    type IntoIter = ::std::vec::IntoIter<StatusChange>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.changes.into_iter()
+    }
+}
+// }}}
+
+/// Sample format of audio data
+#[derive(Debug, Copy, Clone, PartialEq, Eq, RustcEncodable)]
+pub enum SampleFormat {
+    /// integer samples with given resolution in bits
+    Bits(u8),
+    /// 32 bit floating point samples
+    Float,
+    /// 1 bit DSD samples
+    Dsd,
+}
+
+impl SampleFormat {
+    /// Size of a sample in bytes (24 bit samples are padded to 32 bits by MPD)
+    ///
+    /// It's `None` for DSD, as DSD samples are packed as bits.
+    pub fn sample_size(self) -> Option<u32> {
+        match self {
+            SampleFormat::Bits(24) => Some(4),
+            SampleFormat::Bits(bits) => Some((bits as u32 + 7) / 8),
+            SampleFormat::Float => Some(4),
+            SampleFormat::Dsd => None,
+        }
+    }
+}
+
+impl fmt::Display for SampleFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SampleFormat::Bits(bits) => bits.fmt(f),
+            SampleFormat::Float => f.write_str("f"),
+            SampleFormat::Dsd => f.write_str("dsd"),
+        }
+    }
+}
+
+impl FromStr for SampleFormat {
+    type Err = ParseError;
+    fn from_str(s: &str) -> Result<SampleFormat, ParseError> {
+        match s {
+            "f" => Ok(SampleFormat::Float),
+            "dsd" => Ok(SampleFormat::Dsd),
+            _ => s.parse().map(SampleFormat::Bits).map_err(ParseError::BadBits),
+        }
+    }
+}
+
+/// Base sample rate of DSD formats, DSD64 is sampled at 64 times this rate
+const DSD_BASE_RATE: u32 = 44100;
+
 /// Audio playback format
+///
+/// It's sent by MPD as `rate:bits:chans` (like `44100:24:2`),
+/// with `f` for floating point samples (like `48000:f:2`),
+/// and as `rate:dsd:chans` or `dsdN:chans` for DSD (like `352800:dsd:2` or `dsd64:2`).
 #[derive(Debug, Copy, Clone, PartialEq, RustcEncodable)]
 pub struct AudioFormat {
-    /// sample rate, kbps
+    /// sample rate, Hz (for DSD it's the rate of bytes, 8 samples each, per channel,
+    /// like MPD reports it, e.g. 352800 for DSD64)
     pub rate: u32,
-    /// sample resolution in bits, can be 0 for floating point resolution
-    pub bits: u8,
+    /// sample format
+    pub sample: SampleFormat,
     /// number of channels
     pub chans: u8,
 }
 
+impl AudioFormat {
+    /// DSD rate multiplier (like 64 for DSD64), if it's a DSD format
+    pub fn dsd_rate(&self) -> Option<u32> {
+        match self.sample {
+            SampleFormat::Dsd => Some((self.rate as u64 * 8 / DSD_BASE_RATE as u64) as u32),
+            _ => None,
+        }
+    }
+
+    /// Audio data rate in bytes per second
+    pub fn bytes_per_second(&self) -> u64 {
+        let frame = match self.sample.sample_size() {
+            Some(size) => self.rate as u64 * size as u64,
+            None => self.rate as u64,
+        };
+        frame * self.chans as u64
+    }
+
+    /// Human readable description, like `44.1 kHz, 24 bit, stereo` or `DSD64, stereo`
+    pub fn describe(&self) -> String {
+        let chans = match self.chans {
+            1 => "mono".to_owned(),
+            2 => "stereo".to_owned(),
+            n => format!("{} channels", n),
+        };
+        match self.sample {
+            SampleFormat::Dsd => format!("DSD{}, {}", self.rate as u64 * 8 / DSD_BASE_RATE as u64, chans),
+            SampleFormat::Float => format!("{} kHz, float, {}", self.rate as f64 / 1000.0, chans),
+            SampleFormat::Bits(bits) => format!("{} kHz, {} bit, {}", self.rate as f64 / 1000.0, bits, chans),
+        }
+    }
+}
+
+/// Formats in MPD notation (like `44100:24:2` or `dsd64:2`)
+impl fmt::Display for AudioFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.dsd_rate() {
+            Some(n) if self.rate as u64 * 8 % DSD_BASE_RATE as u64 == 0 => write!(f, "dsd{}:{}", n, self.chans),
+            _ => write!(f, "{}:{}:{}", self.rate, self.sample, self.chans),
+        }
+    }
+}
+
 impl FromStr for AudioFormat {
     type Err = ParseError;
     fn from_str(s: &str) -> Result<AudioFormat, ParseError> {
-        let mut it = s.split(':');
+        let mask = try!(s.parse::<AudioFormatMask>());
         Ok(AudioFormat {
-            rate: try!(it.next()
-                .ok_or(ParseError::NoRate)
-                .and_then(|v| v.parse().map_err(ParseError::BadRate))),
-            bits: try!(it.next()
-                .ok_or(ParseError::NoBits)
-                .and_then(|v| if &*v == "f" {
-                    Ok(0)
-                } else {
-                    v.parse().map_err(ParseError::BadBits)
-                })),
-            chans: try!(it.next()
-                .ok_or(ParseError::NoChans)
-                .and_then(|v| v.parse().map_err(ParseError::BadChans))),
+            rate: try!(mask.rate.ok_or(ParseError::NoRate)),
+            sample: try!(mask.sample.ok_or(ParseError::NoBits)),
+            chans: try!(mask.chans.ok_or(ParseError::NoChans)),
+        })
+    }
+}
+
+/// Audio format mask, where any part can be a `*` wildcard
+///
+/// Masks are used in output `allowed_formats` and `format` settings,
+/// like `*:24:*` (any 24 bit format), `*:dsd:*` (any DSD format) or `48000:f:2`.
+/// Output format options after `=` (like `dsd64:2=dop`) are ignored.
+#[derive(Debug, Copy, Clone, PartialEq, Default, RustcEncodable)]
+pub struct AudioFormatMask {
+    /// sample rate, Hz (`None` matches any rate)
+    pub rate: Option<u32>,
+    /// sample format (`None` matches any format)
+    pub sample: Option<SampleFormat>,
+    /// number of channels (`None` matches any number)
+    pub chans: Option<u8>,
+}
+
+impl AudioFormatMask {
+    /// Check if audio format matches the mask
+    pub fn matches(&self, format: &AudioFormat) -> bool {
+        self.rate.map_or(true, |r| r == format.rate) && self.sample.map_or(true, |s| s == format.sample) &&
+        self.chans.map_or(true, |c| c == format.chans)
+    }
+}
+
+impl From<AudioFormat> for AudioFormatMask {
+    fn from(format: AudioFormat) -> AudioFormatMask {
+        AudioFormatMask {
+            rate: Some(format.rate),
+            sample: Some(format.sample),
+            chans: Some(format.chans),
+        }
+    }
+}
+
+impl fmt::Display for AudioFormatMask {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let (Some(rate), Some(sample), Some(chans)) = (self.rate, self.sample, self.chans) {
+            return AudioFormat {
+                    rate: rate,
+                    sample: sample,
+                    chans: chans,
+                }
+                .fmt(f);
+        }
+        match self.rate {
+            Some(rate) => rate.fmt(f)?,
+            None => f.write_str("*")?,
+        }
+        f.write_str(":")?;
+        match self.sample {
+            Some(sample) => sample.fmt(f)?,
+            None => f.write_str("*")?,
+        }
+        f.write_str(":")?;
+        match self.chans {
+            Some(chans) => chans.fmt(f),
+            None => f.write_str("*"),
+        }
+    }
+}
+
+impl FromStr for AudioFormatMask {
+    type Err = ParseError;
+    fn from_str(s: &str) -> Result<AudioFormatMask, ParseError> {
+        fn part<T, F>(value: Option<&str>, missing: ParseError, parse: F) -> Result<Option<T>, ParseError>
+            where F: FnOnce(&str) -> Result<T, ParseError>
+        {
+            match value {
+                None => Err(missing),
+                Some("*") => Ok(None),
+                Some(v) => parse(v).map(Some),
+            }
+        }
+
+        let s = s.split('=').next().unwrap_or(s);
+        let mut it = s.split(':');
+        let first = it.next();
+
+        if let Some(dsd) = first.filter(|v| v.starts_with("dsd")) {
+            // `dsdN:chans` notation
+            let rate: u32 = try!(dsd[3..].parse().map_err(ParseError::BadRate));
+            // byte rate has to fit too, report it like an overflowing number
+            let rate = try!(rate.checked_mul(DSD_BASE_RATE)
+                .ok_or_else(|| ParseError::BadRate((u32::max_value() as u64 + 1).to_string().parse::<u32>().unwrap_err())));
+            return Ok(AudioFormatMask {
+                rate: Some(rate / 8),
+                sample: Some(SampleFormat::Dsd),
+                chans: try!(part(it.next(), ParseError::NoChans, |v| v.parse().map_err(ParseError::BadChans))),
+            });
+        }
+
+        Ok(AudioFormatMask {
+            rate: try!(part(first.filter(|v| !v.is_empty()),
+                            ParseError::NoRate,
+                            |v| v.parse().map_err(ParseError::BadRate))),
+            sample: try!(part(it.next(), ParseError::NoBits, str::parse)),
+            chans: try!(part(it.next(), ParseError::NoChans, |v| v.parse().map_err(ParseError::BadChans))),
         })
     }
 }
@@ -320,3 +574,122 @@ impl fmt::Display for ReplayGain {
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use raw::RawResponse;
+    use song::Id;
+
+    #[test]
+    fn status_from_response() {
+        let pairs = [("volume", "80"), ("repeat", "1"), ("playlist", "12"), ("state", "play"), ("songid", "7"), ("song", "3"),
+                     ("time", "10:200"), ("elapsed", "10.250"), ("xfade", "5"), ("audio", "44100:16:2")];
+        let status: Status = RawResponse(pairs.iter().map(|&(k, v)| (k.to_owned(), v.to_owned())).collect()).decode().unwrap();
+        assert_eq!(status.volume, 80);
+        assert!(status.repeat && !status.random);
+        assert_eq!(status.queue_version, 12);
+        assert_eq!(status.state, State::Play);
+        assert_eq!(status.song, Some(QueuePlace { id: Id(7), pos: 3, prio: 0 }));
+        assert_eq!(status.nextsong, None);
+        assert_eq!(status.time, Some((Duration::seconds(10), Duration::seconds(200))));
+        assert_eq!(status.elapsed, Some(Duration::milliseconds(10250)));
+        assert_eq!(status.crossfade, Some(Duration::seconds(5)));
+        assert_eq!(status.audio.map(|a| a.rate), Some(44100));
+    }
+
+    #[test]
+    fn status_diff() {
+        let old = Status {
+            volume: 50,
+            state: State::Play,
+            updating_db: Some(3),
+            error: Some("failed to open".to_owned()),
+            elapsed: Some(Duration::seconds(1)),
+            ..Status::default()
+        };
+        let new = Status {
+            volume: 60,
+            state: State::Play,
+            random: true,
+            queue_version: 2,
+            updating_db: Some(4),
+            elapsed: Some(Duration::seconds(2)),
+            ..Status::default()
+        };
+        assert!(old.diff(&old).is_empty());
+
+        let diff = new.diff(&old);
+        assert_eq!(diff.changes,
+                   vec![StatusChange::Queue { version: 2, len: 0 },
+                        StatusChange::Volume(60),
+                        StatusChange::Random(true),
+                        StatusChange::ErrorCleared,
+                        StatusChange::UpdateFinished(3),
+                        StatusChange::UpdateStarted(4)]);
+        assert!(diff.options());
+        assert_eq!(old.diff(&new).changes[3], StatusChange::Error("failed to open".to_owned()));
+
+        let mixramp = Status { mixrampdb: -17.0, mixrampdelay: Some(Duration::seconds(2)), ..old.clone() };
+        let diff = mixramp.diff(&old);
+        assert_eq!(diff.changes,
+                   vec![StatusChange::MixRampDb(-17.0), StatusChange::MixRampDelay(Some(Duration::seconds(2)))]);
+        assert!(diff.options());
+    }
+
+    #[test]
+    fn audio_formats() {
+        let pcm: AudioFormat = "96000:24:2".parse().unwrap();
+        assert_eq!(pcm.sample, SampleFormat::Bits(24));
+        assert_eq!(pcm.bytes_per_second(), 96000 * 4 * 2);
+        assert_eq!(pcm.describe(), "96 kHz, 24 bit, stereo");
+
+        let float: AudioFormat = "44100:f:1".parse().unwrap();
+        assert_eq!(float.sample, SampleFormat::Float);
+        assert_eq!(float.describe(), "44.1 kHz, float, mono");
+        assert_eq!(float.to_string(), "44100:f:1");
+
+        let dsd: AudioFormat = "dsd64:2".parse().unwrap();
+        assert_eq!((dsd.rate, dsd.sample, dsd.dsd_rate()), (352800, SampleFormat::Dsd, Some(64)));
+        assert_eq!(dsd.bytes_per_second(), 2822400 / 8 * 2);
+        assert_eq!(dsd.to_string(), "dsd64:2");
+        assert_eq!(dsd.describe(), "DSD64, stereo");
+
+        // as reported by MPD in `audio` status field
+        let dsd: AudioFormat = "352800:dsd:2".parse().unwrap();
+        assert_eq!(dsd, "dsd64:2".parse().unwrap());
+        assert_eq!(dsd.to_string(), "dsd64:2");
+        assert_eq!(dsd.to_string().parse::<AudioFormat>().unwrap(), dsd);
+        let dsd: AudioFormat = "705600:dsd:2".parse().unwrap();
+        assert_eq!((dsd.dsd_rate(), dsd.to_string()), (Some(128), "dsd128:2".to_owned()));
+
+        // rates too large for `u32` arithmetic
+        match "dsd99999:2".parse::<AudioFormat>() {
+            Err(ParseError::BadRate(_)) => (),
+            other => panic!("{:?}", other),
+        }
+        let dsd: AudioFormat = "4294967295:dsd:2".parse().unwrap();
+        assert_eq!(dsd.dsd_rate(), Some(779132));
+        assert_eq!(dsd.to_string(), "4294967295:dsd:2");
+        assert_eq!(dsd.describe(), "DSD779132, stereo");
+
+        assert!("*:24:*".parse::<AudioFormat>().is_err());
+        assert!("44100:x:2".parse::<AudioFormat>().is_err());
+    }
+
+    #[test]
+    fn audio_format_masks() {
+        let any24: AudioFormatMask = "*:24:*".parse().unwrap();
+        assert_eq!(any24.to_string(), "*:24:*");
+        assert!(any24.matches(&"96000:24:2".parse().unwrap()));
+        assert!(!any24.matches(&"96000:16:2".parse().unwrap()));
+
+        let any_dsd: AudioFormatMask = "*:dsd:*".parse().unwrap();
+        assert!(any_dsd.matches(&"dsd128:2".parse().unwrap()));
+        assert!(!any_dsd.matches(&"44100:16:2".parse().unwrap()));
+
+        let dop: AudioFormatMask = "dsd64:2=dop".parse().unwrap();
+        assert_eq!(dop.to_string(), "dsd64:2");
+        assert!(dop.matches(&"dsd64:2".parse().unwrap()));
+    }
+}
@@ -0,0 +1,96 @@
+//! The module defines database update jobs
+//!
+//! `Client::update()` and `Client::rescan()` start a job, and return `UpdateJob`
+//! with its ID. `UpdateJob::wait()` waits for the job to finish and returns DB
+//! statistics, and `UpdateJob::wait_delta()` reports how they changed since
+//! a snapshot taken before the job (DB statistics are only fetched by callers,
+//! which are interested in them).
+//!
+//! ```rust,no_run
+//! # extern crate mpd;
+//! # fn main() {
+//! let mut conn = mpd::Client::connect("127.0.0.1:6600").unwrap();
+//! let before = conn.stats().unwrap();
+//! let job = conn.update(Some("Music/New")).unwrap();
+//! let delta = job.wait_delta(&mut conn, &before).unwrap();
+//! println!("job #{}: {:+} songs, {:+} albums", job.id, delta.songs, delta.albums);
+//! # }
+//! ```
+
+use client::Client;
+use error::Result;
+use idle::{Idle, Subsystem};
+use stats::{Stats, StatsDelta};
+use std::io::{Read, Write};
+
+/// MPD wraps job IDs around after this one
+const MAX_JOB_ID: u32 = 1 << 20;
+
+/// Database update job
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UpdateJob {
+    /// job ID
+    pub id: u32,
+}
+
+impl UpdateJob {
+    /// Check if the job is still running, or waits for other jobs to finish
+    pub fn is_running<S: Read + Write>(&self, client: &mut Client<S>) -> Result<bool> {
+        client.status().map(|status| self.is_running_with(status.updating_db))
+    }
+
+    /// Check if the job is still running, given ID of the running job
+    ///
+    /// Jobs run one after another in the order of IDs, so the job is done
+    /// if a newer one runs (with IDs wrapped around).
+    fn is_running_with(&self, current: Option<u32>) -> bool {
+        current.map_or(false, |current| !is_newer(current, self.id))
+    }
+
+    /// Wait for the job to finish, and return DB statistics after it
+    ///
+    /// Blocks on `idle` for `update` events, so the client can't be used meanwhile.
+    /// If other jobs were running or queued, their changes are included into the statistics.
+    pub fn wait<S: Read + Write>(&self, client: &mut Client<S>) -> Result<Stats> {
+        while self.is_running(client)? {
+            client.wait(&[Subsystem::Update])?;
+        }
+        client.stats()
+    }
+
+    /// Wait for the job to finish, and return the change of DB statistics since `before`
+    ///
+    /// `before` should be taken before the job is started. If other jobs were running
+    /// or queued, their changes are included into the delta.
+    pub fn wait_delta<S: Read + Write>(&self, client: &mut Client<S>, before: &Stats) -> Result<StatsDelta> {
+        self.wait(client).map(|stats| stats.delta(before))
+    }
+}
+
+/// Check if job `id` was started after job `other`
+fn is_newer(id: u32, other: u32) -> bool {
+    let distance = id.wrapping_sub(other) % MAX_JOB_ID;
+    distance != 0 && distance < MAX_JOB_ID / 2
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn job_running() {
+        let job = UpdateJob { id: 5 };
+        assert!(!job.is_running_with(None));
+        assert!(job.is_running_with(Some(5)));
+        assert!(job.is_running_with(Some(4)));
+        assert!(!job.is_running_with(Some(6)));
+
+        // IDs wrap around after MAX_JOB_ID
+        let job = UpdateJob { id: MAX_JOB_ID };
+        assert!(job.is_running_with(Some(MAX_JOB_ID - 1)));
+        assert!(!job.is_running_with(Some(1)));
+        let job = UpdateJob { id: 1 };
+        assert!(job.is_running_with(Some(MAX_JOB_ID)));
+        assert!(!job.is_running_with(Some(2)));
+    }
+}
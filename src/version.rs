@@ -2,6 +2,7 @@
 
 
 use error::ParseError;
+use std::fmt;
 use std::str::FromStr;
 
 // Version {{{
@@ -22,4 +23,10 @@ impl FromStr for Version {
         }
     }
 }
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.0, self.1, self.2)
+    }
+}
 // }}}
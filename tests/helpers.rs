@@ -1,9 +1,15 @@
+// not every test uses every helper
+#![allow(dead_code)]
+
 extern crate mpd;
 
 use std::env;
 use std::net::TcpStream;
 
+pub fn addr() -> String {
+    env::var("MPD_SOCK").unwrap_or_else(|_| "127.0.0.1:6600".to_owned())
+}
+
 pub fn connect() -> mpd::Client<TcpStream> {
-    let addr = env::var("MPD_SOCK").unwrap_or_else(|_| "127.0.0.1:6600".to_owned());
-    mpd::Client::connect(&*addr).unwrap()
+    mpd::Client::connect(&*addr()).unwrap()
 }
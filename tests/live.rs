@@ -0,0 +1,27 @@
+extern crate mpd;
+
+mod helpers;
+
+use mpd::live::{Change, LiveClient};
+use std::time::Duration;
+
+#[test]
+fn live_status() {
+    let live = LiveClient::connect(&*helpers::addr(), None).unwrap();
+    let changes = live.subscribe();
+
+    let consume = !live.status().consume;
+    live.run(|client| client.consume(consume)).unwrap();
+    loop {
+        match changes.recv_timeout(Duration::from_secs(5)).unwrap() {
+            Change::Status(status) => {
+                if status.consume == consume {
+                    break;
+                }
+            }
+            _ => (),
+        }
+    }
+    assert_eq!(live.status().consume, consume);
+    live.run(|client| client.consume(!consume)).unwrap();
+}
@@ -13,3 +13,13 @@ fn playlists() {
         println!("{}: {:?}", pl.name, mpd.playlist(&pl.name).unwrap());
     }
 }
+
+#[test]
+fn playlist_files() {
+    let mut mpd = connect();
+    for pl in &mpd.playlists().unwrap() {
+        let files = mpd.pl_files(&pl.name).unwrap();
+        let songs = mpd.playlist(&pl.name).unwrap();
+        assert_eq!(files.len(), songs.len());
+    }
+}
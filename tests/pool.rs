@@ -0,0 +1,31 @@
+extern crate mpd;
+
+mod helpers;
+
+use mpd::pool::Pool;
+use std::time::Duration;
+
+#[test]
+fn checkout() {
+    let pool = Pool::new(&helpers::addr(), 2);
+
+    let mut a = pool.get().unwrap();
+    let b = pool.get().unwrap();
+    assert_eq!(pool.size(), 2);
+    assert!(pool.get_timeout(Duration::from_millis(10)).is_err());
+
+    a.run(|client| client.status()).unwrap();
+    drop(a);
+    assert_eq!(pool.available(), 1);
+    pool.get().unwrap().discard();
+    assert_eq!(pool.size(), 1);
+
+    drop(b);
+    assert_eq!(pool.available(), 1);
+}
+
+#[test]
+#[should_panic(expected = "max_size")]
+fn empty_pool() {
+    Pool::new(&helpers::addr(), 0);
+}
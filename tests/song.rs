@@ -23,6 +23,15 @@ fn queue() {
 #[test]
 fn rescan_update() {
     let mut mpd = connect();
-    println!("update: {:?}", mpd.update());
-    println!("rescan: {:?}", mpd.rescan());
+    println!("update: {:?}", mpd.update(None));
+    println!("rescan: {:?}", mpd.rescan(None));
+}
+
+#[test]
+fn update_wait() {
+    let mut mpd = connect();
+    let before = mpd.stats().unwrap();
+    let job = mpd.update(None).unwrap();
+    println!("delta: {:?}", job.wait_delta(&mut mpd, &before).unwrap());
+    assert!(!job.is_running(&mut mpd).unwrap());
 }